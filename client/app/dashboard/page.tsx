@@ -25,7 +25,8 @@ export default function DashboardPage() {
       })
       if (!res.ok) throw new Error("Could not fetch your posts")
 
-      return res.json()
+      const page = await res.json()
+      return page.items
     },
     enabled: !!user?.accessToken // Only fetch if we have a token
   })
@@ -34,10 +35,13 @@ export default function DashboardPage() {
 
   // 2. Delete Mutation
   const deleteMutation = useMutation({
-    mutationFn: async (postId: string) => {
+    mutationFn: async ({ postId, version }: { postId: string, version: number }) => {
       const res = await fetch(`${process.env.NEXT_PUBLIC_API_URL}/posts/${postId}`, {
         method: "DELETE",
-        headers: { Authorization: `Bearer ${user?.accessToken}` }
+        headers: {
+          Authorization: `Bearer ${user?.accessToken}`,
+          "If-Match": `"${version}"`
+        }
       })
       if (!res.ok) throw new Error("Failed to delete")
     },
@@ -53,12 +57,13 @@ export default function DashboardPage() {
 
   // 3. Update Mutation (optional, for edit functionality)
    const updateMutation = useMutation({
-    mutationFn: async ({ postId, data }: { postId: string, data: any }) => {
+    mutationFn: async ({ postId, version, data }: { postId: string, version: number, data: any }) => {
       const res = await fetch(`${process.env.NEXT_PUBLIC_API_URL}/posts/${postId}`, {
         method: "PATCH",
         headers: { 
           "Content-Type": "application/json",
-          Authorization: `Bearer ${user?.accessToken}` 
+          Authorization: `Bearer ${user?.accessToken}`,
+          "If-Match": `"${version}"`
         },
         body: JSON.stringify(data)
       })
@@ -109,7 +114,7 @@ export default function DashboardPage() {
               </Card.Body>
               <Card.Footer borderTopWidth="1px" pt="3">
                 <HStack justify="flex-end" width="full">
-                  <Button variant="ghost" size="sm" gap="2" onClick={() => updateMutation.mutate({ postId: post._id?.$oid, data: { title: post.title, content: post.content } })}>
+                  <Button variant="ghost" size="sm" gap="2" onClick={() => updateMutation.mutate({ postId: post._id?.$oid, version: post.version, data: { title: post.title, content: post.content } })}>
                     <RiFileEditFill /> Edit
                   </Button>
                   <Button 
@@ -119,7 +124,7 @@ export default function DashboardPage() {
                     gap="2"
                     loading={deleteMutation.isPending}
                     onClick={() => {
-                      if(confirm("Are you sure?")) deleteMutation.mutate(post._id?.$oid)
+                      if(confirm("Are you sure?")) deleteMutation.mutate({ postId: post._id?.$oid, version: post.version })
                     }}
                   >
                     <LuTrash2 /> Delete
@@ -44,7 +44,8 @@ const register = async (username: string, email: string, password?: string) => {
 
   if (!res.ok) {
     const errorData = await res.json().catch(() => ({ message: "Registration failed" }));
-    throw new Error(errorData.message || "Registration failed");
+    // 422 responses list the rejected fields (application/problem+json)
+    throw new Error(errorData.errors?.[0]?.message || errorData.detail || "Registration failed");
   }
 
   return { email }; // Return the email so the UI knows what to log in with
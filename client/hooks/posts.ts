@@ -12,10 +12,17 @@ export type Post = {
   // Add other fields as needed
 };
 
+export type Page<T> = {
+  items: T[];
+  next_cursor: string | null;
+  has_more: boolean;
+};
+
 export async function fetchPosts(): Promise<Post[]> {
   const res = await fetch(`${API_BASE}/posts`);
   if (!res.ok) throw new Error("Failed to fetch posts");
-  return res.json();
+  const page: Page<Post> = await res.json();
+  return page.items;
 }
 
 
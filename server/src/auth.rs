@@ -1,91 +1,156 @@
-/**
- * JWT auth: shared claims, secret, and extractor for protected routes.
+/*
+ * JWT auth: the single claims type, the key material, and the extractor for protected routes.
  */
 
 use axum::{
     async_trait,
     extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
-    response::{IntoResponse, Json, Response},
+    http::request::Parts,
 };
+use chrono::Utc;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models::user::UserRole;
+use crate::telemetry::record_user;
+use crate::AppState;
+
+pub const ISSUER: &str = "rust-blog-api";
+pub const AUDIENCE: &str = "rust-blog-client";
+
+/// Only used when APP_ENV=development and no JWT_SECRET is set
+const DEV_SECRET: &str = "dev-secret-change-in-production";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct JwtClaims {
-    pub sub: String,   // user id
-    pub email: String,
-    pub exp: i64,
-    pub iat: i64,
+pub struct Claims {
+    pub sub: String,    // User ID
+    pub role: UserRole,
+    pub sid: String,    // Session ID, checked against the sessions collection
+    pub iss: String,
+    pub aud: String,
+    pub iat: i64,       // Issued at
+    pub exp: i64,       // Expiration
 }
 
-pub fn get_jwt_secret() -> String {
-    std::env::var("JWT_SECRET").unwrap_or_else(|_| "dev-secret-change-in-production".to_string())
+/// Signs and verifies access tokens. Built once at startup and kept in AppState.
+pub struct AuthService {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    validation: Validation,
+    access_token_ttl: chrono::Duration, // Short lived; sessions are extended through refresh tokens
 }
 
-/// Extractor: requires `Authorization: Bearer <token>` and valid JWT.
-#[derive(Debug, Clone)]
-pub struct AuthUser(pub JwtClaims);
+impl AuthService {
+    pub fn new(secret: &[u8], access_token_ttl: chrono::Duration) -> Self {
+        let mut validation = Validation::default();
+        validation.set_issuer(&[ISSUER]);
+        validation.set_audience(&[AUDIENCE]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        Self {
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            validation,
+            access_token_ttl,
+        }
+    }
+
+    /// Config validation already refused a missing secret outside development
+    pub fn from_config(config: &Config) -> Self {
+        let ttl = chrono::Duration::minutes(config.auth.access_token_ttl_minutes);
+        match config.auth.jwt_secret.as_deref() {
+            Some(secret) => Self::new(secret.as_bytes(), ttl),
+            None => {
+                tracing::warn!("JWT_SECRET is not set, using the development secret");
+                Self::new(DEV_SECRET.as_bytes(), ttl)
+            }
+        }
+    }
+
+    pub fn access_token_ttl_secs(&self) -> i64 {
+        self.access_token_ttl.num_seconds()
+    }
+
+    pub fn issue_access_token(
+        &self,
+        user_id: ObjectId,
+        role: UserRole,
+        session_id: ObjectId,
+    ) -> Result<String, AppError> {
+        let now = Utc::now();
+        let claims = Claims {
+            sub: user_id.to_hex(),
+            role,
+            sid: session_id.to_hex(),
+            iss: ISSUER.to_string(),
+            aud: AUDIENCE.to_string(),
+            iat: now.timestamp(),
+            exp: (now + self.access_token_ttl).timestamp(),
+        };
+
+        encode(&Header::default(), &claims, &self.encoding_key).map_err(|e| AppError::internal(e))
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims, AppError> {
+        decode::<Claims>(token, &self.decoding_key, &self.validation)
+            .map(|data| data.claims)
+            .map_err(|_| AppError::InvalidToken)
+    }
+}
 
-#[derive(Debug)]
-pub struct AuthError {
-    pub status: StatusCode,
-    pub message: String,
+/// Extractor: requires `Authorization: Bearer <token>`, a valid JWT and a live session.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: ObjectId,
+    pub role: UserRole,
+    pub session_id: ObjectId,
 }
 
-impl IntoResponse for AuthError {
-    fn into_response(self) -> Response {
-        (self.status, Json(serde_json::json!({ "error": self.message }))).into_response()
+impl AuthUser {
+    pub fn is_admin(&self) -> bool {
+        self.role == UserRole::Admin
+    }
+
+    /// Guard for admin-only handlers
+    pub fn require_admin(&self) -> Result<(), AppError> {
+        if self.is_admin() { Ok(()) } else { Err(AppError::Forbidden) }
+    }
+
+    /// Ownership rule: the owner or an admin may modify a resource
+    pub fn can_modify(&self, owner_id: &ObjectId) -> bool {
+        &self.user_id == owner_id || self.is_admin()
     }
 }
 
 #[async_trait]
-impl<S> FromRequestParts<S> for AuthUser
-where
-    S: Send + Sync,
-{
-    type Rejection = AuthError;
-
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let auth_header = parts
+impl FromRequestParts<Arc<AppState>> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        // 1. Get the bearer token
+        let token = parts
             .headers
             .get("Authorization")
-            .and_then(|v| v.to_str().ok())
-            .ok_or_else(|| AuthError {
-                status: StatusCode::UNAUTHORIZED,
-                message: "Missing Authorization header".to_string(),
-            })?;
-
-        let token = auth_header
-            .strip_prefix("Bearer ")
-            .ok_or_else(|| AuthError {
-                status: StatusCode::UNAUTHORIZED,
-                message: "Expected Bearer token".to_string(),
-            })?;
-
-        let secret = get_jwt_secret();
-        let mut validation = Validation::default();
-        validation.validate_exp = true;
-
-        let token_data = decode::<JwtClaims>(
-            token,
-            &DecodingKey::from_secret(secret.as_bytes()),
-            &validation,
-        )
-        .map_err(|_| AuthError {
-            status: StatusCode::UNAUTHORIZED,
-            message: "Invalid or expired token".to_string(),
-        })?;
-
-        Ok(AuthUser(token_data.claims))
-    }
-}
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or(AppError::InvalidToken)?;
+
+        // 2. Decode and validate signature, expiry, issuer and audience
+        let claims = state.auth.verify(token)?;
 
-/// Encode JWT (used by login). Re-export for user_routes.
-pub fn encode_jwt(claims: &JwtClaims) -> Result<String, jsonwebtoken::errors::Error> {
-    encode(
-        &Header::default(),
-        claims,
-        &EncodingKey::from_secret(get_jwt_secret().as_bytes()),
-    )
+        let user_id = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+        let session_id = ObjectId::parse_str(&claims.sid).map_err(|_| AppError::InvalidToken)?;
+
+        // 3. The session must still be live (not logged out, revoked or expired)
+        if !state.users.session_is_live(session_id).await? {
+            return Err(AppError::InvalidToken);
+        }
+
+        record_user(user_id);
+        Ok(AuthUser { user_id, role: claims.role, session_id })
+    }
 }
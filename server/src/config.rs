@@ -0,0 +1,594 @@
+/*
+ * Server configuration, loaded once at startup and kept in AppState.
+ * Built-in defaults, overridden by an optional TOML file (CONFIG_FILE, or ./config.toml
+ * when present), overridden by environment variables. Everything is validated up front
+ * so a bad setting stops the server with a readable message instead of failing later.
+ */
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use axum::http::HeaderValue;
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+use crate::imaging::VariantConfig;
+use crate::rate_limit::{LockoutPolicy, Rule};
+
+/// Read when CONFIG_FILE is not set, if it exists
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub environment: Environment,
+    pub server: ServerConfig,
+    pub log: LogConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub media: MediaConfig,
+    pub images: VariantConfig,
+    pub site: SiteConfig,
+    pub trash: TrashConfig,
+    pub rate_limit: RateLimitConfig,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    #[serde(alias = "dev")]
+    Development,
+    #[default]
+    #[serde(alias = "prod")]
+    Production,
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "development" | "dev" => Ok(Self::Development),
+            "production" | "prod" => Ok(Self::Production),
+            _ => Err("expected development or production".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_addr: SocketAddr,
+    pub cors_origins: Vec<String>, // "*" allows any origin
+    pub shutdown_timeout_secs: u64,  // How long in-flight requests may drain after SIGTERM/SIGINT
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: SocketAddr::from(([0, 0, 0, 0], 4000)),
+            cors_origins: vec!["*".to_string()],
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_timeout_secs)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    pub level: String, // An EnvFilter directive; RUST_LOG takes precedence
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { format: LogFormat::Json, level: "info".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Json, // One object per line, for log shippers
+    Text, // Human-readable, for local work
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "text" => Ok(Self::Text),
+            _ => Err("expected json or text".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub uri: String,
+    pub name: String,
+    pub min_pool_size: Option<u32>,
+    pub max_pool_size: Option<u32>, // The driver's default (10) when unset
+    pub connect_attempts: u32,      // Tries at startup before giving up, with backoff in between
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            uri: "mongodb://localhost:27017".to_string(),
+            name: "rust_blog_db".to_string(),
+            min_pool_size: None,
+            max_pool_size: None,
+            connect_attempts: 10,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_secret: Option<String>, // Required outside development
+    pub access_token_ttl_minutes: i64,
+    pub refresh_token_ttl_days: i64,
+    pub bcrypt_cost: u32,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            jwt_secret: None,
+            access_token_ttl_minutes: 15,
+            refresh_token_ttl_days: 30,
+            bcrypt_cost: bcrypt::DEFAULT_COST,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Local,
+    S3,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "local" => Ok(Self::Local),
+            "s3" => Ok(Self::S3),
+            _ => Err("expected local or s3".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaConfig {
+    pub storage: StorageBackend,
+    pub dir: PathBuf, // Local storage root
+    pub max_upload_bytes: usize,
+    pub s3: S3Config,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            storage: StorageBackend::Local,
+            dir: PathBuf::from("./uploads"),
+            max_upload_bytes: 10 * 1024 * 1024,
+            s3: S3Config::default(),
+        }
+    }
+}
+
+/// With an endpoint set (e.g. http://localhost:9000 for MinIO) path-style URLs are used
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Config {
+    pub bucket: Option<String>,
+    pub region: String,
+    pub endpoint: Option<String>,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            bucket: None,
+            region: "us-east-1".to_string(),
+            endpoint: None,
+            access_key: None,
+            secret_key: None,
+        }
+    }
+}
+
+/// Public identity of the blog, used in feeds
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    pub title: String,
+    pub url: String,     // Where readers open posts (the web client)
+    pub api_url: String, // Where this server is reachable
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            title: "Rust Blog".to_string(),
+            url: "http://localhost:3000".to_string(),
+            api_url: "http://localhost:4000".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrashConfig {
+    pub retention_days: i64, // How long trashed posts and users can still be restored
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+impl TrashConfig {
+    pub fn retention(&self) -> chrono::Duration {
+        chrono::Duration::days(self.retention_days)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub store: RateLimitBackend,
+    pub trust_forwarded_for: bool, // Only behind a proxy that sets X-Forwarded-For
+    pub auth: Rule,                // Per client IP: register, login, token refresh
+    pub login: Rule,               // Per account (email), on top of the IP limit
+    pub write: Rule,               // Per user, or per IP when anonymous: every other POST/PUT/PATCH/DELETE
+    pub lockout: LockoutPolicy,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            store: RateLimitBackend::Memory,
+            trust_forwarded_for: false,
+            auth: Rule { burst: 20, per_minute: 20 },
+            login: Rule { burst: 10, per_minute: 10 },
+            write: Rule { burst: 30, per_minute: 30 },
+            lockout: LockoutPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitBackend {
+    #[default]
+    Memory,  // Per instance
+    Mongodb, // Shared by every instance
+}
+
+impl FromStr for RateLimitBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "mongodb" => Ok(Self::Mongodb),
+            _ => Err("expected memory or mongodb".to_string()),
+        }
+    }
+}
+
+/// Background jobs that can be switched off, e.g. on all but one instance
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    pub scheduled_publishing: bool,
+    pub trash_purge: bool,
+    pub image_processing: bool,
+    pub metrics: bool, // Serve GET /metrics
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self { scheduled_publishing: true, trash_purge: true, image_processing: true, metrics: true }
+    }
+}
+
+/// Every problem found while loading, reported together
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match config_file()? {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+
+        let mut problems = Vec::new();
+        config.apply_env(&mut Env { problems: &mut problems });
+        problems.extend(config.validate());
+        if !problems.is_empty() {
+            return Err(ConfigError(problems));
+        }
+
+        config.images.widths.sort_unstable();
+        config.images.widths.dedup();
+        for url in [&mut config.site.url, &mut config.site.api_url] {
+            *url = url.trim_end_matches('/').to_string();
+        }
+        Ok(config)
+    }
+
+    pub fn is_development(&self) -> bool {
+        self.environment == Environment::Development
+    }
+
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let fail = |e: String| ConfigError(vec![format!("{}: {}", path.display(), e)]);
+        let text = std::fs::read_to_string(path).map_err(|e| fail(e.to_string()))?;
+        toml::from_str(&text).map_err(|e| fail(e.message().to_string()))
+    }
+
+    /// The environment variables each setting can be overridden with
+    fn apply_env(&mut self, env: &mut Env) {
+        env.parse("APP_ENV", &mut self.environment);
+
+        env.parse("BIND_ADDR", &mut self.server.bind_addr);
+        env.list("CORS_ORIGINS", &mut self.server.cors_origins);
+        env.parse("SHUTDOWN_TIMEOUT_SECS", &mut self.server.shutdown_timeout_secs);
+
+        env.parse("LOG_FORMAT", &mut self.log.format);
+        env.string("LOG_LEVEL", &mut self.log.level);
+
+        env.string("MONGODB_URI", &mut self.database.uri);
+        env.string("MONGODB_DB_NAME", &mut self.database.name);
+        env.optional_parse("MONGODB_MIN_POOL_SIZE", &mut self.database.min_pool_size);
+        env.optional_parse("MONGODB_MAX_POOL_SIZE", &mut self.database.max_pool_size);
+        env.parse("MONGODB_CONNECT_ATTEMPTS", &mut self.database.connect_attempts);
+
+        env.optional("JWT_SECRET", &mut self.auth.jwt_secret);
+        env.parse("ACCESS_TOKEN_TTL_MINUTES", &mut self.auth.access_token_ttl_minutes);
+        env.parse("REFRESH_TOKEN_TTL_DAYS", &mut self.auth.refresh_token_ttl_days);
+        env.parse("BCRYPT_COST", &mut self.auth.bcrypt_cost);
+
+        env.parse("MEDIA_STORAGE", &mut self.media.storage);
+        env.parse("MEDIA_DIR", &mut self.media.dir);
+        env.parse("MEDIA_MAX_BYTES", &mut self.media.max_upload_bytes);
+        env.optional("S3_BUCKET", &mut self.media.s3.bucket);
+        env.string("S3_REGION", &mut self.media.s3.region);
+        env.optional("S3_ENDPOINT", &mut self.media.s3.endpoint);
+        env.optional("S3_ACCESS_KEY", &mut self.media.s3.access_key);
+        env.optional("S3_SECRET_KEY", &mut self.media.s3.secret_key);
+
+        env.parse("IMAGE_THUMBNAIL_SIZE", &mut self.images.thumbnail_size);
+        env.parse_list("IMAGE_VARIANT_WIDTHS", &mut self.images.widths);
+        env.parse("IMAGE_JPEG_QUALITY", &mut self.images.jpeg_quality);
+
+        env.string("SITE_TITLE", &mut self.site.title);
+        env.string("SITE_URL", &mut self.site.url);
+        env.string("API_URL", &mut self.site.api_url);
+
+        env.parse("TRASH_RETENTION_DAYS", &mut self.trash.retention_days);
+
+        env.parse("RATE_LIMIT_ENABLED", &mut self.rate_limit.enabled);
+        env.parse("RATE_LIMIT_STORE", &mut self.rate_limit.store);
+        env.parse("RATE_LIMIT_TRUST_FORWARDED_FOR", &mut self.rate_limit.trust_forwarded_for);
+        env.parse("RATE_LIMIT_AUTH_PER_MINUTE", &mut self.rate_limit.auth.per_minute);
+        env.parse("RATE_LIMIT_LOGIN_PER_MINUTE", &mut self.rate_limit.login.per_minute);
+        env.parse("RATE_LIMIT_WRITE_PER_MINUTE", &mut self.rate_limit.write.per_minute);
+        env.parse("LOGIN_LOCKOUT_THRESHOLD", &mut self.rate_limit.lockout.threshold);
+
+        env.parse("FEATURE_SCHEDULED_PUBLISHING", &mut self.features.scheduled_publishing);
+        env.parse("FEATURE_TRASH_PURGE", &mut self.features.trash_purge);
+        env.parse("FEATURE_IMAGE_PROCESSING", &mut self.features.image_processing);
+        env.parse("FEATURE_METRICS", &mut self.features.metrics);
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        for origin in &self.server.cors_origins {
+            let valid = origin == "*"
+                || ((origin.starts_with("http://") || origin.starts_with("https://"))
+                    && HeaderValue::from_str(origin).is_ok());
+            check(valid, &format!(
+                "server.cors_origins (CORS_ORIGINS): {:?} is not \"*\" or an http(s) origin", origin
+            ));
+        }
+
+        check(
+            EnvFilter::try_new(&self.log.level).is_ok(),
+            &format!("log.level (LOG_LEVEL): {:?} is not a valid filter, e.g. \"info\" or \"server=debug\"", self.log.level),
+        );
+
+        check(!self.database.uri.trim().is_empty(), "database.uri (MONGODB_URI) must not be empty");
+        check(!self.database.name.trim().is_empty(), "database.name (MONGODB_DB_NAME) must not be empty");
+        check(
+            self.database.max_pool_size != Some(0),
+            "database.max_pool_size (MONGODB_MAX_POOL_SIZE) must be at least 1",
+        );
+        if let (Some(min), Some(max)) = (self.database.min_pool_size, self.database.max_pool_size) {
+            check(min <= max, "database.min_pool_size must not exceed database.max_pool_size");
+        }
+        check(
+            self.database.connect_attempts > 0,
+            "database.connect_attempts (MONGODB_CONNECT_ATTEMPTS) must be at least 1",
+        );
+
+        check(
+            self.is_development() || self.auth.jwt_secret.as_deref().is_some_and(|s| !s.trim().is_empty()),
+            "auth.jwt_secret (JWT_SECRET) must be set (or run with APP_ENV=development)",
+        );
+        check(
+            self.auth.access_token_ttl_minutes > 0,
+            "auth.access_token_ttl_minutes (ACCESS_TOKEN_TTL_MINUTES) must be positive",
+        );
+        check(
+            self.auth.refresh_token_ttl_days > 0,
+            "auth.refresh_token_ttl_days (REFRESH_TOKEN_TTL_DAYS) must be positive",
+        );
+        check(
+            (4..=31).contains(&self.auth.bcrypt_cost),
+            "auth.bcrypt_cost (BCRYPT_COST) must be between 4 and 31",
+        );
+
+        check(self.media.max_upload_bytes > 0, "media.max_upload_bytes (MEDIA_MAX_BYTES) must be positive");
+        check(
+            self.media.storage != StorageBackend::S3 || self.media.s3.bucket.is_some(),
+            "media.s3.bucket (S3_BUCKET) must be set when media.storage is s3",
+        );
+
+        check(self.images.thumbnail_size > 0, "images.thumbnail_size (IMAGE_THUMBNAIL_SIZE) must be positive");
+        check(
+            !self.images.widths.is_empty() && !self.images.widths.contains(&0),
+            "images.widths (IMAGE_VARIANT_WIDTHS) must list positive widths",
+        );
+        check(
+            (1..=100).contains(&self.images.jpeg_quality),
+            "images.jpeg_quality (IMAGE_JPEG_QUALITY) must be between 1 and 100",
+        );
+
+        for (url, name) in [(&self.site.url, "site.url (SITE_URL)"), (&self.site.api_url, "site.api_url (API_URL)")] {
+            check(url.starts_with("http://") || url.starts_with("https://"), &format!("{} must be an http(s) URL", name));
+        }
+
+        check(self.trash.retention_days >= 0, "trash.retention_days (TRASH_RETENTION_DAYS) must not be negative");
+
+        let limits = &self.rate_limit;
+        for (rule, name) in [(limits.auth, "auth"), (limits.login, "login"), (limits.write, "write")] {
+            check(
+                rule.burst > 0 && rule.per_minute > 0,
+                &format!("rate_limit.{} needs a positive burst and per_minute", name),
+            );
+        }
+        check(limits.lockout.threshold > 0, "rate_limit.lockout.threshold (LOGIN_LOCKOUT_THRESHOLD) must be at least 1");
+        check(
+            limits.lockout.base_secs <= limits.lockout.max_secs
+                && limits.lockout.max_secs <= limits.lockout.reset_after_secs,
+            "rate_limit.lockout needs base_secs <= max_secs <= reset_after_secs",
+        );
+
+        problems
+    }
+}
+
+/// CONFIG_FILE must exist when set; the default file is optional
+fn config_file() -> Result<Option<PathBuf>, ConfigError> {
+    match std::env::var("CONFIG_FILE") {
+        Ok(path) if !path.trim().is_empty() => {
+            let path = PathBuf::from(path);
+            if !path.is_file() {
+                return Err(ConfigError(vec![format!("CONFIG_FILE {} does not exist", path.display())]));
+            }
+            Ok(Some(path))
+        }
+        _ => Ok(Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|p| p.is_file())),
+    }
+}
+
+/// Environment overrides; unset or empty variables leave the setting alone
+struct Env<'a> {
+    problems: &'a mut Vec<String>,
+}
+
+impl Env<'_> {
+    fn get(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+    }
+
+    fn string(&mut self, name: &str, target: &mut String) {
+        if let Some(value) = self.get(name) {
+            *target = value;
+        }
+    }
+
+    fn optional(&mut self, name: &str, target: &mut Option<String>) {
+        if let Some(value) = self.get(name) {
+            *target = Some(value);
+        }
+    }
+
+    fn list(&mut self, name: &str, target: &mut Vec<String>) {
+        if let Some(value) = self.get(name) {
+            *target = value.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect();
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, target: &mut T)
+    where
+        T::Err: fmt::Display,
+    {
+        if let Some(value) = self.get(name) {
+            match value.parse() {
+                Ok(parsed) => *target = parsed,
+                Err(e) => self.problems.push(format!("{}={:?}: {}", name, value, e)),
+            }
+        }
+    }
+
+    fn optional_parse<T: FromStr>(&mut self, name: &str, target: &mut Option<T>)
+    where
+        T::Err: fmt::Display,
+    {
+        if let Some(value) = self.get(name) {
+            match value.parse() {
+                Ok(parsed) => *target = Some(parsed),
+                Err(e) => self.problems.push(format!("{}={:?}: {}", name, value, e)),
+            }
+        }
+    }
+
+    fn parse_list<T: FromStr>(&mut self, name: &str, target: &mut Vec<T>)
+    where
+        T::Err: fmt::Display,
+    {
+        if let Some(value) = self.get(name) {
+            let parsed: Result<Vec<T>, _> = value.split(',').map(|v| v.trim().parse()).collect();
+            match parsed {
+                Ok(list) => *target = list,
+                Err(e) => self.problems.push(format!("{}={:?}: {}", name, value, e)),
+            }
+        }
+    }
+}
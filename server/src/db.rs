@@ -1,39 +1,99 @@
-use mongodb::{options::{ClientOptions, IndexOptions}, Client, Database, IndexModel};
-use mongodb::bson::doc;
-use std::env;
+use chrono::{DateTime, Utc};
+use mongodb::{error::ErrorKind, options::{ClientOptions, IndexOptions}, Client, Database, IndexModel};
+use mongodb::bson::{doc, Document};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::watch;
+use crate::config::DatabaseConfig;
+use crate::models::comment::Comment;
+use crate::models::media::Media;
+use crate::models::post::{Post, PostStatus};
+use crate::models::revision::PostRevision;
+use crate::models::session::Session;
 use crate::models::user::User;
+use crate::markdown;
+use crate::metrics;
+use crate::repository::mongo::slug_filter;
+use crate::slug::post_slug_candidates;
+use futures::stream::TryStreamExt;
 
-pub struct AppState {
-    pub db: Database,
+/// First wait between connection attempts; doubled each time up to MAX_CONNECT_BACKOFF
+const INITIAL_CONNECT_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Connect and ping, retrying with exponential backoff while MongoDB is unreachable.
+/// A malformed URI or rejected credentials fail right away.
+pub async fn connect_db(config: &DatabaseConfig) -> mongodb::error::Result<Database> {
+    let mut backoff = INITIAL_CONNECT_BACKOFF;
+    let mut attempt = 1;
+
+    loop {
+        match try_connect(config).await {
+            Ok(db) => {
+                tracing::info!(database = %config.name, "connected to MongoDB");
+                return Ok(db);
+            }
+            Err(e) if attempt < config.connect_attempts && is_retryable(&e) => {
+                tracing::warn!(
+                    attempt, max_attempts = config.connect_attempts, retry_in = ?backoff, error = %e,
+                    "MongoDB not reachable, retrying"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_CONNECT_BACKOFF);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
-pub async fn connect_db() -> Database {
-    // 1. Load connection string from .env or default to localhost
-    let mongodb_uri = env::var("MONGODB_URI")
-        .unwrap_or_else(|_| "mongodb://localhost:27017".into());
-    let db_name = env::var("MONGODB_DB_NAME")
-        .unwrap_or_else(|_| "rust_blog_db".into());
+async fn try_connect(config: &DatabaseConfig) -> mongodb::error::Result<Database> {
+    let mut client_options = ClientOptions::parse(&config.uri).await?;
 
-    // 2. Parse options and connect
-    let mut client_options = ClientOptions::parse(&mongodb_uri)
-        .await
-        .expect("Failed to parse MongoDB URI");
-    
     client_options.app_name = Some("RustBlogAPI".to_string());
+    // Command timings and pool connections for GET /metrics
+    client_options.command_event_handler = Some(metrics::command_event_handler());
+    client_options.cmap_event_handler = Some(metrics::cmap_event_handler());
+    // Only explicit settings override pool options given in the URI
+    if config.min_pool_size.is_some() {
+        client_options.min_pool_size = config.min_pool_size;
+    }
+    if config.max_pool_size.is_some() {
+        client_options.max_pool_size = config.max_pool_size;
+    }
 
-    let client = Client::with_options(client_options)
-        .expect("Failed to initialize MongoDB client");
+    let db = Client::with_options(client_options)?.database(&config.name);
 
-    let db = client.database(&db_name);
+    // The client connects lazily; make sure a server actually answers
+    db.run_command(doc! { "ping": 1 }).await?;
+    Ok(db)
+}
 
-    // 3. Initialize Schema/Indexes
-    init_db(&db).await;
+fn is_retryable(e: &mongodb::error::Error) -> bool {
+    !matches!(*e.kind, ErrorKind::InvalidArgument { .. } | ErrorKind::Authentication { .. })
+}
 
-    println!("✅ Connected to Database: {}", db_name);
-    db
+/// Whether `init_db` has run, and what it could not do; reported by GET /readyz
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum InitStatus {
+    Pending,
+    Complete { finished_at: DateTime<Utc>, failures: Vec<String> },
 }
 
-async fn init_db(db: &Database) {
+/// Run `init_db` in the background so the server can answer probes meanwhile
+pub fn spawn_init_db(db: Database) -> watch::Receiver<InitStatus> {
+    let (status, receiver) = watch::channel(InitStatus::Pending);
+    tokio::spawn(async move {
+        status.send_replace(init_db(&db).await);
+    });
+    receiver
+}
+
+/// Create indexes and backfill older documents. Failures are logged and collected
+/// rather than fatal: the API still works, only slower or with weaker guarantees.
+async fn init_db(db: &Database) -> InitStatus {
+    let mut failures = Vec::new();
     let user_collection = db.collection::<User>("users");
 
     // Define unique index for Email
@@ -48,14 +108,247 @@ async fn init_db(db: &Database) {
         .options(IndexOptions::builder().unique(true).build())
         .build();
 
+    // The trash purge job scans for accounts deleted before the retention cutoff
+    let deleted_index = IndexModel::builder()
+        .keys(doc! { "deleted_at": 1 })
+        .build();
+
     // Create the indexes
-    let indexes = vec![email_index, username_index];
+    let indexes = vec![email_index, username_index, deleted_index];
     
     match user_collection.create_indexes(indexes).await {
-        Ok(_) => println!("🚀 Database indexes initialized (Email & Username are unique)"),
-        Err(e) => {
-            // This might fail if you already have duplicate data in your DB
-            eprintln!("⚠️ Warning: Could not create indexes: {}. Check for existing duplicate data.", e);
+        Ok(_) => tracing::info!("user indexes initialized (email and username are unique)"),
+        // This might fail if you already have duplicate data in your DB
+        Err(e) => failed(&mut failures, "create user indexes (check for existing duplicate data)", e),
+    }
+
+    // Sessions: look up by current or rotated refresh token hash,
+    // and let MongoDB drop them once they expire
+    let session_collection = db.collection::<Session>("sessions");
+    let session_indexes = vec![
+        IndexModel::builder()
+            .keys(doc! { "refresh_token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "rotated_token_hashes": 1 })
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build(),
+    ];
+
+    match session_collection.create_indexes(session_indexes).await {
+        Ok(_) => tracing::info!("session indexes initialized"),
+        Err(e) => failed(&mut failures, "create session indexes", e),
+    }
+
+    // Posts: listings filter on status/publish_at, the scheduler scans scheduled posts
+    let post_collection = db.collection::<Post>("posts");
+    let post_indexes = vec![
+        IndexModel::builder()
+            .keys(doc! { "status": 1, "publish_at": 1 })
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "author_id": 1, "created_at": -1 })
+            .build(),
+        // Slugs are unique; documents without one yet (pre-backfill) are skipped
+        IndexModel::builder()
+            .keys(doc! { "slug": 1 })
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! { "slug": { "$type": "string" } })
+                    .build(),
+            )
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "slug_history": 1 })
+            .build(),
+        // Multikey index: one entry per tag
+        IndexModel::builder()
+            .keys(doc! { "tags": 1 })
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "category": 1 })
+            .build(),
+        // The image worker refreshes embedded covers
+        IndexModel::builder()
+            .keys(doc! { "cover_image.media_id": 1 })
+            .build(),
+        // Trash listing and purge
+        IndexModel::builder()
+            .keys(doc! { "deleted_at": 1 })
+            .build(),
+        // Last-Modified of the feeds
+        IndexModel::builder()
+            .keys(doc! { "updated_at": -1 })
+            .build(),
+        // Full-text search; title matches weigh more than body matches
+        IndexModel::builder()
+            .keys(doc! { "title": "text", "content": "text" })
+            .options(
+                IndexOptions::builder()
+                    .name("post_text".to_string())
+                    .weights(doc! { "title": 3, "content": 1 })
+                    .build(),
+            )
+            .build(),
+    ];
+
+    // Give posts created before slugs existed a slug (before the unique index)
+    match backfill_slugs(db).await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!(posts = n, "generated slugs for existing posts"),
+        Err(e) => failed(&mut failures, "backfill post slugs", e),
+    }
+
+    match post_collection.create_indexes(post_indexes).await {
+        Ok(_) => tracing::info!("post indexes initialized"),
+        Err(e) => failed(&mut failures, "create post indexes", e),
+    }
+
+    // Posts created before publishing states existed were public: keep them that way
+    let backfill = post_collection
+        .update_many(
+            doc! { "status": { "$exists": false } },
+            vec![doc! {
+                "$set": {
+                    "status": PostStatus::Published.as_str(),
+                    "publish_at": "$created_at",
+                    "published_at": "$created_at",
+                }
+            }],
+        )
+        .await;
+
+    match backfill {
+        Ok(r) if r.modified_count > 0 => tracing::info!(posts = r.modified_count, "marked existing posts as published"),
+        Ok(_) => {}
+        Err(e) => failed(&mut failures, "backfill post status", e),
+    }
+
+    // Versions start at 1 for posts written before optimistic locking
+    if let Err(e) = post_collection
+        .update_many(doc! { "version": { "$exists": false } }, doc! { "$set": { "version": 1_i64 } })
+        .await
+    {
+        failed(&mut failures, "backfill post versions", e);
+    }
+
+    match backfill_rendered_content(db).await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!(posts = n, "rendered content for existing posts"),
+        Err(e) => failed(&mut failures, "render existing post content", e),
+    }
+
+    // Comments: a post's thread is read in order, subtrees are deleted by ancestor
+    let comment_collection = db.collection::<Comment>("comments");
+    let comment_indexes = vec![
+        IndexModel::builder()
+            .keys(doc! { "post_id": 1, "created_at": 1 })
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "ancestor_ids": 1 })
+            .build(),
+    ];
+
+    match comment_collection.create_indexes(comment_indexes).await {
+        Ok(_) => tracing::info!("comment indexes initialized"),
+        Err(e) => failed(&mut failures, "create comment indexes", e),
+    }
+
+    // Media: listed per owner, looked up by the posts that link to them,
+    // swept by the image worker for uploads still missing their variants
+    let media_indexes = vec![
+        IndexModel::builder()
+            .keys(doc! { "owner_id": 1, "created_at": -1 })
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "references": 1 })
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "processing": 1 })
+            .build(),
+    ];
+
+    match db.collection::<Media>("media").create_indexes(media_indexes).await {
+        Ok(_) => tracing::info!("media indexes initialized"),
+        Err(e) => failed(&mut failures, "create media indexes", e),
+    }
+
+    // Revisions: numbered per post, the unique index serializes concurrent edits
+    let revision_index = IndexModel::builder()
+        .keys(doc! { "post_id": 1, "revision": -1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+
+    match db.collection::<PostRevision>("post_revisions").create_index(revision_index).await {
+        Ok(_) => tracing::info!("revision indexes initialized"),
+        Err(e) => failed(&mut failures, "create revision indexes", e),
+    }
+
+    // Shared rate limit state (rate_limit.store = "mongodb"): dropped once it no longer matters
+    let expiry_index = || {
+        IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build()
+    };
+    for collection in ["rate_limits", "login_failures"] {
+        match db.collection::<Document>(collection).create_index(expiry_index()).await {
+            Ok(_) => tracing::info!(collection, "rate limit indexes initialized"),
+            Err(e) => failed(&mut failures, "create rate limit indexes", e),
+        }
+    }
+
+    InitStatus::Complete { finished_at: Utc::now(), failures }
+}
+
+fn failed(failures: &mut Vec<String>, step: &str, e: mongodb::error::Error) {
+    tracing::warn!(error = %e, "could not {}", step);
+    failures.push(format!("Could not {}", step)); // Shown by /readyz, so without the driver's error
+}
+
+async fn backfill_slugs(db: &Database) -> mongodb::error::Result<u64> {
+    let collection = db.collection::<Post>("posts");
+    let mut cursor = collection
+        .find(doc! { "$or": [{ "slug": { "$exists": false } }, { "slug": "" }] })
+        .await?;
+
+    let mut count = 0;
+    while let Some(post) = cursor.try_next().await? {
+        let Some(id) = post.id else { continue };
+        let mut slug = String::new();
+        for candidate in post_slug_candidates(&post.title) {
+            if collection.count_documents(slug_filter(&candidate, Some(id))).await? == 0 {
+                slug = candidate;
+                break;
+            }
         }
+        collection
+            .update_one(doc! { "_id": id }, doc! { "$set": { "slug": slug } })
+            .await?;
+        count += 1;
     }
-}
\ No newline at end of file
+    Ok(count)
+}
+
+/// Render posts written before content was stored as Markdown + sanitized HTML
+async fn backfill_rendered_content(db: &Database) -> mongodb::error::Result<u64> {
+    let collection = db.collection::<Post>("posts");
+    let mut cursor = collection
+        .find(doc! { "content_html": { "$exists": false } })
+        .await?;
+
+    let mut count = 0;
+    while let Some(post) = cursor.try_next().await? {
+        let Some(id) = post.id else { continue };
+        collection
+            .update_one(doc! { "_id": id }, doc! { "$set": markdown::render(&post.content).to_document() })
+            .await?;
+        count += 1;
+    }
+    Ok(count)
+}
@@ -1,61 +1,331 @@
+/*
+ * The one error type handlers return.
+ * Every error answers with an RFC 7807 `application/problem+json` body that carries a
+ * stable `code` and a `correlation_id` (the request's X-Request-Id). Server-side failures
+ * keep their cause and the place they were raised, and are traced under the same id.
+ */
 
+use std::error::Error as StdError;
+use std::panic::Location;
 
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use mongodb::error::{
+    ErrorKind, WriteFailure, RETRYABLE_WRITE_ERROR, TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT,
+};
+use rand::RngCore;
+use serde_json::{json, Map, Value};
+use validator::ValidationErrors;
+
+use crate::metrics::METRICS;
+use crate::storage::StorageError;
+use crate::telemetry::request_id;
+use crate::validation::field_errors;
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// MongoDB's duplicate key error code
+const DUPLICATE_KEY: i32 = 11000;
 
+/// MongoDB's IllegalOperation code, which a standalone server answers transactions with
+const ILLEGAL_OPERATION: i32 = 20;
+
+/// Seconds a client should wait before retrying after a transient database error
+const RETRY_AFTER_SECONDS: u64 = 1;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+#[allow(dead_code)]
 pub enum AppError {
     InvalidToken,        // Used in middleware
     WrongCredentials,    // Used in login
     MissingCredentials,  // Used in validation
-    NotFound,            // Used when ID doesn't exist
-    InternalServerError, // The "catch-all"
+    NotFound { resource: &'static str, id: String },
+    InvalidId { resource: &'static str, id: String }, // Not an ObjectId
     Forbidden,           // For non-admins trying to do admin stuff
-    BadRequest,
-    Conflict
+    BadRequest(&'static str),
+    Conflict(&'static str),
+    Duplicate { index: String }, // A unique index rejected the write
+    PreconditionRequired,                        // Versioned write without If-Match
+    PreconditionFailed { current_version: i64 }, // If-Match no longer matches
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    TooManyRequests { retry_after: u64 }, // Seconds until the rate limit lets the client through
+    AccountLocked { retry_after: u64 },   // Too many failed logins
+    RangeNotSatisfiable { size: u64 }, // Range header past the end of a file
+    InvalidBody { status: StatusCode, detail: String }, // Body is not the JSON the route expects
+    Validation(ValidationErrors),                      // Body parsed but broke the DTO's rules
+    Unavailable { source: mongodb::error::Error, location: &'static Location<'static> }, // Worth retrying
+    ReplicaSetRequired { source: mongodb::error::Error }, // A transaction against a standalone server
+    Internal { source: BoxError, location: &'static Location<'static> },
 }
 
+impl AppError {
+    pub fn not_found(resource: &'static str, id: impl ToString) -> Self {
+        Self::NotFound { resource, id: id.to_string() }
+    }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            Self::InternalServerError => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Internal server error",
-            ),
-            Self::WrongCredentials => (
-                StatusCode::UNAUTHORIZED, 
-                "Invalid email or password"
-            ),
-            Self::MissingCredentials => (
-                StatusCode::BAD_REQUEST, 
-                "Missing or invalid input"
-            ),
-            Self::NotFound => (
-                StatusCode::NOT_FOUND, 
-                "Resource not found"
+    pub fn invalid_id(resource: &'static str, id: impl ToString) -> Self {
+        Self::InvalidId { resource, id: id.to_string() }
+    }
+
+    /// A failure the client can't fix; the cause is logged, never sent
+    #[track_caller]
+    pub fn internal(source: impl Into<BoxError>) -> Self {
+        Self::Internal { source: source.into(), location: Location::caller() }
+    }
+
+    /// Stable, machine-readable identifier of the error kind
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidToken => "invalid_token",
+            Self::WrongCredentials => "wrong_credentials",
+            Self::MissingCredentials => "missing_credentials",
+            Self::NotFound { .. } => "not_found",
+            Self::InvalidId { .. } => "invalid_id",
+            Self::Forbidden => "forbidden",
+            Self::BadRequest(_) => "bad_request",
+            Self::Conflict(_) => "conflict",
+            Self::Duplicate { .. } => "duplicate_key",
+            Self::PreconditionRequired => "precondition_required",
+            Self::PreconditionFailed { .. } => "version_mismatch",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::UnsupportedMediaType => "unsupported_media_type",
+            Self::TooManyRequests { .. } => "rate_limited",
+            Self::AccountLocked { .. } => "account_locked",
+            Self::RangeNotSatisfiable { .. } => "range_not_satisfiable",
+            Self::InvalidBody { .. } => "invalid_body",
+            Self::Validation(_) => "validation_failed",
+            Self::Unavailable { .. } => "service_unavailable",
+            Self::ReplicaSetRequired { .. } => "replica_set_required",
+            Self::Internal { .. } => "internal_error",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidToken | Self::WrongCredentials => StatusCode::UNAUTHORIZED,
+            Self::MissingCredentials | Self::InvalidId { .. } | Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound { .. } => StatusCode::NOT_FOUND,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::Conflict(_) | Self::Duplicate { .. } => StatusCode::CONFLICT,
+            Self::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            Self::PreconditionFailed { .. } => StatusCode::PRECONDITION_FAILED,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::TooManyRequests { .. } | Self::AccountLocked { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::RangeNotSatisfiable { .. } => StatusCode::RANGE_NOT_SATISFIABLE,
+            Self::InvalidBody { status, .. } => *status,
+            Self::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::ReplicaSetRequired { .. } | Self::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Human-readable explanation for the client
+    fn detail(&self) -> String {
+        match self {
+            Self::InvalidToken => "Invalid or expired token".to_string(),
+            Self::WrongCredentials => "Invalid email or password".to_string(),
+            Self::MissingCredentials => "Missing or invalid input".to_string(),
+            Self::NotFound { resource, id } => format!("No {} with id {}", resource, id),
+            Self::InvalidId { resource, id } => format!("{:?} is not a valid {} id", id, resource),
+            Self::Forbidden => "You do not have permission to perform this action".to_string(),
+            Self::BadRequest(reason) | Self::Conflict(reason) => reason.to_string(),
+            Self::Duplicate { .. } => "Resource already exists".to_string(),
+            Self::PreconditionRequired => "If-Match header with the current version is required".to_string(),
+            Self::PreconditionFailed { .. } => "The resource was modified by someone else".to_string(),
+            Self::PayloadTooLarge => "Upload is too large".to_string(),
+            Self::UnsupportedMediaType => "File type is not allowed".to_string(),
+            Self::TooManyRequests { .. } => "Too many requests, slow down".to_string(),
+            Self::AccountLocked { .. } => "Too many failed logins, the account is temporarily locked".to_string(),
+            Self::RangeNotSatisfiable { .. } => "Requested range is not satisfiable".to_string(),
+            Self::InvalidBody { detail, .. } => detail.clone(),
+            Self::Validation(errors) => format!("{} field error(s) in the request body", field_errors(errors).len()),
+            Self::Unavailable { .. } => "The database is temporarily unavailable, try again".to_string(),
+            Self::ReplicaSetRequired { .. } => {
+                "This operation needs a transaction, and MongoDB only supports those on a replica set".to_string()
+            }
+            Self::Internal { .. } => "Internal server error".to_string(),
+        }
+    }
+
+    /// Extra problem members for the kinds that have them
+    fn extensions(&self) -> Map<String, Value> {
+        let mut extra = Map::new();
+        match self {
+            Self::NotFound { resource, id } | Self::InvalidId { resource, id } => {
+                extra.insert("resource".into(), json!(resource));
+                extra.insert("id".into(), json!(id));
+            }
+            Self::Duplicate { index } => {
+                extra.insert("index".into(), json!(index));
+            }
+            Self::PreconditionFailed { current_version } => {
+                extra.insert("current_version".into(), json!(current_version));
+            }
+            Self::TooManyRequests { retry_after } | Self::AccountLocked { retry_after } => {
+                extra.insert("retry_after".into(), json!(retry_after));
+            }
+            Self::Validation(errors) => {
+                extra.insert("errors".into(), json!(field_errors(errors)));
+            }
+            _ => {}
+        }
+        extra
+    }
+
+    /// Trace the error; server-side failures with their cause
+    fn log(&self, correlation_id: &str) {
+        match self {
+            Self::Internal { source, location } => tracing::error!(
+                correlation_id, code = self.code(), %location, error = %source, "request failed"
             ),
-            Self::InvalidToken => (
-                StatusCode::UNAUTHORIZED, 
-                "Invalid or expired token"
+            Self::ReplicaSetRequired { source } => tracing::error!(
+                correlation_id, code = self.code(), error = %source, "MongoDB is not a replica set, see DEVELOP.md"
             ),
-            // Add this line to satisfy the compiler!
-            Self::Forbidden => (
-                StatusCode::FORBIDDEN, 
-                "You do not have permission to perform this action"
+            Self::Unavailable { source, location } => tracing::warn!(
+                correlation_id, code = self.code(), %location, error = %source, "database unavailable"
             ),
-            Self::BadRequest => (StatusCode::BAD_REQUEST, "Bad Request"),
-            Self::Conflict => (StatusCode::CONFLICT, "Resource already exists"),
-        };
+            _ => tracing::debug!(correlation_id, code = self.code(), detail = %self.detail(), "request rejected"),
+        }
+    }
+}
 
+impl From<mongodb::error::Error> for AppError {
+    /// Duplicate keys become 409, transient failures 503, anything else 500 (transactions
+    /// on a standalone server with their own code)
+    #[track_caller]
+    fn from(e: mongodb::error::Error) -> Self {
+        if let Some(index) = duplicate_key_index(&e) {
+            return Self::Duplicate { index };
+        }
+        if needs_replica_set(&e) {
+            return Self::ReplicaSetRequired { source: e };
+        }
+        let location = Location::caller();
+        if is_transient(&e) {
+            return Self::Unavailable { source: e, location };
+        }
+        Self::Internal { source: Box::new(e), location }
+    }
+}
+
+impl From<bson::de::Error> for AppError {
+    #[track_caller]
+    fn from(e: bson::de::Error) -> Self {
+        Self::internal(e)
+    }
+}
+
+impl From<bson::ser::Error> for AppError {
+    #[track_caller]
+    fn from(e: bson::ser::Error) -> Self {
+        Self::internal(e)
+    }
+}
+
+impl From<StorageError> for AppError {
+    #[track_caller]
+    fn from(e: StorageError) -> Self {
+        Self::internal(e)
+    }
+}
+
+/// "Transaction numbers are only allowed on a replica set member or mongos"
+fn needs_replica_set(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, ErrorKind::Command(ce) if ce.code == ILLEGAL_OPERATION && ce.message.contains("replica set"))
+}
+
+/// Name of the unique index behind an E11000 error, if that's what this is
+pub fn duplicate_key_index(e: &mongodb::error::Error) -> Option<String> {
+    let write_error = match &*e.kind {
+        ErrorKind::Write(WriteFailure::WriteError(we)) => we,
+        _ => return None,
+    };
+    if write_error.code != DUPLICATE_KEY {
+        return None;
+    }
+    // "E11000 duplicate key error collection: blog.users index: email_1 dup key: { ... }"
+    let index = write_error.message
+        .split("index: ")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap_or("unknown");
+    Some(index.to_string())
+}
+
+pub fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    duplicate_key_index(e).is_some()
+}
+
+/// Failures that may well succeed on retry: lost connections, elections, no reachable server,
+/// commits whose outcome stayed unknown
+fn is_transient(e: &mongodb::error::Error) -> bool {
+    e.contains_label(TRANSIENT_TRANSACTION_ERROR)
+        || e.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT)
+        || e.contains_label(RETRYABLE_WRITE_ERROR)
+        || matches!(
+            *e.kind,
+            ErrorKind::Io(_) | ErrorKind::ConnectionPoolCleared { .. } | ErrorKind::ServerSelection { .. }
+        )
+}
+
+/// Id tying the response to the log lines: the request id, or a random one outside a request
+fn correlation_id() -> String {
+    if let Some(id) = request_id() {
+        return id;
+    }
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
-        let body = Json(json!({
-            "error": error_message,
-        }));
 
-        (status, body).into_response()
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let correlation_id = correlation_id();
+        self.log(&correlation_id);
+        METRICS.error(self.code(), status.as_u16());
+
+        let mut body = Map::new();
+        body.insert("type".into(), json!("about:blank"));
+        body.insert("title".into(), json!(status.canonical_reason().unwrap_or("Error")));
+        body.insert("status".into(), json!(status.as_u16()));
+        body.insert("code".into(), json!(self.code()));
+        body.insert("detail".into(), json!(self.detail()));
+        body.insert("correlation_id".into(), json!(correlation_id));
+        body.extend(self.extensions());
+
+        let mut response = (status, [(header::CONTENT_TYPE, PROBLEM_JSON)], Json(body)).into_response();
+        let headers = response.headers_mut();
+
+        match self {
+            // Stale writes tell the client which version to re-read
+            Self::PreconditionFailed { current_version } => {
+                if let Ok(etag) = format!("\"{}\"", current_version).parse() {
+                    headers.insert(header::ETAG, etag);
+                }
+            }
+            // Tell the client how large the file actually is
+            Self::RangeNotSatisfiable { size } => {
+                if let Ok(range) = format!("bytes */{}", size).parse() {
+                    headers.insert(header::CONTENT_RANGE, range);
+                }
+            }
+            Self::Unavailable { .. } => {
+                headers.insert(header::RETRY_AFTER, RETRY_AFTER_SECONDS.into());
+            }
+            Self::TooManyRequests { retry_after } | Self::AccountLocked { retry_after } => {
+                headers.insert(header::RETRY_AFTER, retry_after.into());
+            }
+            _ => {}
+        }
+
+        response
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,187 @@
+use axum::{extract::{Path, State}, Json, http::StatusCode, response::IntoResponse};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::models::comment::{Comment, CommentNode, CreateCommentRequest, UpdateCommentRequest, MAX_DEPTH};
+use crate::validation::ValidatedJson;
+use crate::AppState;
+
+
+pub async fn create_comment(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(post_id): Path<String>,
+    ValidatedJson(payload): ValidatedJson<CreateCommentRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let post_id = find_live_post(&state, &post_id).await?;
+
+    // 1. Replies inherit the parent's ancestry; the parent must belong to the same post.
+    //    Past MAX_DEPTH the reply goes to the deepest ancestor that may still have replies
+    let (parent_id, ancestor_ids) = match payload.parent_id.as_deref() {
+        Some(raw) => {
+            let parent_oid = ObjectId::parse_str(raw).map_err(|_| AppError::invalid_id("comment", raw))?;
+            let parent = state.comments
+                .find(post_id, parent_oid)
+                .await?
+                .ok_or_else(|| AppError::not_found("comment", parent_oid))?;
+
+            let mut ancestors = parent.ancestor_ids;
+            ancestors.push(parent_oid);
+            ancestors.truncate(MAX_DEPTH);
+            (ancestors.last().copied(), ancestors)
+        }
+        None => (None, Vec::new()),
+    };
+
+    // 2. Insert
+    let now = Utc::now();
+    let comment = Comment {
+        id: None,
+        post_id,
+        author_id: auth.user_id,
+        parent_id,
+        ancestor_ids,
+        content: payload.content,
+        created_at: now,
+        updated_at: now,
+    };
+
+    let id = state.comments.insert(&comment).await?;
+
+    Ok((StatusCode::CREATED, Json(id)))
+}
+
+
+/// Returns the whole discussion of a post as a tree, oldest first at every level
+pub async fn get_comments(
+    State(state): State<Arc<AppState>>,
+    Path(post_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post_id = find_live_post(&state, &post_id).await?;
+
+    let flat = state.comments.list(post_id).await?;
+
+    Ok(Json(build_tree(flat)))
+}
+
+
+pub async fn update_comment(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path((post_id, comment_id)): Path<(String, String)>,
+    ValidatedJson(payload): ValidatedJson<UpdateCommentRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let comment = find_owned_comment(&state, &post_id, &comment_id, &auth).await?;
+    let comment_id = comment.id.ok_or_else(|| AppError::internal("comment without _id"))?;
+
+    state.comments.update_content(comment_id, payload.content).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "status": "success",
+            "message": "Comment updated successfully"
+        })),
+    ))
+}
+
+
+/// Deleting a comment removes its replies too
+pub async fn delete_comment(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path((post_id, comment_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let comment = find_owned_comment(&state, &post_id, &comment_id, &auth).await?;
+    let comment_id = comment.id.ok_or_else(|| AppError::internal("comment without _id"))?;
+
+    let deleted_count = state.comments.delete_thread(comment_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "status": "success",
+            "message": "Comment deleted successfully",
+            "deleted_count": deleted_count
+        })),
+    ))
+}
+
+
+/// Comments can only be read or written on posts the public can see
+async fn find_live_post(state: &AppState, id: &str) -> Result<ObjectId, AppError> {
+    let post_id = ObjectId::parse_str(id).map_err(|_| AppError::invalid_id("post", id))?;
+
+    if !state.posts.is_published(post_id).await? {
+        return Err(AppError::not_found("post", post_id));
+    }
+    Ok(post_id)
+}
+
+
+/// Same author-or-admin rule as posts
+async fn find_owned_comment(
+    state: &AppState,
+    post_id: &str,
+    comment_id: &str,
+    auth: &AuthUser,
+) -> Result<Comment, AppError> {
+    let post_id = ObjectId::parse_str(post_id).map_err(|_| AppError::invalid_id("post", post_id))?;
+    let comment_id = ObjectId::parse_str(comment_id).map_err(|_| AppError::invalid_id("comment", comment_id))?;
+
+    let comment = state.comments
+        .find(post_id, comment_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("comment", comment_id))?;
+
+    if !auth.can_modify(&comment.author_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(comment)
+}
+
+
+/// Nest a flat, chronologically sorted list of comments under their parents.
+/// Replies whose parent is missing (e.g. its author was removed) are promoted to the top level.
+/// Built bottom-up without recursion, so a deep thread cannot exhaust the stack.
+fn build_tree(flat: Vec<CommentNode>) -> Vec<CommentNode> {
+    let mut nodes: HashMap<ObjectId, CommentNode> = HashMap::new();
+    let mut children: HashMap<ObjectId, Vec<ObjectId>> = HashMap::new();
+    let mut roots = Vec::new();
+
+    let ids: HashSet<ObjectId> = flat.iter().map(|c| c.id).collect();
+    for node in flat {
+        match node.parent_id {
+            Some(parent) if ids.contains(&parent) => children.entry(parent).or_default().push(node.id),
+            _ => roots.push(node.id),
+        }
+        nodes.insert(node.id, node);
+    }
+
+    // 1. Parents before their replies, level by level
+    let mut order = roots.clone();
+    let mut i = 0;
+    while let Some(id) = order.get(i) {
+        order.extend(children.get(id).into_iter().flatten());
+        i += 1;
+    }
+
+    // 2. Deepest first, so every reply is complete when it moves into its parent
+    for id in order.iter().rev() {
+        let replies = children
+            .remove(id)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|reply| nodes.remove(reply))
+            .collect();
+        if let Some(node) = nodes.get_mut(id) {
+            node.replies = replies;
+        }
+    }
+
+    roots.iter().filter_map(|id| nodes.remove(id)).collect()
+}
@@ -0,0 +1,319 @@
+/*
+ * Syndication feeds of published posts: RSS 2.0, Atom and JSON Feed 1.1,
+ * for the whole site, one author or one tag.
+ */
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::fmt::Write;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use crate::error::AppError;
+use crate::handlers::post_handler::resolve_author;
+use crate::models::post::PostWithAuthor;
+use crate::repository::PostFilter;
+use crate::slug::slugify;
+use crate::config::SiteConfig;
+use crate::AppState;
+
+/// Number of most recently published posts in a feed
+const FEED_SIZE: i64 = 20;
+
+/// Feeds are polled; let readers and proxies reuse a copy for a few minutes
+const FEED_CACHE_CONTROL: &str = "public, max-age=300";
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+
+/// Serialization picked from the requested file name: feed.rss, feed.atom or feed.json
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::Rss => "feed.rss",
+            Self::Atom => "feed.atom",
+            Self::Json => "feed.json",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Rss => "application/rss+xml; charset=utf-8",
+            Self::Atom => "application/atom+xml; charset=utf-8",
+            Self::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for FeedFormat {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let path = parts.uri.path();
+        [Self::Rss, Self::Atom, Self::Json]
+            .into_iter()
+            .find(|format| path.ends_with(format.file_name()))
+            .ok_or_else(|| AppError::not_found("feed", path))
+    }
+}
+
+fn post_url(site: &SiteConfig, post: &PostWithAuthor) -> String {
+    format!("{}/posts/{}", site.url, post.id.to_hex())
+}
+
+/// One feed: what it covers, and where it lives
+struct Feed {
+    title: String,
+    self_path: String, // Without the file name, e.g. "/tags/rust/"
+    posts: Vec<PostWithAuthor>,
+    updated: DateTime<Utc>,
+}
+
+/// Last-Modified of every feed. A post that was trashed, unpublished or retagged has left
+/// the feed, so the newest post still in it can't tell; the latest change to any post can.
+async fn last_change(state: &AppState) -> Result<DateTime<Utc>, AppError> {
+    Ok(state.posts.last_change().await?.unwrap_or(DateTime::UNIX_EPOCH))
+}
+
+
+/// GET /feed.rss, /feed.atom, /feed.json — every published post
+pub async fn site_feed(
+    State(state): State<Arc<AppState>>,
+    format: FeedFormat,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let feed = Feed {
+        title: state.config.site.title.clone(),
+        self_path: "/".to_string(),
+        posts: state.posts.latest(&PostFilter::published(), FEED_SIZE).await?,
+        updated: last_change(&state).await?,
+    };
+    Ok(respond(&state.config.site, feed, format, &headers))
+}
+
+/// GET /users/:author/feed.{rss,atom,json} — by author id or username
+pub async fn author_feed(
+    State(state): State<Arc<AppState>>,
+    Path(author): Path<String>,
+    format: FeedFormat,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let author_id = resolve_author(&state, &author).await?.ok_or_else(|| AppError::not_found("user", &author))?;
+
+    let filter = PostFilter { author_id: Some(author_id), ..PostFilter::published() };
+    let posts = state.posts.latest(&filter, FEED_SIZE).await?;
+
+    let name = posts.first().map_or(author.as_str(), |p| p.author_name.as_str());
+    let feed = Feed {
+        title: format!("{} — {}", state.config.site.title, name),
+        self_path: format!("/users/{}/", author),
+        posts,
+        updated: last_change(&state).await?,
+    };
+    Ok(respond(&state.config.site, feed, format, &headers))
+}
+
+/// GET /tags/:slug/feed.{rss,atom,json}
+pub async fn tag_feed(
+    State(state): State<Arc<AppState>>,
+    Path(tag): Path<String>,
+    format: FeedFormat,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let slug = slugify(&tag);
+
+    let filter = PostFilter { tag: Some(slug.clone()), ..PostFilter::published() };
+    let posts = state.posts.latest(&filter, FEED_SIZE).await?;
+
+    let feed = Feed {
+        title: format!("{} — #{}", state.config.site.title, slug),
+        self_path: format!("/tags/{}/", slug),
+        posts,
+        updated: last_change(&state).await?,
+    };
+    Ok(respond(&state.config.site, feed, format, &headers))
+}
+
+/// Render the feed, or answer 304 when nothing changed since If-Modified-Since
+fn respond(site: &SiteConfig, feed: Feed, format: FeedFormat, headers: &HeaderMap) -> Response {
+    let updated = feed.updated;
+    let last_modified = updated.format(HTTP_DATE).to_string();
+
+    let caching = [
+        (header::LAST_MODIFIED, last_modified),
+        (header::CACHE_CONTROL, FEED_CACHE_CONTROL.to_string()),
+    ];
+
+    let unchanged = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+        // HTTP dates have second precision; compare at that precision too
+        .is_some_and(|since| updated.timestamp() <= since.timestamp());
+    if unchanged {
+        return (StatusCode::NOT_MODIFIED, caching).into_response();
+    }
+
+    let body = match format {
+        FeedFormat::Rss => rss(site, &feed, updated),
+        FeedFormat::Atom => atom(site, &feed, updated),
+        FeedFormat::Json => json_feed(site, &feed),
+    };
+
+    (
+        StatusCode::OK,
+        caching,
+        [(header::CONTENT_TYPE, format.content_type().to_string())],
+        body,
+    )
+        .into_response()
+}
+
+/// RSS 2.0, with the full HTML in content:encoded
+fn rss(site: &SiteConfig, feed: &Feed, updated: DateTime<Utc>) -> String {
+    let self_url = format!("{}{}{}", site.api_url, feed.self_path, FeedFormat::Rss.file_name());
+
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:content="http://purl.org/rss/1.0/modules/content/" xmlns:dc="http://purl.org/dc/elements/1.1/">"#);
+    let _ = write!(
+        xml,
+        "<channel><title>{}</title><link>{}</link><description>{}</description>\
+         <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/><lastBuildDate>{}</lastBuildDate>",
+        escape_xml(&feed.title),
+        escape_xml(&site.url),
+        escape_xml(&feed.title),
+        escape_xml(&self_url),
+        updated.to_rfc2822(),
+    );
+
+    for post in &feed.posts {
+        let url = post_url(site, post);
+        let published = post.published_at.or(post.publish_at).unwrap_or(post.created_at);
+        let _ = write!(
+            xml,
+            "<item><title>{}</title><link>{}</link><guid isPermaLink=\"false\">{}</guid>\
+             <pubDate>{}</pubDate><dc:creator>{}</dc:creator>",
+            escape_xml(&post.title),
+            escape_xml(&url),
+            post.id.to_hex(),
+            published.to_rfc2822(),
+            escape_xml(&post.author_name),
+        );
+        for tag in &post.tags {
+            let _ = write!(xml, "<category>{}</category>", escape_xml(tag));
+        }
+        let _ = write!(
+            xml,
+            "<description>{}</description><content:encoded>{}</content:encoded></item>",
+            escape_xml(&post.excerpt),
+            escape_xml(&post.content_html),
+        );
+    }
+
+    xml.push_str("</channel></rss>");
+    xml
+}
+
+/// Atom 1.0 (RFC 4287)
+fn atom(site: &SiteConfig, feed: &Feed, updated: DateTime<Utc>) -> String {
+    let self_url = format!("{}{}{}", site.api_url, feed.self_path, FeedFormat::Atom.file_name());
+
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = write!(
+        xml,
+        "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>{}</title><id>{}</id>\
+         <link rel=\"self\" href=\"{}\"/><link href=\"{}\"/><updated>{}</updated>",
+        escape_xml(&feed.title),
+        escape_xml(&self_url),
+        escape_xml(&self_url),
+        escape_xml(&site.url),
+        updated.to_rfc3339(),
+    );
+
+    for post in &feed.posts {
+        let url = post_url(site, post);
+        let published = post.published_at.or(post.publish_at).unwrap_or(post.created_at);
+        let _ = write!(
+            xml,
+            "<entry><title>{}</title><id>{}</id><link href=\"{}\"/>\
+             <published>{}</published><updated>{}</updated><author><name>{}</name></author>",
+            escape_xml(&post.title),
+            escape_xml(&url),
+            escape_xml(&url),
+            published.to_rfc3339(),
+            post.updated_at.to_rfc3339(),
+            escape_xml(&post.author_name),
+        );
+        for tag in &post.tags {
+            let _ = write!(xml, "<category term=\"{}\"/>", escape_xml(tag));
+        }
+        let _ = write!(
+            xml,
+            "<summary>{}</summary><content type=\"html\">{}</content></entry>",
+            escape_xml(&post.excerpt),
+            escape_xml(&post.content_html),
+        );
+    }
+
+    xml.push_str("</feed>");
+    xml
+}
+
+/// JSON Feed 1.1 (https://jsonfeed.org/version/1.1)
+fn json_feed(site: &SiteConfig, feed: &Feed) -> String {
+    let items: Vec<_> = feed.posts.iter().map(|post| {
+        let published = post.published_at.or(post.publish_at).unwrap_or(post.created_at);
+        json!({
+            "id": post.id.to_hex(),
+            "url": post_url(site, post),
+            "title": post.title,
+            "content_html": post.content_html,
+            "summary": post.excerpt,
+            "image": post.cover_image.as_ref().map(|c| format!("{}{}", site.api_url, c.url)),
+            "date_published": published.to_rfc3339(),
+            "date_modified": post.updated_at.to_rfc3339(),
+            "authors": [{ "name": post.author_name }],
+            "tags": post.tags,
+        })
+    }).collect();
+
+    json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": feed.title,
+        "home_page_url": site.url,
+        "feed_url": format!("{}{}{}", site.api_url, feed.self_path, FeedFormat::Json.file_name()),
+        "items": items,
+    })
+    .to_string()
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Not allowed anywhere in XML 1.0
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,55 @@
+/*
+ * Probes for the orchestrator: /healthz says the process is alive,
+ * /readyz says it can serve traffic (MongoDB answers and init_db has run).
+ */
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use mongodb::bson::doc;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::db::InitStatus;
+use crate::AppState;
+
+/// A ping slower than this counts as the database being down
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// GET /healthz — liveness; never touches the database
+pub async fn healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+/// GET /readyz — 503 while MongoDB is unreachable or indexes are still being set up.
+/// Index steps that failed are reported, but leave the service "degraded" rather than down.
+pub async fn readyz(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let started = Instant::now();
+    let ping = tokio::time::timeout(PING_TIMEOUT, state.db.run_command(doc! { "ping": 1 })).await;
+
+    // The probe is unauthenticated: why the ping failed (hosts, topology) goes to the log only
+    let database_up = matches!(ping, Ok(Ok(_)));
+    let database = match ping {
+        Ok(Ok(_)) => json!({ "status": "up", "latency_ms": started.elapsed().as_millis() as u64 }),
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "readiness ping failed");
+            json!({ "status": "down" })
+        }
+        Err(_) => {
+            tracing::warn!(timeout = ?PING_TIMEOUT, "readiness ping timed out");
+            json!({ "status": "down" })
+        }
+    };
+
+    let init = state.db_init.borrow().clone();
+    let (status, code) = match &init {
+        _ if !database_up => ("not_ready", StatusCode::SERVICE_UNAVAILABLE),
+        InitStatus::Pending => ("not_ready", StatusCode::SERVICE_UNAVAILABLE),
+        InitStatus::Complete { failures, .. } if !failures.is_empty() => ("degraded", StatusCode::OK),
+        InitStatus::Complete { .. } => ("ready", StatusCode::OK),
+    };
+
+    let body = json!({
+        "status": status,
+        "checks": { "database": database, "indexes": init },
+    });
+    (code, Json(body))
+}
@@ -0,0 +1,351 @@
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use sha2::{Digest, Sha256};
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::imaging;
+use crate::models::media::{ImageRef, Media, MediaResponse, ProcessingStatus};
+use crate::models::pagination::{page_size, Cursor, Page, PageQuery};
+use crate::storage::StorageError;
+use crate::AppState;
+
+/// Only images are accepted; the type is sniffed from the bytes
+const ALLOWED_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+/// Files never change under an id, so clients and CDNs may keep them forever
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Length of an ObjectId in hex
+const OBJECT_ID_HEX_LEN: usize = 24;
+
+
+/// POST /media — multipart upload with a `file` field
+pub async fn upload_media(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = state.config.media.max_upload_bytes;
+
+    // 1. Read the `file` field, enforcing the size limit while streaming
+    let mut upload = None;
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let original_name = field.file_name().map(str::to_string);
+        let mut data = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            if data.len() + chunk.len() > limit {
+                return Err(AppError::PayloadTooLarge);
+            }
+            data.extend_from_slice(&chunk);
+        }
+        upload = Some((original_name, data));
+        break;
+    }
+    let (original_name, data) = upload.ok_or(AppError::BadRequest("The upload needs a `file` field"))?;
+
+    // 2. Trust the bytes, not the client's Content-Type
+    let kind = infer::get(&data).ok_or(AppError::UnsupportedMediaType)?;
+    if !ALLOWED_TYPES.contains(&kind.mime_type()) {
+        return Err(AppError::UnsupportedMediaType);
+    }
+
+    // 3. Location data and the like must not leak through the original. A JPEG keeps
+    //    its orientation tag; it is also kept aside so the variants come out upright
+    let orientation = imaging::exif_orientation(&data);
+    let data = imaging::strip_metadata(&data, kind.mime_type()).ok_or(AppError::UnsupportedMediaType)?;
+
+    // 4. Store the bytes, then the metadata
+    let id = ObjectId::new();
+    let now = Utc::now();
+    let storage_key = format!("{}/{}.{}", now.format("%Y/%m"), id.to_hex(), kind.extension());
+
+    state.media.put(&storage_key, &data, kind.mime_type()).await?;
+
+    let media = Media {
+        id: Some(id),
+        owner_id: auth.user_id,
+        storage_key,
+        content_type: kind.mime_type().to_string(),
+        size: data.len() as i64,
+        sha256: hex_digest(&data),
+        original_name,
+        references: Vec::new(),
+        created_at: now,
+        orientation: orientation as i32,
+        processing: ProcessingStatus::Pending,
+        processing_started_at: None,
+        processing_error: None,
+        width: None,
+        height: None,
+        blurhash: None,
+        variants: Vec::new(),
+    };
+
+    if let Err(e) = state.uploads.insert(&media).await {
+        // Don't leave unreachable bytes behind
+        let _ = state.media.delete(&media.storage_key).await;
+        return Err(e);
+    }
+
+    // 5. Variants are generated in the background; a full queue is picked up by the worker's sweep
+    let _ = state.image_jobs.try_send(id);
+
+    Ok((StatusCode::CREATED, Json(MediaResponse::from(media))))
+}
+
+/// GET /media — your uploads, newest first
+pub async fn list_media(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Query(query): Query<PageQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let sort = query.sort.unwrap_or_default();
+    let descending = sort.descending();
+    let limit = page_size(query.limit);
+
+    let after = query.cursor.as_deref().map(|raw| Cursor::decode(raw, sort)).transpose()?;
+
+    let items = state.uploads.list(auth.user_id, descending, after.as_ref(), limit + 1).await?;
+
+    let page = Page::from_overfetch(items, limit, |m| Cursor {
+        sort,
+        key: m.created_at,
+        id: m.id.unwrap_or_default(),
+    });
+
+    Ok(Json(page.map(MediaResponse::from)))
+}
+
+/// GET /media/:id — serves the original; supports a single `Range` and conditional requests
+pub async fn get_media(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let media = find_media(&state, &id).await?;
+
+    let object = StoredObject {
+        key: &media.storage_key,
+        content_type: &media.content_type,
+        size: media.size as u64,
+        sha256: &media.sha256,
+        created_at: media.created_at,
+    };
+    serve_object(&state, object, &headers).await
+}
+
+/// GET /media/:id/:variant — a resized copy, e.g. `w640.webp` or `thumb.jpg`
+pub async fn get_media_variant(
+    State(state): State<Arc<AppState>>,
+    Path((id, variant)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let media = find_media(&state, &id).await?;
+    let variant = media.variants
+        .iter()
+        .find(|v| v.file_name() == variant)
+        .ok_or_else(|| AppError::not_found("media variant", format!("{}/{}", id, variant)))?; // Unknown, or not generated yet
+
+    let object = StoredObject {
+        key: &variant.storage_key,
+        content_type: &variant.content_type,
+        size: variant.size as u64,
+        sha256: &variant.sha256,
+        created_at: media.created_at,
+    };
+    serve_object(&state, object, &headers).await
+}
+
+/// DELETE /media/:id — owner or admin, and only once no post or profile uses it
+pub async fn delete_media(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let media = find_media(&state, &id).await?;
+
+    if !auth.can_modify(&media.owner_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    let media_id = media.id.ok_or_else(|| AppError::internal("media without _id"))?;
+    if state.uploads.used_as_avatar(media_id).await? {
+        return Err(AppError::Conflict("The file is used as an avatar"));
+    }
+
+    if !state.uploads.delete_unreferenced(media_id).await? {
+        return Err(AppError::Conflict("The file is still used by a post"));
+    }
+
+    let keys = std::iter::once(&media.storage_key).chain(media.variants.iter().map(|v| &v.storage_key));
+    for key in keys {
+        if let Err(e) = state.media.delete(key).await {
+            tracing::warn!(storage_key = %key, error = %e, "could not delete media file");
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Point the `references` of every media file at the posts that use it, by linking
+/// to it in the content or as cover image. Called whenever a post is written.
+pub async fn sync_media_references(
+    state: &AppState,
+    post_id: ObjectId,
+    content: &str,
+    cover: Option<ObjectId>,
+) -> Result<(), AppError> {
+    let mut linked = linked_media_ids(content);
+    linked.extend(cover.filter(|id| !linked.contains(id)));
+    state.uploads.sync_references(post_id, &linked).await
+}
+
+/// What `serve_object` needs to know about a stored file
+struct StoredObject<'a> {
+    key: &'a str,
+    content_type: &'a str,
+    size: u64,
+    sha256: &'a str,
+    created_at: DateTime<Utc>,
+}
+
+/// Stream a stored file with cache headers, honouring If-None-Match and a single Range
+async fn serve_object(
+    state: &AppState,
+    object: StoredObject<'_>,
+    headers: &HeaderMap,
+) -> Result<Response, AppError> {
+    let etag = format!("\"{}\"", object.sha256);
+    let last_modified = object.created_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+        (header::LAST_MODIFIED, last_modified),
+    ];
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let range = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(value) => parse_range(value, object.size)?,
+        None => None,
+    };
+
+    let body = match state.media.get(object.key, range).await {
+        Ok(body) => body,
+        Err(StorageError::NotFound) => return Err(AppError::not_found("file", object.key)),
+        Err(e) => return Err(e.into()),
+    };
+
+    let common = [
+        (header::CONTENT_TYPE, object.content_type.to_string()),
+        (header::ACCEPT_RANGES, "bytes".to_string()),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+    ];
+
+    Ok(match range {
+        Some((start, end)) => {
+            let content_range = format!("bytes {}-{}/{}", start, end, object.size);
+            (
+                StatusCode::PARTIAL_CONTENT,
+                cache_headers,
+                common,
+                [(header::CONTENT_RANGE, content_range)],
+                body,
+            )
+                .into_response()
+        }
+        None => (StatusCode::OK, cache_headers, common, body).into_response(),
+    })
+}
+
+/// An upload of the caller (any upload, for admins) to embed as cover image or avatar
+pub async fn find_image(state: &AppState, id: &str, auth: &AuthUser) -> Result<ImageRef, AppError> {
+    let media = find_media(state, id).await?;
+    if !auth.can_modify(&media.owner_id) {
+        return Err(AppError::Forbidden);
+    }
+    Ok(media.image_ref())
+}
+
+async fn find_media(state: &AppState, id: &str) -> Result<Media, AppError> {
+    let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::invalid_id("media", id))?;
+
+    state.uploads
+        .find(obj_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("media", obj_id))
+}
+
+/// Ids from every `/media/<id>` link in a post's Markdown
+fn linked_media_ids(content: &str) -> Vec<ObjectId> {
+    let mut ids = Vec::new();
+    for (i, _) in content.match_indices("/media/") {
+        let rest = &content[i + "/media/".len()..];
+        if let Some(hex) = rest.get(..OBJECT_ID_HEX_LEN)
+            && let Ok(id) = ObjectId::parse_str(hex)
+            && !ids.contains(&id)
+        {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// Single `bytes=` range as inclusive positions. Anything we don't support
+/// (multiple ranges, other units, garbage) is ignored and the whole file is served.
+fn parse_range(value: &str, size: u64) -> Result<Option<(u64, u64)>, AppError> {
+    let Some(spec) = value.strip_prefix("bytes=") else { return Ok(None) };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else { return Ok(None) };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=-500: the last 500 bytes
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 || size == 0 {
+                return Err(AppError::RangeNotSatisfiable { size });
+            }
+            (size.saturating_sub(suffix), size - 1)
+        }
+        // bytes=500-
+        (Ok(start), Err(_)) if end.is_empty() => (start, size.saturating_sub(1)),
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+        _ => return Ok(None),
+    };
+
+    if range.0 >= size {
+        return Err(AppError::RangeNotSatisfiable { size });
+    }
+    Ok(Some(range))
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn multipart_error(e: axum::extract::multipart::MultipartError) -> AppError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::PayloadTooLarge
+    } else {
+        AppError::InvalidBody { status: e.status(), detail: e.body_text() }
+    }
+}
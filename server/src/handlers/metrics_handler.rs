@@ -0,0 +1,9 @@
+use axum::{http::header, response::IntoResponse};
+use crate::error::AppError;
+use crate::metrics::METRICS;
+
+/// GET /metrics — Prometheus text format, for the scraper
+pub async fn metrics() -> Result<impl IntoResponse, AppError> {
+    let body = METRICS.render()?;
+    Ok(([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body))
+}
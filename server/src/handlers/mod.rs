@@ -1,2 +1,11 @@
 pub mod user_handler;
-pub mod post_handler; 
\ No newline at end of file
+pub mod post_handler;
+pub mod search_handler;
+pub mod comment_handler;
+pub mod tag_handler;
+pub mod revision_handler;
+pub mod trash_handler;
+pub mod media_handler;
+pub mod feed_handler;
+pub mod health_handler;
+pub mod metrics_handler;
\ No newline at end of file
@@ -1,15 +1,21 @@
 
 
-use axum::{extract::State, Json, http::StatusCode, response::IntoResponse};
+use axum::{extract::{Query, State}, Json, http::{header, HeaderMap, HeaderValue, StatusCode}, response::{IntoResponse, Response}};
 use std::sync::Arc;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use crate::error::AppError;
-use mongodb::bson::doc;
-use futures::stream::TryStreamExt; // Required for helper methods like try_collect()
-use crate::models::post::{Post, CreatePostRequest, PostWithAuthor, UpdatePostRequest};
+use crate::models::post::{Post, CreatePostRequest, PostStatus, PostWithAuthor, PublishRequest, UpdatePostRequest};
+use crate::models::pagination::{page_size, Cursor, Page, PostListQuery, SortOrder};
+use crate::models::tag::{normalize_category, normalize_tags};
+use crate::markdown;
+use crate::repository::{PostFilter, PostKey, PostUpdate};
+use crate::slug::{slugify, unique_post_slug};
+use crate::validation::ValidatedJson;
 use crate::AppState;
-use crate::middleware::AuthUser;
-// use mongodb::options::FindOptions;
+use crate::auth::AuthUser;
+use crate::handlers::media_handler::{find_image, sync_media_references};
+use crate::handlers::revision_handler::{ensure_initial_revision, record_revision};
+use sha2::{Digest, Sha256};
 use axum::extract::Path;
 use bson::oid::ObjectId;
 
@@ -17,119 +23,140 @@ use bson::oid::ObjectId;
 pub async fn create_post(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
-    Json(payload): Json<CreatePostRequest>,
+    ValidatedJson(payload): ValidatedJson<CreatePostRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    let collection = state.db.collection::<Post>("posts");
+    let author_id = auth.user_id;
+    let now = Utc::now();
 
-    // Convert string ID from JWT back to ObjectId
-    let author_id = ObjectId::parse_str(&auth.user_id)
-        .map_err(|_| AppError::BadRequest)?;
+    // Work out when (and whether) the post goes public
+    let (status, publish_at) = resolve_publication(payload.status, payload.publish_at, now)?;
+
+    let tags = normalize_tags(&payload.tags)?;
+    let category = payload.category.as_deref().and_then(normalize_category);
+
+    let slug = unique_post_slug(state.posts.as_ref(), &payload.title, None).await?;
+
+    let rendered = markdown::render(&payload.content);
+
+    let cover_image = match payload.cover_image_id.as_deref() {
+        Some(id) => Some(find_image(&state, id, &auth).await?),
+        None => None,
+    };
 
     let new_post = Post {
         id: None,
         author_id,
+        slug,
+        slug_history: Vec::new(),
         title: payload.title,
         content: payload.content,
-        created_at: Utc::now(),
-        updated_at: Utc::now(),
+        content_html: rendered.html,
+        toc: rendered.toc,
+        excerpt: rendered.excerpt,
+        reading_time_minutes: rendered.reading_time_minutes,
+        created_at: now,
+        updated_at: now,
+        status,
+        publish_at,
+        published_at: (status == PostStatus::Published).then_some(now),
+        tags,
+        category,
+        cover_image,
+        version: 1,
+        deleted_at: None,
+        deleted_by: None,
     };
 
-    let result = collection.insert_one(new_post).await
-        .map_err(|_| AppError::InternalServerError)?;
+    // Two posts racing for the same slug: one of them gets a 409
+    let id = state.posts.insert(&new_post).await?;
 
-    Ok((StatusCode::CREATED, Json(result.inserted_id)))
+    // Revision 1 is the post as created
+    let created = Post { id: Some(id), ..new_post };
+    record_revision(&state, &created, author_id, None).await?;
+    let cover = created.cover_image.as_ref().map(|c| c.media_id);
+    sync_media_references(&state, id, &created.content, cover).await?;
+
+    Ok((StatusCode::CREATED, Json(id)))
 }
 
 
 pub async fn get_posts(
     State(state): State<Arc<AppState>>,
+    Query(query): Query<PostListQuery>,
 ) -> Result<impl IntoResponse, AppError> {
-    let collection = state.db.collection::<Post>("posts");
-
-    // Define the pipeline
-    let pipeline = vec![
-        // 1. Join with users collection
-        doc! {
-            "$lookup": {
-                "from": "users",
-                "localField": "author_id",
-                "foreignField": "_id",
-                "as": "author_info"
-            }
-        },
-        // 2. lookup returns an array, "unwind" it to a single object
-        doc! { "$unwind": "$author_info" },
-        // 3. Project only the fields we want
-        doc! {
-            "$project": {
-                "_id": 1,
-                "title": 1,
-                "content": 1,
-                "created_at": 1,
-                "author_name": "$author_info.username" // Map username to author_name
-            }
-        },
-    ];
-
-    let mut cursor = collection.aggregate(pipeline).await
-        .map_err(|_| AppError::InternalServerError)?;
-
-    let mut results = Vec::new();
-    while let Some(doc) = cursor.try_next().await.map_err(|_| AppError::InternalServerError)? {
-        // Convert BSON document to our Rust struct
-        let post: PostWithAuthor = bson::from_document(doc)
-            .map_err(|_| AppError::InternalServerError)?;
-        results.push(post);
-    }
-
-    Ok(Json(results))
+    // Public listings only show posts that are live
+    let page = list_posts(&state, PostFilter::published(), &query).await?;
+    Ok(Json(page))
 }
 
 
 pub async fn get_post_by_author(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Query(query): Query<PostListQuery>,
 ) -> Result<impl IntoResponse, AppError> {
-    let collection = state.db.collection::<Post>("posts");
-
-    // Convert string ID from JWT back to ObjectId
-    let author_id = ObjectId::parse_str(&auth.user_id)
-        .map_err(|_| AppError::BadRequest)?;
-
-    let pipeline = vec![
-        doc! { "$match": { "author_id": author_id } },
-        doc! {
-            "$lookup": {
-                "from": "users",
-                "localField": "author_id",
-                "foreignField": "_id",
-                "as": "author_info"
-            }
-        },
-        doc! { "$unwind": "$author_info" },
-        doc! {
-            "$project": {
-                "_id": 1,
-                "title": 1,
-                "content": 1,
-                "created_at": 1,
-                "updated_at": 1,
-                "author_name": "$author_info.username"
-            }
-        },
-    ];
+    // Authors see all of their own posts, optionally narrowed down by status
+    let filter = PostFilter {
+        author_id: Some(auth.user_id),
+        status: query.status,
+        ..PostFilter::default()
+    };
+
+    let page = list_posts(&state, filter, &query).await?;
+    Ok(Json(page))
+}
 
-    let mut cursor = collection.aggregate(pipeline).await
-        .map_err(|_| AppError::InternalServerError)?;
 
-    let mut results = Vec::new();
-    while let Some(doc) = cursor.try_next().await.map_err(|_| AppError::InternalServerError)? {
-        let post: PostWithAuthor = bson::from_document(doc)
-            .map_err(|_| AppError::InternalServerError)?;
-        results.push(post);
+/// Accepts either an author ObjectId or a username
+pub async fn resolve_author(state: &AppState, author: &str) -> Result<Option<ObjectId>, AppError> {
+    if let Ok(oid) = ObjectId::parse_str(author) {
+        return Ok(Some(oid));
     }
 
-    Ok(Json(results))
+    let user = state.users.find_by_username(author).await?;
+    Ok(user.and_then(|u| u.id))
+}
+
+
+/// Runs one page of a post listing: the query string's filters on top of `filter`,
+/// continuing after its cursor
+pub async fn list_posts(
+    state: &AppState,
+    mut filter: PostFilter,
+    query: &PostListQuery,
+) -> Result<Page<PostWithAuthor>, AppError> {
+    let sort = query.sort.unwrap_or_default();
+    let limit = page_size(query.limit);
+
+    // 1. Resolve ?author= (ObjectId or username), unless `filter` already picked the author
+    if filter.author_id.is_none()
+        && let Some(author) = query.author.as_deref()
+    {
+        match resolve_author(state, author).await? {
+            Some(author_id) => filter.author_id = Some(author_id),
+            // Unknown author: nothing can match, skip the query entirely
+            None => return Ok(Page::empty()),
+        }
+    }
+
+    // 2. Tag / category and date range on created_at
+    if let Some(tag) = query.tag.as_deref() { filter.tag = Some(slugify(tag)); }
+    if let Some(category) = query.category.as_deref() { filter.category = Some(slugify(category)); }
+    filter.from = query.from;
+    filter.to = query.to;
+
+    // 3. Continue after the cursor, if any (one extra row tells us whether there is a next page)
+    let after = query.cursor.as_deref().map(|raw| Cursor::decode(raw, sort)).transpose()?;
+    let results = state.posts.list(&filter, sort, after.as_ref(), limit + 1).await?;
+
+    Ok(Page::from_overfetch(results, limit, |post| Cursor {
+        sort,
+        key: match sort {
+            SortOrder::Updated => post.updated_at,
+            _ => post.created_at,
+        },
+        id: post.id,
+    }))
 }
 
 
@@ -137,64 +164,78 @@ pub async fn update_post(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
     Path(post_id): Path<String>,
-    Json(payload): Json<UpdatePostRequest>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<UpdatePostRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    let collection = state.db.collection::<Post>("posts");
-    
+    let expected_version = parse_if_match(&headers)?;
+
     // 1. Validate ID
-    let obj_id = ObjectId::parse_str(&post_id).map_err(|e| {
-        eprintln!("ID Parsing Error: {}", e);
-        AppError::BadRequest
-    })?;
-
-    // 2. Fetch & Ownership Check
-    // We only select the author_id to save bandwidth
-    let post = collection
-        .find_one(doc! { "_id": obj_id })
-        .await
-        .map_err(|e| {
-            eprintln!("Find Error: {:?}", e);
-            AppError::InternalServerError
-        })?
-        .ok_or(AppError::NotFound)?;
-
-    if post.author_id.to_hex() != auth.user_id && auth.role != "admin" {
+    let obj_id = ObjectId::parse_str(&post_id).map_err(|_| AppError::invalid_id("post", &post_id))?;
+
+    // 2. Fetch & Ownership Check (posts in the trash must be restored first)
+    let post = state.posts
+        .find(obj_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("post", obj_id))?;
+
+    if !auth.can_modify(&post.author_id) {
         return Err(AppError::Forbidden);
     }
 
-    // 3. Build Update Document Idiomatically
-    let mut update_fields = doc! {};
-    if let Some(t) = payload.title { update_fields.insert("title", t); }
-    if let Some(c) = payload.content { update_fields.insert("content", c); }
+    // Fail fast when the editor started from an older version
+    if expected_version.is_some_and(|v| v != post.version) {
+        return Err(AppError::PreconditionFailed { current_version: post.version });
+    }
+
+    // 3. Collect the changes
+    let mut update = PostUpdate::default();
+    if let Some(t) = payload.title {
+        apply_title_change(&state, &post, &t, &mut update).await?;
+    }
+    if let Some(c) = payload.content {
+        update.set_content(c);
+    }
+    if let Some(tags) = payload.tags { update.tags = Some(normalize_tags(&tags)?); }
+    if let Some(category) = payload.category {
+        update.category = Some(normalize_category(&category));
+    }
+    if let Some(cover_id) = payload.cover_image_id {
+        let cover = match cover_id.as_str() {
+            "" => None,
+            id => Some(find_image(&state, id, &auth).await?),
+        };
+        update.cover_image = Some(cover);
+    }
     
     // If nothing was provided to update, just return early
-    if update_fields.is_empty() {
+    if update.is_empty() {
         return Ok(StatusCode::OK.into_response());
     }
 
-    update_fields.insert("updated_at", Utc::now());
-
     // 4. Perform Update and Handle Result
-    let update_result = collection
-        .update_one(doc! { "_id": obj_id }, doc! { "$set": update_fields })
-        .await
-        .map_err(|e| {
-            // THIS IS THE LINE THAT WILL TELL YOU WHY IT 500s
-            eprintln!("CRITICAL: MongoDB UpdateOne Failed: {:?}", e);
-            AppError::InternalServerError
-        })?;
-
-    if update_result.matched_count == 0 {
-        return Err(AppError::NotFound);
-    }
+    // Conditioned on the version we checked, so a concurrent save cannot be overwritten;
+    // a slug taken by a concurrent write is a 409
+    let updated = state.posts.update(obj_id, Some(post.version), update).await?;
+
+    let Some(updated) = updated else {
+        return Err(version_conflict(&state, obj_id).await);
+    };
+
+    // 5. Keep the previous text: history gets the new state as its next revision
+    ensure_initial_revision(&state, &post).await?;
+    record_revision(&state, &updated, auth.user_id, None).await?;
+    let cover = updated.cover_image.as_ref().map(|c| c.media_id);
+    sync_media_references(&state, obj_id, &updated.content, cover).await?;
 
-    // 5. Explicit JSON Response
+    // 6. Explicit JSON Response
     // Returning a JSON body is safer than a bare StatusCode for many clients
     Ok((
         StatusCode::OK,
+        [(header::ETAG, etag(updated.version))],
         Json(serde_json::json!({
             "status": "success",
-            "message": "Post updated successfully"
+            "message": "Post updated successfully",
+            "version": updated.version
         })),
     ).into_response())
 }
@@ -203,84 +244,316 @@ pub async fn update_post(
 pub async fn get_post_by_id(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>, // Ensure Path is imported from axum::extract
+    auth: Option<AuthUser>, // Anonymous readers only get published posts
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
-    println!("DEBUG: Searching for ID: {}", id);
-    let collection = state.db.collection::<Post>("posts");
-    
     // 1. Convert the URL string to an ObjectId
-    let obj_id = ObjectId::parse_str(&id).map_err(|_| {
-        eprintln!("Invalid ID format: {}", id);
-        AppError::BadRequest
-    })?;
-
-    // 2. Use a simpler pipeline for debugging
-    let pipeline = vec![
-        doc! { "$match": { "_id": obj_id } },
-        doc! {
-            "$lookup": {
-                "from": "users",
-                "localField": "author_id",
-                "foreignField": "_id",
-                "as": "author_info"
-            }
-        },
-        doc! { "$unwind": "$author_info" },
-        doc! {
-            "$project": {
-                "_id": 1,
-                "title": 1,
-                "content": 1,
-                "created_at": 1,
-                "author_name": "$author_info.username"
-            }
-        },
-    ];
-
-    let mut cursor = collection.aggregate(pipeline).await
-        .map_err(|_| AppError::InternalServerError)?;
-
-    // 3. Try to get the first result
-    if let Some(doc) = cursor.try_next().await.map_err(|_| AppError::InternalServerError)? {
-        let post: PostWithAuthor = bson::from_document(doc).map_err(|e| {
-            println!("Mapping error: {:?}", e);
-            AppError::InternalServerError
-        })?;
-        return Ok(Json(post));
+    let obj_id = ObjectId::parse_str(&id).map_err(|_| AppError::invalid_id("post", &id))?;
+
+    // 2. Fetch it, respecting draft visibility
+    let post = find_visible_post(&state, PostKey::Id(obj_id), auth.as_ref()).await?;
+    versioned_response(post, &headers)
+}
+
+
+/// GET /posts/by-slug/:slug — old slugs answer with a redirect to the canonical one
+pub async fn get_post_by_slug(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+    auth: Option<AuthUser>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    // 1. Current slug
+    match find_visible_post(&state, PostKey::Slug(&slug), auth.as_ref()).await {
+        Ok(post) => return versioned_response(post, &headers),
+        Err(AppError::NotFound { .. }) => {}
+        Err(e) => return Err(e),
     }
 
-    // If no document found, return 404
-    Err(AppError::NotFound)
+    // 2. Retired slug: point the client at the canonical URL
+    let post = find_visible_post(&state, PostKey::PreviousSlug(&slug), auth.as_ref()).await?;
+    let location = format!("/posts/by-slug/{}", post.slug);
+
+    Ok((
+        StatusCode::MOVED_PERMANENTLY,
+        [(header::LOCATION, location.clone())],
+        Json(serde_json::json!({
+            "redirect": location,
+            "slug": post.slug
+        })),
+    ).into_response())
+}
+
+
+/// Load one post with its author. Drafts, scheduled and archived posts
+/// are only visible to their author and admins; everyone else gets a 404.
+async fn find_visible_post(
+    state: &AppState,
+    key: PostKey<'_>,
+    auth: Option<&AuthUser>,
+) -> Result<PostWithAuthor, AppError> {
+    // Trashed posts are only reachable via /trash
+    let post = state.posts
+        .find_with_author(key)
+        .await?
+        .ok_or_else(|| AppError::not_found("post", key))?;
+
+    let is_live = post.status == PostStatus::Published
+        && post.publish_at.is_none_or(|at| at <= Utc::now());
+    if !is_live && !auth.is_some_and(|a| a.can_modify(&post.author_id)) {
+        return Err(AppError::not_found("post", post.id));
+    }
+
+    Ok(post)
 }
 
 pub async fn delete_post(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
     Path(id): Path<String>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
-    let collection = state.db.collection::<Post>("posts");
-    let obj_id = ObjectId::parse_str(&id).map_err(|_| AppError::BadRequest)?;
+    let obj_id = ObjectId::parse_str(&id).map_err(|_| AppError::invalid_id("post", &id))?;
+    let expected_version = parse_if_match(&headers)?;
 
     // 1. Find post to check ownership
-    let post = collection.find_one(doc! { "_id": obj_id }).await
-        .map_err(|_| AppError::InternalServerError)?
-        .ok_or(AppError::NotFound)?;
+    let post = state.posts.find(obj_id).await?
+        .ok_or_else(|| AppError::not_found("post", obj_id))?;
 
     // 2. Ownership Guard
-    if post.author_id.to_hex() != auth.user_id && auth.role != "admin" {
+    if !auth.can_modify(&post.author_id) {
         return Err(AppError::Forbidden);
     }
 
-    // 3. Delete
-    collection.delete_one(doc! { "_id": obj_id }).await
-        .map_err(|_| AppError::InternalServerError)?;
+    if expected_version.is_some_and(|v| v != post.version) {
+        return Err(AppError::PreconditionFailed { current_version: post.version });
+    }
+
+    // 3. Move (only the version the client saw) to the trash.
+    // Comments and revisions stay until the purge job removes the post for good.
+    let trashed = state.posts.trash(obj_id, post.version, auth.user_id).await?;
+
+    if !trashed {
+        return Err(version_conflict(&state, obj_id).await);
+    }
 
         Ok((
         StatusCode::OK,
         Json(serde_json::json!({
             "status": "success",
-            "message": "Post deleted successfully"
+            "message": "Post moved to trash"
         })),
     ).into_response())
 }
 
 
+/// Publish now, or schedule when the body carries a future `publish_at`
+pub async fn publish_post(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+    payload: Option<Json<PublishRequest>>,
+) -> Result<impl IntoResponse, AppError> {
+    let post = find_owned_post(&state, &id, &auth).await?;
+    let now = Utc::now();
+
+    let publish_at = payload.and_then(|Json(p)| p.publish_at);
+    let update = match publish_at {
+        Some(at) if at > now => PostUpdate {
+            status: Some(PostStatus::Scheduled),
+            publish_at: Some(Some(at)),
+            ..PostUpdate::default()
+        },
+        _ => PostUpdate {
+            status: Some(PostStatus::Published),
+            publish_at: Some(Some(now)),
+            published_at: Some(now),
+            ..PostUpdate::default()
+        },
+    };
+
+    set_post_state(&state, post, update).await
+}
+
+
+/// Take a post back to draft; it disappears from public listings
+pub async fn unpublish_post(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post = find_owned_post(&state, &id, &auth).await?;
+
+    let update = PostUpdate {
+        status: Some(PostStatus::Draft),
+        publish_at: Some(None),
+        ..PostUpdate::default()
+    };
+
+    set_post_state(&state, post, update).await
+}
+
+
+/// Archive a post: hidden from listings but kept for its author
+pub async fn archive_post(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post = find_owned_post(&state, &id, &auth).await?;
+
+    let update = PostUpdate { status: Some(PostStatus::Archived), ..PostUpdate::default() };
+
+    set_post_state(&state, post, update).await
+}
+
+
+/// Decide the initial status of a new post from the requested status and publish date
+fn resolve_publication(
+    requested: Option<PostStatus>,
+    publish_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Result<(PostStatus, Option<DateTime<Utc>>), AppError> {
+    match (requested, publish_at) {
+        (Some(PostStatus::Draft), at) => Ok((PostStatus::Draft, at)),
+        (Some(PostStatus::Archived), _) => Err(AppError::BadRequest("A new post can't be archived")),
+        (Some(PostStatus::Scheduled), None) => Err(AppError::BadRequest("A scheduled post needs publish_at")),
+        (Some(PostStatus::Scheduled), Some(at)) | (None, Some(at)) if at > now => {
+            Ok((PostStatus::Scheduled, Some(at)))
+        }
+        // Published (explicitly or by default) or a publish date already in the past
+        _ => Ok((PostStatus::Published, Some(now))),
+    }
+}
+
+
+/// Load a post by its string id and apply the author-or-admin ownership rule
+pub async fn find_owned_post(state: &AppState, id: &str, auth: &AuthUser) -> Result<Post, AppError> {
+    let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::invalid_id("post", id))?;
+
+    let post = state.posts
+        .find(obj_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("post", obj_id))?;
+
+    if !auth.can_modify(&post.author_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(post)
+}
+
+
+/// Apply a status transition and return the updated post
+async fn set_post_state(state: &AppState, post: Post, update: PostUpdate) -> Result<Json<Post>, AppError> {
+    let obj_id = post.id.ok_or_else(|| AppError::internal("post without _id"))?;
+
+    let updated = state.posts
+        .update(obj_id, None, update)
+        .await?
+        .ok_or_else(|| AppError::not_found("post", obj_id))?;
+
+    Ok(Json(updated))
+}
+
+
+/// Set a new title; a title that slugs differently also gets a new slug,
+/// while the old one keeps resolving through slug_history
+pub async fn apply_title_change(
+    state: &AppState,
+    post: &Post,
+    title: &str,
+    update: &mut PostUpdate,
+) -> Result<(), AppError> {
+    let new_slug = unique_post_slug(state.posts.as_ref(), title, post.id).await?;
+
+    if new_slug != post.slug {
+        let mut history: Vec<String> = post.slug_history.iter()
+            .filter(|s| **s != new_slug)
+            .cloned()
+            .collect();
+        if !post.slug.is_empty() {
+            history.push(post.slug.clone());
+        }
+        update.slug = Some(new_slug);
+        update.slug_history = Some(history);
+    }
+    update.title = Some(title.to_string());
+    Ok(())
+}
+
+
+/// Strong ETag for a post version, the validator If-Match is checked against
+fn etag(version: i64) -> String {
+    format!("\"{}\"", version)
+}
+
+
+/// ETag for a rendered post: `"<version>-<digest>"`. The body also carries the comment count,
+/// the author's name and the cover image, which change without a version bump, so the tag
+/// covers the serialized bytes rather than the version alone.
+fn representation_etag(version: i64, body: &[u8]) -> String {
+    let digest = format!("{:x}", Sha256::digest(body));
+    format!("\"{}-{}\"", version, &digest[..16])
+}
+
+
+/// Versioned writes require `If-Match` with the ETag of a GET (`"<version>-<digest>"`) or of a
+/// write (`"<version>"`); only the version is compared. `*` matches any current version.
+fn parse_if_match(headers: &HeaderMap) -> Result<Option<i64>, AppError> {
+    let value = headers
+        .get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(AppError::PreconditionRequired)?
+        .trim();
+
+    if value == "*" {
+        return Ok(None);
+    }
+
+    // Weak validators are not allowed for If-Match (RFC 9110 strong comparison)
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .map(|v| v.split_once('-').map_or(v, |(version, _)| version))
+        .and_then(|v| v.parse().ok())
+        .map(Some)
+        .ok_or(AppError::BadRequest("If-Match must be an ETag of the post or *"))
+}
+
+
+/// 304 when `If-None-Match` already names this representation, otherwise the post with its ETag
+fn versioned_response(post: PostWithAuthor, headers: &HeaderMap) -> Result<Response, AppError> {
+    let body = serde_json::to_vec(&post).map_err(AppError::internal)?;
+    let current = representation_etag(post.version, &body);
+
+    // Weak comparison (RFC 9110): the W/ prefix is ignored on both sides
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == current)
+        });
+
+    let etag_value = HeaderValue::from_str(&current).expect("ETag is ASCII");
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag_value)]).into_response());
+    }
+
+    Ok((
+        [(header::ETAG, etag_value), (header::CONTENT_TYPE, HeaderValue::from_static("application/json"))],
+        body,
+    ).into_response())
+}
+
+
+/// A version-conditioned write matched nothing: report the current version, or 404 if it is gone
+async fn version_conflict(state: &AppState, post_id: ObjectId) -> AppError {
+    match state.posts.version_of(post_id).await {
+        Ok(Some(current_version)) => AppError::PreconditionFailed { current_version },
+        Ok(None) => AppError::not_found("post", post_id),
+        Err(e) => e,
+    }
+}
@@ -0,0 +1,160 @@
+use axum::{extract::{Path, Query, State}, Json, response::IntoResponse};
+use std::sync::Arc;
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use similar::{ChangeTag, TextDiff};
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::handlers::media_handler::sync_media_references;
+use crate::handlers::post_handler::{apply_title_change, find_owned_post};
+use crate::models::post::Post;
+use crate::models::revision::{DiffLine, DiffOp, DiffQuery, PostRevision, PostSnapshot, RevisionDiff};
+use crate::repository::PostUpdate;
+use crate::AppState;
+
+/// Attempts at claiming the next revision number when edits race
+const MAX_REVISION_RETRIES: usize = 3;
+
+
+/// GET /posts/:id/revisions — newest first; owner or admin only since drafts live here too
+pub async fn get_revisions(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post = find_owned_post(&state, &id, &auth).await?;
+    let post_id = post.id.ok_or_else(|| AppError::internal("post without _id"))?;
+
+    let revisions = state.posts.revisions(post_id).await?;
+
+    Ok(Json(revisions))
+}
+
+
+/// GET /posts/:id/revisions/diff?from=1&to=2 — line-level diff of title and content
+pub async fn diff_revisions(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let post = find_owned_post(&state, &id, &auth).await?;
+    let post_id = post.id.ok_or_else(|| AppError::internal("post without _id"))?;
+
+    let from = find_revision(&state, post_id, query.from).await?;
+    let to = find_revision(&state, post_id, query.to).await?;
+
+    Ok(Json(RevisionDiff {
+        from: query.from,
+        to: query.to,
+        title: diff_lines(&from.snapshot.title, &to.snapshot.title),
+        content: diff_lines(&from.snapshot.content, &to.snapshot.content),
+    }))
+}
+
+
+/// POST /posts/:id/revisions/:rev/restore — writes the old snapshot back as a new revision
+pub async fn restore_revision(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path((id, rev)): Path<(String, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    // 1. Same ownership check as editing
+    let post = find_owned_post(&state, &id, &auth).await?;
+    let post_id = post.id.ok_or_else(|| AppError::internal("post without _id"))?;
+    let snapshot = find_revision(&state, post_id, rev).await?.snapshot;
+
+    // 2. Put the snapshot back (a different title also means a different slug)
+    let mut update = PostUpdate {
+        tags: Some(snapshot.tags),
+        category: Some(snapshot.category),
+        ..PostUpdate::default()
+    };
+    update.set_content(snapshot.content);
+    if snapshot.title != post.title {
+        apply_title_change(&state, &post, &snapshot.title, &mut update).await?;
+    }
+
+    let restored = state.posts
+        .update(post_id, None, update)
+        .await?
+        .ok_or_else(|| AppError::not_found("post", post_id))?;
+
+    // 3. The restore itself is part of the history
+    let revision = record_revision(&state, &restored, auth.user_id, Some(rev)).await?;
+    let cover = restored.cover_image.as_ref().map(|c| c.media_id);
+    sync_media_references(&state, post_id, &restored.content, cover).await?;
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "message": "Revision restored",
+        "revision": revision
+    })))
+}
+
+
+/// Append the current state of `post` as its next revision
+pub async fn record_revision(
+    state: &AppState,
+    post: &Post,
+    editor_id: ObjectId,
+    restored_from: Option<i64>,
+) -> Result<i64, AppError> {
+    let post_id = post.id.ok_or_else(|| AppError::internal("post without _id"))?;
+    for _ in 0..MAX_REVISION_RETRIES {
+        let last = state.posts.last_revision(post_id).await?.unwrap_or(0);
+
+        let revision = PostRevision {
+            id: None,
+            post_id,
+            revision: last + 1,
+            editor_id,
+            created_at: Utc::now(),
+            snapshot: PostSnapshot::from(post),
+            restored_from,
+        };
+
+        match state.posts.insert_revision(&revision).await {
+            Ok(()) => return Ok(last + 1),
+            // Someone else took this number: read the new last one and retry
+            Err(AppError::Duplicate { .. }) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(AppError::Conflict("The post is being edited concurrently, try again"))
+}
+
+
+/// Posts written before revisions existed get their pre-edit state recorded as revision 1
+pub async fn ensure_initial_revision(state: &AppState, post: &Post) -> Result<(), AppError> {
+    let post_id = post.id.ok_or_else(|| AppError::internal("post without _id"))?;
+
+    if state.posts.last_revision(post_id).await?.is_none() {
+        record_revision(state, post, post.author_id, None).await?;
+    }
+    Ok(())
+}
+
+
+async fn find_revision(state: &AppState, post_id: ObjectId, revision: i64) -> Result<PostRevision, AppError> {
+    state.posts
+        .find_revision(post_id, revision)
+        .await?
+        .ok_or_else(|| AppError::not_found("revision", revision))
+}
+
+
+fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => DiffOp::Equal,
+                ChangeTag::Insert => DiffOp::Insert,
+                ChangeTag::Delete => DiffOp::Delete,
+            },
+            text: change.value().trim_end_matches('\n').to_string(),
+        })
+        .collect()
+}
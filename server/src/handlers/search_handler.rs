@@ -0,0 +1,204 @@
+use axum::{extract::{Query, State}, Json, response::IntoResponse};
+use std::sync::Arc;
+use crate::error::AppError;
+use crate::handlers::post_handler::resolve_author;
+use crate::models::pagination::{decode_offset, page_size, Page};
+use crate::models::search::{SearchHit, SearchQuery};
+use crate::repository::PostFilter;
+use crate::slug::slugify;
+use crate::AppState;
+
+const MAX_QUERY_LEN: usize = 200;
+const MAX_SNIPPETS: usize = 3;
+const SNIPPET_RADIUS: usize = 60; // Bytes of context on each side of a match
+
+
+/// GET /posts/search?q= — relevance-ranked search over published posts
+pub async fn search_posts(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let q = query.q.trim();
+    if q.is_empty() || q.len() > MAX_QUERY_LEN {
+        return Err(AppError::BadRequest("The search query is empty or too long"));
+    }
+
+    let limit = page_size(query.limit);
+    let offset = match query.cursor.as_deref() {
+        Some(raw) => decode_offset(raw)?,
+        None => 0,
+    };
+
+    // 1. Text match (phrases and -negations included) on live posts only
+    let mut filter = PostFilter::published();
+    if let Some(author) = query.author.as_deref() {
+        match resolve_author(&state, author).await? {
+            Some(author_id) => filter.author_id = Some(author_id),
+            None => return Ok(Json(Page::empty())),
+        }
+    }
+    filter.tag = query.tag.as_deref().map(slugify);
+    filter.from = query.from;
+    filter.to = query.to;
+
+    // 2. Rank by relevance, page by offset (scores have no stable cursor key)
+    let found = state.posts.search(q, &filter, offset, limit + 1).await?;
+
+    // 3. Attach highlighted snippets
+    let terms = highlight_terms(q);
+    let hits: Vec<SearchHit> = found
+        .into_iter()
+        .map(|(post, score)| SearchHit {
+            title_highlight: mark(&post.title, &find_matches(&post.title, &terms)),
+            snippets: snippets(&post.content, &terms),
+            score,
+            post,
+        })
+        .collect();
+
+    Ok(Json(Page::from_overfetch_at(hits, limit, offset)))
+}
+
+
+/// Terms worth highlighting: quoted phrases and bare words, minus negated ones
+fn highlight_terms(q: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+
+    // Splitting on quotes leaves phrases at odd positions
+    let parts: Vec<&str> = q.split('"').collect();
+    for (i, part) in parts.iter().enumerate() {
+        if i % 2 == 1 {
+            let negated = parts[i - 1].ends_with('-');
+            if !negated && !part.trim().is_empty() {
+                terms.push(part.trim().to_ascii_lowercase());
+            }
+            continue;
+        }
+
+        for word in part.split_whitespace() {
+            if !word.starts_with('-') {
+                terms.push(word.to_ascii_lowercase());
+            }
+        }
+    }
+
+    // Prefer longer matches when terms overlap
+    terms.sort_by_key(|t| std::cmp::Reverse(t.len()));
+    terms.dedup();
+    terms
+}
+
+
+/// Byte ranges of case-insensitive (ASCII) matches, sorted and merged.
+/// ASCII lowercasing keeps byte offsets identical to the original text.
+fn find_matches(text: &str, terms: &[String]) -> Vec<(usize, usize)> {
+    let hay = text.to_ascii_lowercase();
+    let mut ranges = Vec::new();
+
+    for term in terms {
+        let mut start = 0;
+        while let Some(pos) = hay[start..].find(term.as_str()) {
+            let begin = start + pos;
+            ranges.push((begin, begin + term.len()));
+            start = begin + term.len();
+        }
+    }
+
+    ranges.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (s, e) in ranges {
+        match merged.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    merged
+}
+
+
+/// Up to MAX_SNIPPETS excerpts around matches; the opening of the text if nothing matched
+fn snippets(content: &str, terms: &[String]) -> Vec<String> {
+    let matches = find_matches(content, terms);
+    if matches.is_empty() {
+        let end = ceil_boundary(content, SNIPPET_RADIUS * 2);
+        let suffix = if end < content.len() { "…" } else { "" };
+        return vec![format!("{}{}", escape_html(&content[..end]), suffix)];
+    }
+
+    let mut result = Vec::new();
+    let mut covered_until = 0;
+    for &(s, e) in &matches {
+        if result.len() == MAX_SNIPPETS {
+            break;
+        }
+        if s < covered_until {
+            continue; // Already shown in the previous snippet
+        }
+
+        let start = floor_boundary(content, s.saturating_sub(SNIPPET_RADIUS));
+        let end = ceil_boundary(content, e + SNIPPET_RADIUS);
+        covered_until = end;
+
+        let local: Vec<(usize, usize)> = matches
+            .iter()
+            .filter(|(ms, me)| *ms >= start && *me <= end)
+            .map(|(ms, me)| (ms - start, me - start))
+            .collect();
+
+        let prefix = if start > 0 { "…" } else { "" };
+        let suffix = if end < content.len() { "…" } else { "" };
+        result.push(format!("{}{}{}", prefix, mark(&content[start..end], &local), suffix));
+    }
+    result
+}
+
+
+/// HTML-escape `text`, wrapping the given byte ranges in <mark>
+fn mark(text: &str, ranges: &[(usize, usize)]) -> String {
+    let mut out = String::with_capacity(text.len() + ranges.len() * 13);
+    let mut pos = 0;
+    for &(s, e) in ranges {
+        out.push_str(&escape_html(&text[pos..s]));
+        out.push_str("<mark>");
+        out.push_str(&escape_html(&text[s..e]));
+        out.push_str("</mark>");
+        pos = e;
+    }
+    out.push_str(&escape_html(&text[pos..]));
+    out
+}
+
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+
+fn floor_boundary(text: &str, mut i: usize) -> usize {
+    while !text.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+
+fn ceil_boundary(text: &str, mut i: usize) -> usize {
+    if i >= text.len() {
+        return text.len();
+    }
+    while !text.is_char_boundary(i) {
+        i += 1;
+    }
+    i
+}
@@ -0,0 +1,98 @@
+use axum::{extract::{Path, Query, State}, Json, response::IntoResponse};
+use std::sync::Arc;
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::handlers::post_handler::list_posts;
+use crate::models::pagination::PostListQuery;
+use crate::models::tag::{MergeTagsRequest, RenameTagRequest};
+use crate::repository::PostFilter;
+use crate::slug::slugify;
+use crate::validation::ValidatedJson;
+use crate::AppState;
+
+
+/// GET /tags — every tag in use on published posts, most used first
+pub async fn get_tags(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let tags = state.posts.tag_counts(&PostFilter::published()).await?;
+
+    Ok(Json(tags))
+}
+
+
+/// GET /tags/:slug/posts — same pagination contract and filters as GET /posts
+pub async fn get_posts_by_tag(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+    Query(mut query): Query<PostListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    query.tag = Some(slug);
+
+    let page = list_posts(&state, PostFilter::published(), &query).await?;
+    Ok(Json(page))
+}
+
+
+/// PATCH /tags/:slug — admin only, renames the tag on every post
+pub async fn rename_tag(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(slug): Path<String>,
+    ValidatedJson(payload): ValidatedJson<RenameTagRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    auth.require_admin()?;
+
+    let from = slugify(&slug);
+    let to = slugify(&payload.name);
+    if from.is_empty() || to.is_empty() {
+        return Err(AppError::BadRequest("Tag names must contain letters or digits"));
+    }
+
+    let modified = retag_posts(&state, &from, &to).await?;
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "tag": to,
+        "posts_updated": modified
+    })))
+}
+
+
+/// POST /tags/merge — admin only, folds every source tag into the target
+pub async fn merge_tags(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    ValidatedJson(payload): ValidatedJson<MergeTagsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    auth.require_admin()?;
+
+    let target = slugify(&payload.target);
+    if target.is_empty() {
+        return Err(AppError::BadRequest("Tag names must contain letters or digits"));
+    }
+
+    let mut modified = 0;
+    for source in &payload.sources {
+        let source = slugify(source);
+        if source.is_empty() || source == target {
+            continue;
+        }
+        modified += retag_posts(&state, &source, &target).await?;
+    }
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "tag": target,
+        "posts_updated": modified
+    })))
+}
+
+
+/// Replace tag `from` with `to` on every post, without creating duplicates
+async fn retag_posts(state: &AppState, from: &str, to: &str) -> Result<u64, AppError> {
+    if from == to {
+        return Ok(0);
+    }
+    state.posts.retag(from, to).await
+}
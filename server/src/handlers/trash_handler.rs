@@ -0,0 +1,97 @@
+use axum::{extract::{Path, Query, State}, Json, response::IntoResponse};
+use std::cmp::Reverse;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::models::pagination::{page_size, Cursor, Page, SortOrder};
+use crate::models::trash::{RestoredUser, TrashEntry, TrashQuery, TrashedPost, TrashedUser};
+use crate::models::user::UserResponse;
+use crate::AppState;
+
+
+/// GET /trash — your deleted posts; admins see every deleted post and user.
+/// One listing, latest deletion first, paged by deletion time.
+pub async fn get_trash(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Query(query): Query<TrashQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let retention = state.config.trash.retention();
+    let limit = page_size(query.limit);
+    let after = query.cursor.as_deref().map(|raw| Cursor::decode(raw, SortOrder::Newest)).transpose()?;
+
+    // 1. A page of each, one extra row to tell whether more follow
+    let author_id = if auth.is_admin() { None } else { Some(auth.user_id) };
+    let posts = state.posts.trashed(author_id, after.as_ref(), limit + 1).await?;
+    let users = if auth.is_admin() { state.users.trashed(after.as_ref(), limit + 1).await? } else { Vec::new() };
+
+    // 2. Merged by deletion time (ties broken by id); whatever is left over belongs to later pages
+    let posts = posts.into_iter().map(|p| (position(p.deleted_at, p.id), TrashEntry::Post(TrashedPost::new(p, retention))));
+    let users = users.into_iter().map(|u| (position(u.deleted_at, u.id), TrashEntry::User(TrashedUser::new(u, retention))));
+    let mut items: Vec<_> = posts.chain(users).collect();
+    items.sort_by_key(|(position, _)| Reverse(*position));
+    items.truncate(limit as usize + 1);
+
+    let page = Page::from_overfetch(items, limit, |&((key, id), _)| Cursor { sort: SortOrder::Newest, key, id });
+    Ok(Json(page.map(|(_, entry)| entry)))
+}
+
+/// POST /trash/posts/:id/restore — owner or admin; the post comes back with its old status
+pub async fn restore_post(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let obj_id = ObjectId::parse_str(&id).map_err(|_| AppError::invalid_id("post", &id))?;
+
+    let post = state.posts
+        .find_trashed(obj_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("trashed post", obj_id))?;
+
+    if !auth.can_modify(&post.author_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    let restored = state.posts
+        .restore(obj_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("trashed post", obj_id))?; // Restored or purged in the meantime
+
+    Ok(Json(restored))
+}
+
+/// POST /trash/users/:id/restore — admin only; the user has to log in again.
+/// Posts deleted along with the account come back too.
+pub async fn restore_user(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    auth.require_admin()?;
+
+    let obj_id = ObjectId::parse_str(&id).map_err(|_| AppError::invalid_id("user", &id))?;
+
+    let (user, posts_restored) = state.users
+        .restore(obj_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("trashed user", obj_id))?;
+
+    Ok(Json(RestoredUser {
+        user: UserResponse {
+            id: obj_id.to_hex(),
+            username: user.username,
+            email: user.email,
+            role: user.role.to_string(),
+            avatar: user.avatar,
+        },
+        posts_restored,
+    }))
+}
+
+/// Where an entry sits in the listing
+fn position(deleted_at: Option<DateTime<Utc>>, id: Option<ObjectId>) -> (DateTime<Utc>, ObjectId) {
+    (deleted_at.unwrap_or_default(), id.unwrap_or_default())
+}
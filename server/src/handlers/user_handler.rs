@@ -1,37 +1,43 @@
-use axum::{extract::State, Json, http::StatusCode, response::IntoResponse, extract::Path};
+use axum::{extract::{Path, Query, State}, Json, http::StatusCode, response::IntoResponse};
 use std::sync::Arc;
-use bcrypt::{hash, verify, DEFAULT_COST};
-use crate::models::user::{AuthBody, Claims, LoginRequest, RegisterUserRequest, UpdateProfileRequest, User, UserResponse, UserRole};
+use bcrypt::{hash, verify};
+use crate::handlers::media_handler::find_image;
+use crate::models::user::{
+    AuthBody, DeleteUserQuery, DeleteUserResponse, LoginRequest, PostsPolicy, RegisterUserRequest,
+    UpdateProfileRequest, User, UserResponse, UserRole, GHOST_EMAIL, GHOST_USERNAME,
+};
+use crate::models::session::{RefreshRequest, Session, TokenPair};
+use crate::metrics::METRICS;
+use crate::repository::{Cascade, ProfileUpdate};
+use crate::telemetry::{record_user, redact_email, redact_token};
+use crate::validation::ValidatedJson;
 use crate::AppState;
 use crate::error::AppError;
 use chrono::Utc;
-use jsonwebtoken::{encode, Header, EncodingKey};
-use mongodb::bson::doc;
-use validator::Validate;
-use crate::middleware::AuthUser;
+use crate::auth::AuthUser;
 use bson::oid::ObjectId;
-use futures::stream::StreamExt;
+use crate::models::pagination::{page_size, Cursor, Page, PageQuery};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
 
 
 pub async fn register_user(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<RegisterUserRequest>,
+    ValidatedJson(payload): ValidatedJson<RegisterUserRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    let collection = state.db.collection::<User>("users");
+    // The ghost account's identity is reserved
+    if is_reserved(Some(&payload.username), Some(&payload.email)) {
+        return Err(AppError::Conflict("This username or email is reserved"));
+    }
 
     // 1. Check if email is already taken
-    let count = collection
-        .count_documents(doc! { "email": &payload.email })
-        .await
-        .map_err(|_| AppError::InternalServerError)?;
-
-    if count > 0 {
-        return Err(AppError::BadRequest);
+    if state.users.email_registered(&payload.email).await? {
+        return Err(AppError::BadRequest("Email is already registered"));
     }
 
     // 2. Hash password
-    let hashed_password = hash(payload.password, DEFAULT_COST)
-        .map_err(|_| AppError::InternalServerError)?;
+    let hashed_password = hash(payload.password, state.config.auth.bcrypt_cost).map_err(|e| AppError::internal(e))?;
 
     // 3. Create the User instance with default Role
     let new_user = User {
@@ -40,55 +46,26 @@ pub async fn register_user(
         email: payload.email.clone(),
         password: hashed_password,
         role: UserRole::User, // Hardcoded safety
+        avatar: None,
+        deleted_at: None,
+        deleted_by: None,
     };
 
-// 4. Insert with Duplicate Key detection (The safety net)
-    let result = collection.insert_one(new_user).await.map_err(|e| {
-        if let mongodb::error::ErrorKind::Write(
-            mongodb::error::WriteFailure::WriteError(we),
-        ) = *e.kind
-        {
-            if we.code == 11000 {
-                return AppError::Conflict; // Catch race conditions
-            }
-        }
-        AppError::InternalServerError
-    })?;
-
-    let new_id = result
-        .inserted_id
-        .as_object_id()
-        .ok_or(AppError::InternalServerError)?;
-
-    // 5. Create JWT
-    let expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
-        .expect("valid timestamp")
-        .timestamp() as usize;
-
-    let claims = Claims {
-        sub: new_id.to_hex(),
-        // Ensure UserRole has a to_string() or use format!("{:?}", role)
-        role: format!("{:?}", UserRole::User), 
-        exp: expiration,
-    };
+    // 4. Insert; a race on username or email is a 409
+    let new_id = state.users.insert(&new_user).await?;
 
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret".into());
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    ).map_err(|_| AppError::InternalServerError)?;
+    // 5. Open a session and issue the token pair
+    let tokens = start_session(&state, new_id, UserRole::User).await?;
 
-    // 6. Return both Token and User Object
+    // 6. Return both Tokens and User Object
     Ok((StatusCode::CREATED, Json(AuthBody {
-        access_token: token,
-        token_type: "Bearer".to_string(),
+        tokens,
         user: UserResponse {
             id: new_id.to_hex(),
             username: payload.username,
             email: payload.email,
             role: UserRole::User.to_string(),
+            avatar: None,
         },
     })))
 }
@@ -97,81 +74,154 @@ pub async fn register_user(
 /// Handler to log in a user and return a JWT
 pub async fn login_user(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<LoginRequest>,
+    ValidatedJson(payload): ValidatedJson<LoginRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    println!("--- Login Attempt: {} ---", payload.email);
-    let collection = state.db.collection::<User>("users");
+    tracing::debug!(email = %redact_email(&payload.email), "login attempt");
+    // Throttle guessing before spending time on bcrypt
+    state.limiter.check_login(&payload.email).await?;
 
     // 1. Find user
-    let user = collection
-        .find_one(doc! { "email": &payload.email })
-        .await
-        .map_err(|e| {
-            eprintln!("DB Error: {:?}", e);
-            AppError::InternalServerError
-        })?
-        .ok_or(AppError::WrongCredentials)?;
+    let Some(user) = state.users.find_by_email(&payload.email).await? else {
+        METRICS.login(false);
+        state.limiter.login_failed(&payload.email).await;
+        return Err(AppError::WrongCredentials);
+    };
 
     // 2. Verify password (Safe check)
-    println!("Verifying password...");
     let is_valid = verify(&payload.password, &user.password).unwrap_or(false);
     if !is_valid {
-        println!("Invalid password for {}", payload.email);
+        tracing::info!(email = %redact_email(&payload.email), "login rejected: wrong password");
+        METRICS.login(false);
+        if let Some(until) = state.limiter.login_failed(&payload.email).await {
+            tracing::warn!(email = %redact_email(&payload.email), %until, "account locked after failed logins");
+        }
         return Err(AppError::WrongCredentials);
     }
 
-    // 3. Create Claims safely
-    let expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
-        .expect("valid timestamp")
-        .timestamp() as usize;
-
-    // SAFE ID EXTRACTION: No .expect()
-    let user_id = match user.id {
-        Some(oid) => oid.to_hex(),
-        None => {
-            eprintln!("Error: User {} found but has no ObjectId", payload.email);
-            return Err(AppError::InternalServerError);
-        }
-    };
+    // 3. SAFE ID EXTRACTION: No .expect()
+    let user_id = user.id.ok_or_else(|| AppError::internal("user without _id"))?;
 
-    let claims = Claims {
-        sub: user_id,
-        role: user.role.to_string(),
-        exp: expiration,
-    };
+    // 4. Open a session and issue the token pair
+    let tokens = start_session(&state, user_id, user.role.clone()).await?;
 
-    // 4. Encode JWT
-    println!("Encoding JWT...");
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default_secret_key_123".into());
-    
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    ).map_err(|e| {
-        eprintln!("JWT Encoding Error: {:?}", e);
-        AppError::InternalServerError
-    })?;
-
-    println!("Login successful!");
+    record_user(user_id);
+    tracing::info!("login succeeded");
+    METRICS.login(true);
+    state.limiter.login_succeeded(&payload.email).await;
     Ok((StatusCode::OK, Json(AuthBody {
-        access_token: token,
-        token_type: "Bearer".to_string(),
+        tokens,
         user: UserResponse {
-            id: user.id.unwrap().to_hex(),
+            id: user_id.to_hex(),
             username: user.username,
             email: user.email,
             role: user.role.to_string(),
+            avatar: user.avatar,
         },
     })))
 }
 
 
-pub async fn logout_user() -> impl IntoResponse {
-    // For JWT, logout is typically handled client-side by deleting the token.
-    // Optionally, you could implement token blacklisting here.
-    (StatusCode::OK, "Logged out successfully")
+/// Exchange a refresh token for a new token pair.
+/// The presented token is rotated out; presenting it again revokes the whole session.
+pub async fn refresh_token(
+    State(state): State<Arc<AppState>>,
+    ValidatedJson(payload): ValidatedJson<RefreshRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let presented_hash = hash_refresh_token(&payload.refresh_token);
+
+    // 1. Atomically swap the current token for a new one
+    let new_refresh = generate_refresh_token();
+    let rotated = state.users
+        .rotate_refresh_token(&presented_hash, &hash_refresh_token(&new_refresh))
+        .await?;
+
+    let Some(session) = rotated else {
+        // 2. Reuse detection: an already rotated token means it leaked (or was raced)
+        if state.users.revoke_reused_token(&presented_hash).await? {
+            tracing::warn!(
+                token = %redact_token(&payload.refresh_token),
+                "refresh token reuse detected, session revoked"
+            );
+        }
+        return Err(AppError::InvalidToken);
+    };
+
+    // 3. The role may have changed since login, so read it fresh
+    let user = state.users
+        .find(session.user_id)
+        .await?
+        .ok_or(AppError::InvalidToken)?;
+
+    let session_id = session.id.ok_or_else(|| AppError::internal("session without _id"))?;
+    let access_token = state.auth.issue_access_token(session.user_id, user.role, session_id)?;
+
+    Ok(Json(TokenPair {
+        access_token,
+        refresh_token: new_refresh,
+        token_type: "Bearer".to_string(),
+        expires_in: state.auth.access_token_ttl_secs(),
+    }))
+}
+
+
+/// Revoke the session behind the current access token
+pub async fn logout_user(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    state.users.revoke_session(auth.session_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "status": "success",
+            "message": "Logged out successfully"
+        })),
+    ))
+}
+
+
+/// Persist a new session and issue its access and refresh tokens
+async fn start_session(
+    state: &AppState,
+    user_id: ObjectId,
+    role: UserRole,
+) -> Result<TokenPair, AppError> {
+    let now = Utc::now();
+    let refresh_token = generate_refresh_token();
+
+    let session = Session {
+        id: None,
+        user_id,
+        refresh_token_hash: hash_refresh_token(&refresh_token),
+        rotated_token_hashes: Vec::new(),
+        created_at: now,
+        last_used_at: now,
+        expires_at: now + chrono::Duration::days(state.config.auth.refresh_token_ttl_days),
+        revoked_at: None,
+    };
+
+    let session_id = state.users.create_session(&session).await?;
+
+    Ok(TokenPair {
+        access_token: state.auth.issue_access_token(user_id, role, session_id)?,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: state.auth.access_token_ttl_secs(),
+    })
+}
+
+
+/// 256 bits of randomness, URL safe
+fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+
+fn hash_refresh_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
 }
 
 /// Handler to get current user
@@ -179,18 +229,11 @@ pub async fn get_current_user(
     State(state): State<Arc<AppState>>,
     auth: AuthUser, // The extractor does the heavy lifting
 ) -> Result<impl IntoResponse, AppError> {
-    let collection = state.db.collection::<User>("users");
-
-    // Convert the string ID back to ObjectId
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&auth.user_id)
-        .map_err(|_| AppError::NotFound)?;
-
     // Fetch user from DB
-    let user = collection
-        .find_one(doc! { "_id": oid })
-        .await
-        .map_err(|_| AppError::InternalServerError)?
-        .ok_or(AppError::NotFound)?;
+    let user = state.users
+        .find(auth.user_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("user", auth.user_id))?;
 
     // In your handler, convert User -> UserResponse
 let response = UserResponse {
@@ -198,6 +241,7 @@ let response = UserResponse {
     username: user.username,
     email: user.email,
     role: user.role.to_string(),
+    avatar: user.avatar,
 };
 
     Ok(Json(response))
@@ -206,121 +250,123 @@ let response = UserResponse {
 pub async fn update_profile(
     State(state): State<Arc<AppState>>,
     auth: AuthUser, // Your JWT extractor
-    Json(payload): Json<UpdateProfileRequest>,
+    ValidatedJson(payload): ValidatedJson<UpdateProfileRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    let collection = state.db.collection::<User>("users");
-    let obj_id = ObjectId::parse_str(&auth.user_id).map_err(|_| AppError::BadRequest)?;
+    let obj_id = auth.user_id;
+
+    if is_reserved(payload.username.as_deref(), payload.email.as_deref()) {
+        return Err(AppError::Conflict("This username or email is reserved"));
+    }
 
-    // 1. Build the update document
-    let mut update_doc = doc! {};
-    if let Some(u) = payload.username { update_doc.insert("username", u); }
-    if let Some(e) = payload.email { update_doc.insert("email", e); }
+    // 1. Collect the changes
+    let mut update = ProfileUpdate {
+        username: payload.username,
+        email: payload.email,
+        avatar: None,
+    };
+    if let Some(avatar_id) = payload.avatar_id {
+        let avatar = match avatar_id.as_str() {
+            "" => None,
+            id => Some(find_image(&state, id, &auth).await?),
+        };
+        update.avatar = Some(avatar);
+    }
 
-    if update_doc.is_empty() {
-        return Err(AppError::BadRequest);
+    if update.is_empty() {
+        return Err(AppError::BadRequest("Nothing to update"));
     }
 
-    // 2. Perform the update
-    let result = collection
-        .update_one(doc! { "_id": obj_id }, doc! { "$set": update_doc })
-        .await;
-
-    // 3. Handle Duplicate Key Errors (11000)
-    match result {
-        Ok(res) if res.matched_count == 0 => Err(AppError::NotFound),
-        Ok(_) => Ok(StatusCode::OK),
-        Err(e) => {
-            if let mongodb::error::ErrorKind::Write(
-                mongodb::error::WriteFailure::WriteError(we)
-            ) = *e.kind {
-                if we.code == 11000 {
-                    // This triggers if the new username/email is already taken
-                    return Err(AppError::Conflict);
-                }
-            }
-            Err(AppError::InternalServerError)
-        }
+    // 2. Perform the update; a username or email that is already taken is a 409
+    if !state.users.update_profile(obj_id, update).await? {
+        return Err(AppError::not_found("user", obj_id));
     }
+    Ok(StatusCode::OK)
 }
 
-/// Handler for admin to get all users
+/// Handler for admin to get all users (paginated by _id, which encodes creation time)
 pub async fn get_all_users(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Query(query): Query<PageQuery>,
 ) -> Result<impl IntoResponse, AppError> {
     // 1. Guard Clause
-    if auth.role != "admin" {
-        eprintln!("Unauthorized access attempt: User {} with role {}", auth.user_id, auth.role);
+    if !auth.is_admin() {
+        tracing::warn!(role = %auth.role, "non-admin tried to list all users");
         return Err(AppError::Forbidden);
     }
 
-    let collection = state.db.collection::<User>("users");
-
-    // 2. Fetch Cursor
-    let cursor = collection
-        .find(doc! {})
-        .await
-        .map_err(|e| {
-            eprintln!("Database Find Error: {:?}", e);
-            AppError::InternalServerError
-        })?;
-
-    // 3. Clean Refactor: Collect the stream directly into a Vector
-    // This replaces the manual while loop and handles the Result for each item
-    let users: Vec<User> = cursor
-        .collect::<Vec<Result<User, _>>>()
-        .await
-        .into_iter()
-        .collect::<Result<Vec<User>, _>>()
-        .map_err(|e| {
-            eprintln!("Cursor Collection Error: {:?}", e);
-            AppError::InternalServerError
-        })?;
-
-  let safe_users: Vec<UserResponse> = users
-        .into_iter()
-        .map(|u| UserResponse {
-            id: u.id.map(|id| id.to_hex()).unwrap_or_default(),
-            username: u.username,
-            email: u.email,
-            role: u.role.to_string(),
-        })
-        .collect();
+    // Users have no timestamps of their own, so both the order and the cursor use _id
+    let sort = query.sort.unwrap_or_default();
+    let descending = sort.descending();
+    let limit = page_size(query.limit);
+    let after = query.cursor.as_deref().map(|raw| Cursor::decode(raw, sort)).transpose()?.map(|c| c.id);
+
+    // 2. Fetch one extra row, which tells us whether there is a next page
+    let users: Vec<User> = state.users.list(after, descending, limit + 1).await?;
+
+    let page = Page::from_overfetch(users, limit, |u| {
+        let id = u.id.unwrap_or_default();
+        Cursor { sort, key: id.timestamp().to_chrono(), id }
+    });
+
+    let safe_users = page.map(|u| UserResponse {
+        id: u.id.map(|id| id.to_hex()).unwrap_or_default(),
+        username: u.username,
+        email: u.email,
+        role: u.role.to_string(),
+        avatar: u.avatar,
+    });
 
     Ok(Json(safe_users))
 }
 
-/// Handler for admin to delete users
+/// Handler for admin to delete users.
+/// `?posts=delete|reassign:<user_id>|anonymize` (default anonymize) decides what happens
+/// to their posts; the account and the posts change together or not at all.
 pub async fn delete_user(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
     Path(target_id): Path<String>,
+    Query(query): Query<DeleteUserQuery>,
 ) -> Result<impl IntoResponse, AppError> {
     // 1. Admin Guard
-    if auth.role != "admin" {
-        return Err(AppError::Forbidden);
-    }
+    auth.require_admin()?;
 
-    // 2. Prevent self-deletion
-    if auth.user_id == target_id {
-        return Err(AppError::BadRequest); // Or a specific "Cannot delete self" error
-    }
-
-    // 3. Convert string ID to MongoDB ObjectId
+    // 2. Convert string ID to MongoDB ObjectId
     let obj_id = ObjectId::parse_str(&target_id)
-        .map_err(|_| AppError::BadRequest)?;
+        .map_err(|_| AppError::invalid_id("user", &target_id))?;
 
-    let collection = state.db.collection::<User>("users");
+    // 3. Prevent self-deletion
+    if auth.user_id == obj_id {
+        return Err(AppError::BadRequest("You can't delete your own account"));
+    }
 
-    // 4. Execute deletion
-    let result = collection
-        .delete_one(doc! { "_id": obj_id })
-        .await
-        .map_err(|_| AppError::InternalServerError)?;
+    let policy = match query.posts.as_deref() {
+        None => PostsPolicy::Anonymize,
+        Some(raw) => PostsPolicy::parse(raw)
+            .ok_or(AppError::BadRequest("posts must be delete, reassign:<user_id> or anonymize"))?,
+    };
+    if policy == PostsPolicy::Reassign(obj_id) {
+        return Err(AppError::BadRequest("Posts can't be reassigned to the deleted user"));
+    }
 
-    if result.deleted_count == 0 {
-        return Err(AppError::NotFound); // User didn't exist
+    // 4. Trash the account, apply the policy and revoke sessions, all or nothing
+    let outcome = state.users.delete(obj_id, auth.user_id, policy).await?;
+
+    match outcome {
+        Cascade::UserNotFound => Err(AppError::not_found("user", obj_id)),
+        Cascade::Rejected => Err(AppError::BadRequest("This user can't be deleted, or can't receive the posts")),
+        Cascade::Done { posts_affected, new_owner } => Ok(Json(DeleteUserResponse {
+            deleted_user_id: obj_id.to_hex(),
+            posts_policy: policy.name(),
+            posts_reassigned_to: new_owner.map(|id| id.to_hex()),
+            posts_affected,
+        })),
     }
+}
 
-    Ok(StatusCode::NO_CONTENT) // 204 No Content is standard for successful deletes
-}
\ No newline at end of file
+/// Username and email of the ghost user can't be taken by real accounts
+fn is_reserved(username: Option<&str>, email: Option<&str>) -> bool {
+    username.is_some_and(|u| u.eq_ignore_ascii_case(GHOST_USERNAME))
+        || email.is_some_and(|e| e.eq_ignore_ascii_case(GHOST_EMAIL))
+}
@@ -0,0 +1,272 @@
+/*
+ * Image processing for uploads: metadata stripping (done at upload time, on the raw
+ * bytes) and the resized variants plus blurhash (done by the background worker).
+ */
+
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use serde::Deserialize;
+
+/// Blurhash detail: 4x3 components is the usual placeholder size
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+
+/// Side of the image the blurhash is computed from; more pixels only cost time
+const BLURHASH_SAMPLE: u32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VariantFormat {
+    Webp,
+    Jpeg,
+}
+
+impl VariantFormat {
+    pub const ALL: [Self; 2] = [Self::Webp, Self::Jpeg];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Webp => "webp",
+            Self::Jpeg => "jpg",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Webp => "image/webp",
+            Self::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+/// Which variants get generated: a square thumbnail plus one copy per width, as WebP and JPEG
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VariantConfig {
+    pub thumbnail_size: u32,
+    pub widths: Vec<u32>,
+    pub jpeg_quality: u8,
+}
+
+impl Default for VariantConfig {
+    fn default() -> Self {
+        Self { thumbnail_size: 200, widths: vec![320, 640, 1280], jpeg_quality: 82 }
+    }
+}
+
+pub struct EncodedVariant {
+    pub name: String, // "thumb" or "w<width>"
+    pub format: VariantFormat,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: Vec<u8>,
+}
+
+pub struct Processed {
+    pub width: u32, // Of the original, after applying its orientation
+    pub height: u32,
+    pub blurhash: String,
+    pub variants: Vec<EncodedVariant>,
+}
+
+/// EXIF orientation of the image (1 when there is none). Read before the
+/// metadata is stripped, so the variants can still be rotated the right way.
+pub fn exif_orientation(data: &[u8]) -> u8 {
+    ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+        .and_then(|mut decoder| decoder.orientation().ok())
+        .map(Orientation::to_exif)
+        .unwrap_or(1)
+}
+
+/// Remove EXIF (including GPS), XMP and text metadata without re-encoding. A JPEG keeps
+/// a minimal EXIF block with just its orientation, so the original still displays upright.
+/// Returns None when the file is not a well-formed image of the given type.
+pub fn strip_metadata(data: &[u8], content_type: &str) -> Option<Vec<u8>> {
+    match content_type {
+        "image/jpeg" => strip_jpeg(data, exif_orientation(data)),
+        "image/png" => strip_png(data),
+        "image/webp" => strip_webp(data),
+        _ => Some(data.to_vec()), // GIF carries no EXIF
+    }
+}
+
+/// Decode once, then build the blurhash, the square thumbnail and one variant per
+/// configured width (never upscaled), each as WebP and JPEG. CPU bound: run it on a blocking thread.
+pub fn process(data: &[u8], orientation: u8, config: &VariantConfig) -> Result<Processed, String> {
+    let mut img = image::load_from_memory(data).map_err(|e| e.to_string())?;
+    if let Some(orientation) = Orientation::from_exif(orientation) {
+        img.apply_orientation(orientation);
+    }
+    let (width, height) = (img.width(), img.height());
+
+    let sample = img.thumbnail(BLURHASH_SAMPLE, BLURHASH_SAMPLE).to_rgba8();
+    let blurhash = blurhash::encode(
+        BLURHASH_COMPONENTS.0,
+        BLURHASH_COMPONENTS.1,
+        sample.width(),
+        sample.height(),
+        sample.as_raw(),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut sizes = vec![(
+        "thumb".to_string(),
+        img.resize_to_fill(config.thumbnail_size, config.thumbnail_size, FilterType::Lanczos3),
+    )];
+    let mut widths: Vec<u32> = config.widths.iter().copied().filter(|w| *w < width).collect();
+    if widths.len() < config.widths.len() {
+        widths.push(width); // Wider targets than the original all collapse into the original size
+    }
+    for w in widths {
+        sizes.push((format!("w{}", w), img.resize(w, u32::MAX, FilterType::Lanczos3)));
+    }
+
+    let mut variants = Vec::new();
+    for (name, resized) in sizes {
+        for format in VariantFormat::ALL {
+            variants.push(EncodedVariant {
+                name: name.clone(),
+                format,
+                width: resized.width(),
+                height: resized.height(),
+                bytes: encode(&resized, format, config.jpeg_quality)?,
+            });
+        }
+    }
+
+    Ok(Processed { width, height, blurhash, variants })
+}
+
+fn encode(img: &DynamicImage, format: VariantFormat, jpeg_quality: u8) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    let result = match format {
+        // The pure-Rust WebP encoder is lossless only
+        VariantFormat::Webp => {
+            let rgba = img.to_rgba8();
+            WebPEncoder::new_lossless(&mut buf).encode(
+                rgba.as_raw(),
+                rgba.width(),
+                rgba.height(),
+                image::ExtendedColorType::Rgba8,
+            )
+        }
+        VariantFormat::Jpeg => {
+            JpegEncoder::new_with_quality(&mut buf, jpeg_quality).encode_image(&img.to_rgb8())
+        }
+    };
+    result.map(|_| buf).map_err(|e| e.to_string())
+}
+
+/// Drop APP1 (EXIF, XMP) and APP13 (IPTC) segments; everything from the scan on is copied as is.
+/// An orientation other than 1 goes back in as a fresh APP1 after SOI (and JFIF's APP0).
+fn strip_jpeg(data: &[u8], orientation: u8) -> Option<Vec<u8>> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut out = data[..2].to_vec();
+    let mut pos = 2;
+    let mut orientation_segment = (orientation != 1).then(|| orientation_app1(orientation));
+
+    loop {
+        let marker = *data.get(pos + 1)?;
+        if data[pos] != 0xFF {
+            return None;
+        }
+        // Fill bytes may pad the gap between segments
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker != 0xE0 && let Some(segment) = orientation_segment.take() {
+            out.extend_from_slice(&segment);
+        }
+        // Start of scan: the rest is entropy-coded image data
+        if marker == 0xDA {
+            out.extend_from_slice(&data[pos..]);
+            return Some(out);
+        }
+        let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        let end = pos + 2 + len;
+        let segment = data.get(pos..end)?;
+        if marker != 0xE1 && marker != 0xED {
+            out.extend_from_slice(segment);
+        }
+        pos = end;
+    }
+}
+
+/// APP1 segment whose EXIF holds a single tag: Orientation (0x0112)
+fn orientation_app1(orientation: u8) -> Vec<u8> {
+    let tiff: &[u8] = &[
+        b'M', b'M', 0x00, 0x2A, 0x00, 0x00, 0x00, 0x08, // Big endian, first IFD right after the header
+        0x00, 0x01, // One entry
+        0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, orientation, 0x00, 0x00, // SHORT x1, inline
+        0x00, 0x00, 0x00, 0x00, // No next IFD
+    ];
+    let len = (2 + 6 + tiff.len()) as u16; // Length field + "Exif\0\0" + TIFF
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&len.to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(tiff);
+    segment
+}
+
+/// Drop eXIf and the text chunks (which is where XMP lives in PNG)
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !data.starts_with(SIGNATURE) {
+        return None;
+    }
+    let mut out = SIGNATURE.to_vec();
+    let mut pos = SIGNATURE.len();
+
+    while pos < data.len() {
+        let len = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = data.get(pos + 4..pos + 8)?;
+        let end = pos + 12 + len; // length + type + data + crc
+        let chunk = data.get(pos..end)?;
+        if !matches!(kind, b"eXIf" | b"tEXt" | b"iTXt" | b"zTXt") {
+            out.extend_from_slice(chunk);
+        }
+        pos = end;
+    }
+    Some(out)
+}
+
+/// Drop the EXIF and XMP chunks and clear their flags in the VP8X header
+fn strip_webp(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return None;
+    }
+    let mut out = b"RIFF\0\0\0\0WEBP".to_vec();
+    let mut pos = 12;
+
+    while pos < data.len() {
+        let kind = data.get(pos..pos + 4)?;
+        let len = u32::from_le_bytes(data.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+        let end = (pos + 8 + len + (len & 1)).min(data.len()); // Chunks are padded to even sizes
+        let chunk = data.get(pos..end)?;
+        match kind {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                let flags_at = out.len() + 8;
+                out.extend_from_slice(chunk);
+                if let Some(flags) = out.get_mut(flags_at) {
+                    *flags &= !(0x08 | 0x04); // EXIF and XMP present bits
+                }
+            }
+            _ => out.extend_from_slice(chunk),
+        }
+        pos = end;
+    }
+
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(out)
+}
@@ -0,0 +1,312 @@
+/*
+ * Background jobs spawned at startup.
+ */
+
+use chrono::Utc;
+use futures::stream::TryStreamExt;
+use mongodb::bson::{self, doc, oid::ObjectId, Document};
+use mongodb::options::ReturnDocument;
+use mongodb::Database;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::imaging::{self, VariantConfig};
+
+use crate::models::comment::Comment;
+use crate::models::media::{Media, MediaVariant, ProcessingStatus};
+use crate::models::post::{Post, PostStatus};
+use crate::models::revision::PostRevision;
+use crate::models::session::Session;
+use crate::models::user::User;
+use crate::storage::MediaStorage;
+
+/// How often scheduled posts are checked
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the trash is emptied of expired items
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Uploads waiting for the image worker; when full, the periodic sweep picks them up
+pub const IMAGE_QUEUE_SIZE: usize = 256;
+
+/// How often the image worker looks for uploads it missed (full queue, restarts)
+const IMAGE_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// An upload stuck in `processing` this long was abandoned (e.g. by a restart)
+const STALE_PROCESSING_MINUTES: i64 = 10;
+
+/// Periodically flips scheduled posts whose `publish_at` has passed to published
+pub fn spawn_scheduled_publisher(db: Database) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PUBLISH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match publish_due_posts(&db).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!(posts = n, "published scheduled posts"),
+                Err(e) => tracing::warn!(error = %e, "scheduled publishing failed"),
+            }
+        }
+    })
+}
+
+pub async fn publish_due_posts(db: &Database) -> mongodb::error::Result<u64> {
+    let now = Utc::now();
+
+    let result = db.collection::<Post>("posts")
+        .update_many(
+            doc! {
+                "status": PostStatus::Scheduled.as_str(),
+                "publish_at": { "$lte": now },
+                "deleted_at": null,
+            },
+            doc! {
+                "$set": {
+                    "status": PostStatus::Published.as_str(),
+                    "published_at": now,
+                    "updated_at": now,
+                },
+                "$inc": { "version": 1 },
+            },
+        )
+        .await?;
+
+    Ok(result.modified_count)
+}
+
+/// Periodically hard-deletes posts and users that sat in the trash past the retention period
+pub fn spawn_trash_purger(db: Database, retention: chrono::Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match purge_trash(&db, retention).await {
+                Ok((0, 0)) => {}
+                Ok((posts, users)) => tracing::info!(posts, users, "purged expired items from the trash"),
+                Err(e) => tracing::warn!(error = %e, "trash purge failed"),
+            }
+        }
+    })
+}
+
+/// Returns the number of purged posts and users
+pub async fn purge_trash(db: &Database, retention: chrono::Duration) -> mongodb::error::Result<(u64, u64)> {
+    let cutoff = Utc::now() - retention;
+    let expired = doc! { "deleted_at": { "$lte": cutoff } };
+
+    // Posts go together with their discussion and edit history; their media become unreferenced
+    let posts = db.collection::<Post>("posts");
+    let post_ids = matching_ids(&posts, &expired).await?;
+    if !post_ids.is_empty() {
+        db.collection::<Comment>("comments")
+            .delete_many(doc! { "post_id": { "$in": &post_ids } })
+            .await?;
+        db.collection::<PostRevision>("post_revisions")
+            .delete_many(doc! { "post_id": { "$in": &post_ids } })
+            .await?;
+        db.collection::<Media>("media")
+            .update_many(
+                doc! { "references": { "$in": &post_ids } },
+                doc! { "$pull": { "references": { "$in": &post_ids } } },
+            )
+            .await?;
+    }
+    let purged_posts = posts
+        .delete_many(doc! { "_id": { "$in": &post_ids } })
+        .await?
+        .deleted_count;
+
+    // Users lose their (already revoked) sessions
+    let users = db.collection::<User>("users");
+    let user_ids = matching_ids(&users, &expired).await?;
+    if !user_ids.is_empty() {
+        db.collection::<Session>("sessions")
+            .delete_many(doc! { "user_id": { "$in": &user_ids } })
+            .await?;
+    }
+    let purged_users = users
+        .delete_many(doc! { "_id": { "$in": &user_ids } })
+        .await?
+        .deleted_count;
+
+    Ok((purged_posts, purged_users))
+}
+
+async fn matching_ids<T: Send + Sync>(
+    collection: &mongodb::Collection<T>,
+    filter: &mongodb::bson::Document,
+) -> mongodb::error::Result<Vec<ObjectId>> {
+    collection
+        .clone_with_type::<mongodb::bson::Document>()
+        .find(filter.clone())
+        .projection(doc! { "_id": 1 })
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map(|docs| docs.iter().filter_map(|d| d.get_object_id("_id").ok()).collect())
+}
+
+/// Generates the variants and blurhash of uploaded images, one at a time.
+/// New uploads arrive through `queue`; anything missed is found by a periodic sweep.
+pub fn spawn_image_worker(
+    db: Database,
+    storage: Arc<dyn MediaStorage>,
+    config: VariantConfig,
+    mut queue: mpsc::Receiver<ObjectId>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let config = Arc::new(config);
+        let mut ticker = tokio::time::interval(IMAGE_SWEEP_INTERVAL);
+        loop {
+            let ids = tokio::select! {
+                Some(id) = queue.recv() => vec![id],
+                _ = ticker.tick() => {
+                    let media = db.collection::<Media>("media");
+                    match matching_ids(&media, &claimable_media()).await {
+                        Ok(ids) => ids,
+                        Err(e) => {
+                            tracing::warn!(error = %e, "image sweep failed");
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            for id in ids {
+                match process_image(&db, storage.as_ref(), &config, id).await {
+                    Ok(true) => tracing::info!(media_id = %id, "processed image"),
+                    Ok(false) => {} // Already processed, or deleted meanwhile
+                    Err(e) => tracing::warn!(media_id = %id, error = %e, "image processing failed"),
+                }
+            }
+        }
+    })
+}
+
+/// Uploads without variants that nobody is (still) working on
+fn claimable_media() -> Document {
+    let stale = Utc::now() - chrono::Duration::minutes(STALE_PROCESSING_MINUTES);
+    doc! {
+        "$or": [
+            { "processing": { "$in": [null, ProcessingStatus::Pending.as_str()] } },
+            {
+                "processing": ProcessingStatus::Processing.as_str(),
+                "processing_started_at": { "$lt": stale },
+            },
+        ]
+    }
+}
+
+/// Returns false when the upload was not ours to process
+pub async fn process_image(
+    db: &Database,
+    storage: &dyn MediaStorage,
+    config: &Arc<VariantConfig>,
+    id: ObjectId,
+) -> Result<bool, String> {
+    let collection = db.collection::<Media>("media");
+
+    // 1. Claim it, so a second worker (or instance) leaves it alone
+    let mut filter = claimable_media();
+    filter.insert("_id", id);
+    let claimed = collection
+        .find_one_and_update(
+            filter,
+            doc! { "$set": {
+                "processing": ProcessingStatus::Processing.as_str(),
+                "processing_started_at": Utc::now(),
+            } },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(media) = claimed else { return Ok(false) };
+
+    // 2. Generate and store the variants
+    let (processed, variants) = match generate_variants(storage, config, &media).await {
+        Ok(generated) => generated,
+        Err(e) => {
+            let _ = collection
+                .update_one(
+                    doc! { "_id": id },
+                    doc! { "$set": { "processing": ProcessingStatus::Failed.as_str(), "processing_error": &e } },
+                )
+                .await;
+            return Err(e);
+        }
+    };
+    let updated = collection
+        .find_one_and_update(
+            doc! { "_id": id },
+            doc! { "$set": {
+                "processing": ProcessingStatus::Ready.as_str(),
+                "processing_error": null,
+                "width": processed.width as i32,
+                "height": processed.height as i32,
+                "blurhash": &processed.blurhash,
+                "variants": bson::to_bson(&variants).map_err(|e| e.to_string())?,
+            } },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(updated) = updated else { return Ok(false) };
+
+    // 3. Posts and profiles embed the image; give them the variant URLs
+    let image = bson::to_bson(&updated.image_ref()).map_err(|e| e.to_string())?;
+    db.collection::<Post>("posts")
+        .update_many(doc! { "cover_image.media_id": id }, doc! { "$set": { "cover_image": &image } })
+        .await
+        .map_err(|e| e.to_string())?;
+    db.collection::<User>("users")
+        .update_many(doc! { "avatar.media_id": id }, doc! { "$set": { "avatar": &image } })
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(true)
+}
+
+/// Decode the original (on a blocking thread) and upload every variant next to it
+async fn generate_variants(
+    storage: &dyn MediaStorage,
+    config: &Arc<VariantConfig>,
+    media: &Media,
+) -> Result<(imaging::Processed, Vec<MediaVariant>), String> {
+    let original = storage.get(&media.storage_key, None).await.map_err(|e| e.to_string())?;
+
+    let orientation = media.orientation as u8;
+    let config = Arc::clone(config);
+    let processed = tokio::task::spawn_blocking(move || imaging::process(&original, orientation, &config))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let stem = media.storage_key
+        .rsplit_once('.')
+        .map_or(media.storage_key.as_str(), |(stem, _)| stem);
+
+    let mut variants = Vec::with_capacity(processed.variants.len());
+    for variant in &processed.variants {
+        let extension = variant.format.extension();
+        let storage_key = format!("{}_{}.{}", stem, variant.name, extension);
+        storage
+            .put(&storage_key, &variant.bytes, variant.format.content_type())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        variants.push(MediaVariant {
+            name: variant.name.clone(),
+            extension: extension.to_string(),
+            content_type: variant.format.content_type().to_string(),
+            width: variant.width as i32,
+            height: variant.height as i32,
+            size: variant.bytes.len() as i64,
+            sha256: format!("{:x}", Sha256::digest(&variant.bytes)),
+            storage_key,
+        });
+    }
+
+    Ok((processed, variants))
+}
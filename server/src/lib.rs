@@ -0,0 +1,78 @@
+/*
+ * The blog API as a library: the modules, the shared state and the full middleware
+ * stack around the routes. main.rs connects the pieces and serves them; tests can
+ * build the same application on top of the in-memory repositories.
+ */
+
+pub mod config;
+pub mod db;
+pub mod models;
+pub mod handlers;
+pub mod routes;
+pub mod auth;
+pub mod error;
+pub mod jobs;
+pub mod slug;
+pub mod markdown;
+pub mod storage;
+pub mod imaging;
+pub mod validation;
+pub mod telemetry;
+pub mod metrics;
+pub mod rate_limit;
+pub mod repository;
+
+use std::sync::Arc;
+use axum::http::{HeaderValue, Method};
+use axum::Router;
+use mongodb::bson::oid::ObjectId;
+use tokio::sync::{mpsc, watch};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::auth::AuthService;
+use crate::config::Config;
+use crate::db::InitStatus;
+use crate::rate_limit::RateLimiter;
+use crate::repository::{CommentRepository, MediaRepository, PostRepository, UserRepository};
+use crate::storage::MediaStorage;
+
+pub struct AppState {
+    pub config: Config,
+    pub db: mongodb::Database,
+    pub posts: Arc<dyn PostRepository>, // Queries behind the post handlers
+    pub users: Arc<dyn UserRepository>, // Accounts and their sessions
+    pub comments: Arc<dyn CommentRepository>,
+    pub uploads: Arc<dyn MediaRepository>, // What is known about the files in `media`
+    pub db_init: watch::Receiver<InitStatus>, // Progress of index creation and backfills
+    pub auth: AuthService,
+    pub media: Arc<dyn MediaStorage>,
+    pub image_jobs: mpsc::Sender<ObjectId>, // Uploads for the image worker
+    pub limiter: RateLimiter,
+}
+
+/// Every route behind rate limits, CORS, metrics and request logging
+pub fn app(state: Arc<AppState>) -> Router {
+    let cors = CorsLayer::new()
+        .allow_origin(cors_origins(&state.config.server.cors_origins))
+        // Allow specific methods
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+        // Allow headers like Content-Type and Authorization
+        .allow_headers(Any);
+
+    // Use the route factory we just built
+    let app = routes::create_routes(&state.config)
+        .layer(axum::middleware::from_fn_with_state(state.clone(), rate_limit::enforce))
+        .layer(cors)
+        .with_state(state);
+
+    // Request counts and latencies per route, then request ids and one span per request
+    telemetry::instrument(app.layer(axum::middleware::from_fn(metrics::track_requests)))
+}
+
+/// "*" allows any origin; otherwise only the listed ones (validated when the config was loaded)
+fn cors_origins(origins: &[String]) -> AllowOrigin {
+    if origins.iter().any(|o| o == "*") {
+        return AllowOrigin::from(Any);
+    }
+    AllowOrigin::list(origins.iter().filter_map(|o| HeaderValue::from_str(o).ok()))
+}
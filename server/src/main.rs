@@ -4,48 +4,155 @@
  * It is responsible for starting the server and handling requests.
  */
 
-mod db;
-mod models;
-mod handlers;
-mod routes; 
-mod middleware;
-mod error;
-
+use std::future::IntoFuture;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use dotenvy::dotenv;
-use tower_http::cors::{Any, CorsLayer};
-use axum::http::Method;
-
-use crate::db::connect_db;
 
-pub struct AppState {
-    pub db: mongodb::Database,
-}
+use server::auth::AuthService;
+use server::config::Config;
+use server::rate_limit::RateLimiter;
+use server::repository::MongoRepository;
+use server::db::{connect_db, spawn_init_db};
+use server::{jobs, storage, telemetry, AppState};
+use tokio::sync::{mpsc, oneshot};
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
 
-    // 2. Connect to DB (this also runs init_db with indexes)
-    let database = connect_db().await;
+    // 1. Settings from config.toml and the environment; refuse to start on anything invalid
+    //    (printed directly: logging is configured by these very settings)
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    });
+
+    // Leveled, structured logs (JSON by default); see telemetry.rs
+    telemetry::init(&config.log);
+
+    // Key material for JWTs
+    let auth = AuthService::from_config(&config);
+
+    // Where uploaded files go (local disk or an S3-compatible bucket)
+    let media = storage::from_config(&config.media).unwrap_or_else(|e| {
+        tracing::error!(error = %e, "invalid media storage settings");
+        std::process::exit(1);
+    });
+
+    // 2. Connect to DB (retried with backoff), then create indexes in the background;
+    //    /readyz reports not ready until that is done
+    let database = connect_db(&config.database).await.unwrap_or_else(|e| {
+        tracing::error!(error = %e, "could not connect to MongoDB");
+        std::process::exit(1);
+    });
+    let db_init = spawn_init_db(database.clone());
     
-    let shared_state = Arc::new(AppState { db: database });
-
-    let cors = CorsLayer::new()
-        // Allow specific origin (Change this for production!)
-        .allow_origin(Any) 
-        // Allow specific methods
-        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
-        // Allow headers like Content-Type and Authorization
-        .allow_headers(Any);
-
-    // Use the route factory we just built
-    let app = routes::create_routes()
-    .layer(cors)
-        .with_state(shared_state);
-
-    let listener = TcpListener::bind("0.0.0.0:4000").await.unwrap();
-    println!("🚀 Server running on http://localhost:4000");
-    axum::serve(listener, app).await.unwrap();
-}
\ No newline at end of file
+    // 3. Background jobs (each can be switched off in [features])
+    if config.features.scheduled_publishing {
+        jobs::spawn_scheduled_publisher(database.clone());
+    }
+    if config.features.trash_purge {
+        jobs::spawn_trash_purger(database.clone(), config.trash.retention());
+    }
+    let (image_jobs, image_queue) = mpsc::channel(jobs::IMAGE_QUEUE_SIZE);
+    if config.features.image_processing {
+        jobs::spawn_image_worker(database.clone(), media.clone(), config.images.clone(), image_queue);
+    }
+
+    let bind_addr = config.server.bind_addr;
+    let shutdown_timeout = config.server.shutdown_timeout();
+
+    // Login throttling and write limits, in memory or shared through MongoDB
+    let limiter = RateLimiter::from_config(&config.rate_limit, &database);
+
+    // Everything the handlers store goes through the repositories
+    let repository = Arc::new(MongoRepository::new(&database));
+
+    let state = Arc::new(AppState {
+        config,
+        db: database,
+        posts: repository.clone(),
+        users: repository.clone(),
+        comments: repository.clone(),
+        uploads: repository,
+        db_init,
+        auth,
+        media,
+        image_jobs,
+        limiter,
+    });
+
+    // The routes with rate limits, CORS, metrics and request logging, see lib.rs
+    let app = server::app(state);
+
+    let listener = TcpListener::bind(bind_addr).await.unwrap_or_else(|e| {
+        tracing::error!(%bind_addr, error = %e, "could not bind");
+        std::process::exit(1);
+    });
+    tracing::info!(%bind_addr, "server running");
+
+    // 4. On SIGTERM/SIGINT stop accepting connections and let in-flight requests finish,
+    //    but no longer than the drain timeout
+    let (draining, drain_started) = oneshot::channel();
+    // Peer addresses feed the per-IP rate limits
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+        shutdown_signal().await;
+        tracing::info!(timeout = ?shutdown_timeout, "shutting down, draining in-flight requests");
+        let _ = draining.send(());
+    });
+
+    tokio::select! {
+        result = server.into_future() => {
+            if let Err(e) = result {
+                tracing::error!(error = %e, "server error");
+                std::process::exit(1);
+            }
+        }
+        _ = drain_deadline(drain_started, shutdown_timeout) => {
+            tracing::warn!("drain timeout elapsed, dropping remaining connections");
+        }
+    }
+    tracing::info!("server stopped");
+}
+
+/// Resolves on Ctrl+C or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!(error = %e, "could not listen for Ctrl+C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "could not listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Resolves `timeout` after shutdown began; never if it didn't
+async fn drain_deadline(drain_started: oneshot::Receiver<()>, timeout: Duration) {
+    match drain_started.await {
+        Ok(()) => tokio::time::sleep(timeout).await,
+        Err(_) => std::future::pending().await,
+    }
+}
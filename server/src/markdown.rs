@@ -0,0 +1,148 @@
+/*
+ * Markdown rendering for post content.
+ * The source is CommonMark with GFM tables, task lists and strikethrough. The HTML
+ * is sanitized before it is stored, so clients can insert `content_html` as is.
+ */
+
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use mongodb::bson::{self, doc, Document};
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd};
+
+use crate::models::post::TocEntry;
+use crate::slug::slugify;
+
+/// Heading ids get this prefix so user content can't clobber ids used by the page
+pub const HEADING_ID_PREFIX: &str = "section-";
+
+/// Excerpts are cut at a word boundary before this many characters
+const EXCERPT_CHARS: usize = 200;
+
+const WORDS_PER_MINUTE: usize = 200;
+
+static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut builder = ammonia::Builder::default();
+    builder
+        // Task list checkboxes
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["checked", "disabled"])
+        .set_tag_attribute_value("input", "type", "checkbox")
+        // Table column alignment
+        .add_tag_attributes("th", ["style"])
+        .add_tag_attributes("td", ["style"])
+        .filter_style_properties(HashSet::from(["text-align"]))
+        // Anchors for the table of contents
+        .add_generic_attributes(["id"])
+        .id_prefix(Some(HEADING_ID_PREFIX));
+    builder
+});
+
+/// Everything derived from a post's Markdown source
+#[derive(Debug, Clone, Default)]
+pub struct Rendered {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+    pub excerpt: String,
+    pub reading_time_minutes: i64,
+}
+
+impl Rendered {
+    /// The post fields to `$set` alongside a new `content`
+    pub fn to_document(&self) -> Document {
+        doc! {
+            "content_html": &self.html,
+            "toc": bson::to_bson(&self.toc).unwrap_or_default(),
+            "excerpt": &self.excerpt,
+            "reading_time_minutes": self.reading_time_minutes,
+        }
+    }
+}
+
+pub fn render(source: &str) -> Rendered {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH;
+
+    let mut events = Vec::new();
+    let mut toc = Vec::new();
+    let mut ids: HashSet<String> = HashSet::new();
+
+    let mut heading: Option<(usize, String)> = None; // Index of the open heading and its text so far
+    let mut in_code_block = false;
+    let mut prose = String::new(); // Paragraph text, for the excerpt
+    let mut words = 0;
+
+    for event in Parser::new_ext(source, options) {
+        match &event {
+            Event::Start(Tag::Heading { .. }) => heading = Some((events.len(), String::new())),
+            Event::End(TagEnd::Heading(level)) => {
+                if let Some((start, text)) = heading.take() {
+                    let id = unique_id(&text, &mut ids);
+                    if let Event::Start(Tag::Heading { id: slot, .. }) = &mut events[start] {
+                        *slot = Some(id.clone().into());
+                    }
+                    toc.push(TocEntry {
+                        level: *level as i32,
+                        title: text.trim().to_string(),
+                        anchor: format!("{}{}", HEADING_ID_PREFIX, id),
+                    });
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            Event::Text(text) | Event::Code(text) => {
+                words += text.split_whitespace().count();
+                if let Some((_, heading_text)) = heading.as_mut() {
+                    heading_text.push_str(text);
+                } else if !in_code_block {
+                    prose.push_str(text);
+                }
+            }
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(TagEnd::Paragraph | TagEnd::Item | TagEnd::TableCell) => prose.push(' '),
+            _ => {}
+        }
+        events.push(event);
+    }
+
+    let mut raw_html = String::new();
+    html::push_html(&mut raw_html, events.into_iter());
+
+    Rendered {
+        html: SANITIZER.clean(&raw_html).to_string(),
+        toc,
+        excerpt: excerpt(&prose),
+        reading_time_minutes: words.div_ceil(WORDS_PER_MINUTE).max(1) as i64,
+    }
+}
+
+/// Heading id from its text; repeated headings get "-2", "-3", ...
+fn unique_id(text: &str, ids: &mut HashSet<String>) -> String {
+    let base = match slugify(text) {
+        s if s.is_empty() => "section".to_string(),
+        s => s,
+    };
+
+    let mut id = base.clone();
+    let mut n = 2;
+    while !ids.insert(id.clone()) {
+        id = format!("{}-{}", base, n);
+        n += 1;
+    }
+    id
+}
+
+/// Start of the prose, collapsed to single spaces and cut at a word boundary
+fn excerpt(prose: &str) -> String {
+    let text = prose.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= EXCERPT_CHARS {
+        return text;
+    }
+
+    let cut: String = text.chars().take(EXCERPT_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(i) => &cut[..i],
+        None => &cut[..],
+    };
+    format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation()))
+}
@@ -0,0 +1,187 @@
+/*
+ * Prometheus metrics, served at GET /metrics.
+ * One process-wide registry: HTTP traffic is recorded by `track_requests`, errors by
+ * AppError, MongoDB commands and pool connections by the driver's event handlers.
+ */
+
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use mongodb::event::{cmap::CmapEvent, command::CommandEvent, EventHandler};
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::error::AppError;
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    http_in_flight: IntGauge,
+    errors: IntCounterVec,
+    mongodb_commands: HistogramVec,
+    mongodb_connections: IntGauge,
+    mongodb_checked_out: IntGauge,
+    logins: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route template and status"),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric");
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Time to produce a response")
+                .buckets(exponential_buckets(0.001, 2.0, 14).expect("valid buckets")),
+            &["method", "route"],
+        )
+        .expect("valid metric");
+        let http_in_flight = IntGauge::new("http_requests_in_flight", "Requests being handled right now")
+            .expect("valid metric");
+        let errors = IntCounterVec::new(
+            Opts::new("app_errors_total", "Error responses by AppError code"),
+            &["code", "status"],
+        )
+        .expect("valid metric");
+        let mongodb_commands = HistogramVec::new(
+            HistogramOpts::new("mongodb_command_duration_seconds", "MongoDB command round trips")
+                .buckets(exponential_buckets(0.0005, 2.0, 14).expect("valid buckets")),
+            &["command", "outcome"],
+        )
+        .expect("valid metric");
+        let mongodb_connections = IntGauge::new("mongodb_connections", "Open connections in the MongoDB pool")
+            .expect("valid metric");
+        let mongodb_checked_out = IntGauge::new(
+            "mongodb_connections_checked_out",
+            "MongoDB connections currently in use by an operation",
+        )
+        .expect("valid metric");
+        let logins = IntCounterVec::new(
+            Opts::new("logins_total", "Login attempts by outcome"),
+            &["outcome"],
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_duration.clone()),
+            Box::new(http_in_flight.clone()),
+            Box::new(errors.clone()),
+            Box::new(mongodb_commands.clone()),
+            Box::new(mongodb_connections.clone()),
+            Box::new(mongodb_checked_out.clone()),
+            Box::new(logins.clone()),
+        ] {
+            registry.register(collector).expect("metric registered once");
+        }
+
+        Self {
+            registry,
+            http_requests,
+            http_duration,
+            http_in_flight,
+            errors,
+            mongodb_commands,
+            mongodb_connections,
+            mongodb_checked_out,
+            logins,
+        }
+    }
+
+    pub fn error(&self, code: &str, status: u16) {
+        self.errors.with_label_values(&[code, &status.to_string()]).inc();
+    }
+
+    pub fn login(&self, success: bool) {
+        self.logins.with_label_values(&[if success { "success" } else { "failure" }]).inc();
+    }
+
+    /// Everything in the Prometheus text exposition format
+    pub fn render(&self) -> Result<String, AppError> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| AppError::internal(e))?;
+        String::from_utf8(buffer).map_err(|e| AppError::internal(e))
+    }
+}
+
+/// Middleware counting and timing every request under its route template
+/// (`/posts/:id`, not the raw path, so the number of series stays bounded)
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let metrics = &*METRICS;
+    let _in_flight = InFlight::start(&metrics.http_in_flight);
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    metrics.http_duration.with_label_values(&[&method, &route]).observe(started.elapsed().as_secs_f64());
+    metrics.http_requests
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    response
+}
+
+/// Counts a request as in flight until dropped, including when the client goes away mid-request
+struct InFlight<'a>(&'a IntGauge);
+
+impl<'a> InFlight<'a> {
+    fn start(gauge: &'a IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Times every command the driver sends (find, insert, aggregate, ...)
+pub fn command_event_handler() -> EventHandler<CommandEvent> {
+    EventHandler::callback(|event| {
+        let (command, outcome, duration) = match event {
+            CommandEvent::Succeeded(e) => (e.command_name, "success", e.duration),
+            CommandEvent::Failed(e) => (e.command_name, "failure", e.duration),
+            _ => return, // Started, and any kinds added later
+        };
+        METRICS.mongodb_commands
+            .with_label_values(&[&command, outcome])
+            .observe(duration.as_secs_f64());
+    })
+}
+
+/// Tracks how many pool connections are open and how many are in use
+pub fn cmap_event_handler() -> EventHandler<CmapEvent> {
+    EventHandler::callback(|event| {
+        let metrics = &*METRICS;
+        match event {
+            CmapEvent::ConnectionCreated(_) => metrics.mongodb_connections.inc(),
+            CmapEvent::ConnectionClosed(_) => metrics.mongodb_connections.dec(),
+            CmapEvent::ConnectionCheckedOut(_) => metrics.mongodb_checked_out.inc(),
+            CmapEvent::ConnectionCheckedIn(_) => metrics.mongodb_checked_out.dec(),
+            _ => {}
+        }
+    })
+}
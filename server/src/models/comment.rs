@@ -0,0 +1,60 @@
+/*
+ * Reader comments on posts. Replies point at their parent and keep the full
+ * chain of ancestors so a whole thread can be removed with a single query.
+ */
+
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Replies nest at most this deep; a reply to a comment at this depth joins its parent's replies
+pub const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Comment {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub post_id: ObjectId,
+    pub author_id: ObjectId,
+    pub parent_id: Option<ObjectId>, // None for top-level comments
+    #[serde(default)]
+    pub ancestor_ids: Vec<ObjectId>, // Root first, direct parent last; at most MAX_DEPTH
+    pub content: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A comment with its author's name and its replies, as returned by GET /posts/:id/comments
+#[derive(Serialize, Deserialize)]
+pub struct CommentNode {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub post_id: ObjectId,
+    pub author_id: ObjectId,
+    pub parent_id: Option<ObjectId>,
+    pub author_name: String,
+    pub content: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub replies: Vec<CommentNode>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CreateCommentRequest {
+    #[validate(length(min = 1, max = 5000))]
+    pub content: String,
+    pub parent_id: Option<String>, // Reply to this comment
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateCommentRequest {
+    #[validate(length(min = 1, max = 5000))]
+    pub content: String,
+}
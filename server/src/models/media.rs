@@ -0,0 +1,171 @@
+/*
+ * Uploaded files. The bytes live in the configured storage backend,
+ * this document tracks who uploaded them, which posts link to them and
+ * the resized variants the image worker generated.
+ */
+
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::serde_helpers::{chrono_datetime_as_bson_datetime, chrono_datetime_as_bson_datetime_optional};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Media {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub owner_id: ObjectId,
+    pub storage_key: String, // Path inside the storage backend
+    pub content_type: String, // Sniffed from the bytes, not taken from the client
+    pub size: i64,
+    pub sha256: String, // Hex digest, doubles as the ETag
+    #[serde(default)]
+    pub original_name: Option<String>,
+    #[serde(default)]
+    pub references: Vec<ObjectId>, // Posts whose content links to this file
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default = "no_rotation")]
+    pub orientation: i32, // EXIF orientation of the upload, applied to the variants
+    #[serde(default)]
+    pub processing: ProcessingStatus,
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub processing_started_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub processing_error: Option<String>,
+    #[serde(default)]
+    pub width: Option<i32>,
+    #[serde(default)]
+    pub height: Option<i32>,
+    #[serde(default)]
+    pub blurhash: Option<String>,
+    #[serde(default)]
+    pub variants: Vec<MediaVariant>,
+}
+
+fn no_rotation() -> i32 {
+    1
+}
+
+impl Media {
+    pub fn url(&self) -> String {
+        format!("/media/{}", self.id.map(|id| id.to_hex()).unwrap_or_default())
+    }
+
+    /// What posts and profiles embed when they use this file as an image
+    pub fn image_ref(&self) -> ImageRef {
+        ImageRef {
+            media_id: self.id.unwrap_or_default(),
+            url: self.url(),
+            width: self.width,
+            height: self.height,
+            blurhash: self.blurhash.clone(),
+            variants: self.variants.iter().map(|v| VariantRef {
+                url: format!("{}/{}", self.url(), v.file_name()),
+                name: v.name.clone(),
+                content_type: v.content_type.clone(),
+                width: v.width,
+                height: v.height,
+            }).collect(),
+        }
+    }
+}
+
+/// Where the image worker is with a file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessingStatus {
+    #[default]
+    Pending,
+    Processing,
+    Ready,
+    Failed,
+}
+
+impl ProcessingStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Processing => "processing",
+            Self::Ready => "ready",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// A resized copy of an uploaded image
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaVariant {
+    pub name: String, // "thumb" or "w<width>"
+    pub extension: String, // "webp" or "jpg"
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub size: i64,
+    pub sha256: String,
+    pub storage_key: String,
+}
+
+impl MediaVariant {
+    /// Last path segment of the variant URL, e.g. "w640.webp"
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", self.name, self.extension)
+    }
+}
+
+/// An image as embedded in a post (`cover_image`) or a profile (`avatar`).
+/// `variants` is empty until the worker has processed the upload.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ImageRef {
+    pub media_id: ObjectId,
+    pub url: String, // The original upload
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub blurhash: Option<String>,
+    #[serde(default)]
+    pub variants: Vec<VariantRef>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct VariantRef {
+    pub name: String,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub url: String,
+}
+
+#[derive(Serialize)]
+pub struct MediaResponse {
+    pub id: String,
+    pub url: String,
+    pub content_type: String,
+    pub size: i64,
+    pub original_name: Option<String>,
+    pub references: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub processing: ProcessingStatus,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub blurhash: Option<String>,
+    pub variants: Vec<VariantRef>,
+}
+
+impl From<Media> for MediaResponse {
+    fn from(media: Media) -> Self {
+        let image = media.image_ref();
+        Self {
+            id: media.id.map(|id| id.to_hex()).unwrap_or_default(),
+            url: image.url,
+            processing: media.processing,
+            width: image.width,
+            height: image.height,
+            blurhash: image.blurhash,
+            variants: image.variants,
+            content_type: media.content_type,
+            size: media.size,
+            original_name: media.original_name,
+            references: media.references.iter().map(|id| id.to_hex()).collect(),
+            created_at: media.created_at,
+        }
+    }
+}
@@ -1,2 +1,10 @@
 pub mod user;
-pub mod post;
\ No newline at end of file
+pub mod post;
+pub mod pagination;
+pub mod session;
+pub mod search;
+pub mod comment;
+pub mod tag;
+pub mod revision;
+pub mod trash;
+pub mod media;
\ No newline at end of file
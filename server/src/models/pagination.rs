@@ -0,0 +1,201 @@
+/*
+ * Shared pagination contract for list endpoints.
+ * Cursors are opaque to clients: base64url("<sort>:<sort key millis>:<object id>"),
+ * or base64url("o:<offset>") for relevance-ranked results that have no stable key.
+ */
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::models::post::PostStatus;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+const INVALID_CURSOR: &str = "Invalid or expired page cursor";
+const CURSOR_SORT_MISMATCH: &str = "The page cursor belongs to a different sort order";
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Newest,
+    Oldest,
+    Updated,
+}
+
+impl SortOrder {
+    /// The post field the listing is ordered by (ties are broken by `_id`)
+    pub fn field(self) -> &'static str {
+        match self {
+            Self::Newest | Self::Oldest => "created_at",
+            Self::Updated => "updated_at",
+        }
+    }
+
+    pub fn descending(self) -> bool {
+        !matches!(self, Self::Oldest)
+    }
+
+    /// The `sort` query value, also recorded in cursors
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Newest => "newest",
+            Self::Oldest => "oldest",
+            Self::Updated => "updated",
+        }
+    }
+}
+
+/// Query string for GET /posts and GET /posts/me
+#[derive(Debug, Deserialize, Default)]
+pub struct PostListQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    pub sort: Option<SortOrder>,
+    pub author: Option<String>, // Author ObjectId or username
+    pub from: Option<DateTime<Utc>>, // created_at >= from
+    pub to: Option<DateTime<Utc>>,   // created_at <= to
+    pub status: Option<PostStatus>,  // Only honoured on GET /posts/me
+    pub tag: Option<String>,
+    pub category: Option<String>,
+}
+
+/// Query string for list endpoints without extra filters (e.g. admin users)
+#[derive(Debug, Deserialize, Default)]
+pub struct PageQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    pub sort: Option<SortOrder>,
+}
+
+/// Clamp a user supplied limit into 1..=MAX_PAGE_SIZE
+pub fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Position of the last item of a page: the order it was listed in, its sort key and id
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub sort: SortOrder,
+    pub key: DateTime<Utc>,
+    pub id: ObjectId,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let raw = format!("{}:{}:{}", self.sort.name(), self.key.timestamp_millis(), self.id.to_hex());
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    /// A cursor only continues the listing it came from: another `sort` is a 400
+    pub fn decode(value: &str, sort: SortOrder) -> Result<Self, AppError> {
+        let bytes = URL_SAFE_NO_PAD.decode(value).map_err(|_| AppError::BadRequest(INVALID_CURSOR))?;
+        let raw = String::from_utf8(bytes).map_err(|_| AppError::BadRequest(INVALID_CURSOR))?;
+        let mut parts = raw.splitn(3, ':');
+        let (Some(name), Some(millis), Some(id)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(AppError::BadRequest(INVALID_CURSOR));
+        };
+
+        if name != sort.name() {
+            return Err(AppError::BadRequest(CURSOR_SORT_MISMATCH));
+        }
+        let millis: i64 = millis.parse().map_err(|_| AppError::BadRequest(INVALID_CURSOR))?;
+        let key = DateTime::from_timestamp_millis(millis).ok_or(AppError::BadRequest(INVALID_CURSOR))?;
+        let id = ObjectId::parse_str(id).map_err(|_| AppError::BadRequest(INVALID_CURSOR))?;
+
+        Ok(Self { sort, key, id })
+    }
+
+    /// Filter selecting everything strictly after this cursor in the given order
+    pub fn after(&self, field: &str, descending: bool) -> Document {
+        let op = if descending { "$lt" } else { "$gt" };
+        let key = BsonDateTime::from_chrono(self.key);
+
+        doc! {
+            "$or": [
+                { field: { op: key } },
+                { field: key, "_id": { op: self.id } },
+            ]
+        }
+    }
+}
+
+/// Deepest offset an offset cursor may point at; relevance ranking past it is not worth the `$skip`
+pub const MAX_OFFSET: i64 = 10_000;
+
+/// Position in a result set that can only be paged by offset (e.g. search ranking)
+pub fn encode_offset(offset: i64) -> String {
+    URL_SAFE_NO_PAD.encode(format!("o:{}", offset))
+}
+
+pub fn decode_offset(value: &str) -> Result<i64, AppError> {
+    let bytes = URL_SAFE_NO_PAD.decode(value).map_err(|_| AppError::BadRequest(INVALID_CURSOR))?;
+    let raw = String::from_utf8(bytes).map_err(|_| AppError::BadRequest(INVALID_CURSOR))?;
+
+    raw.strip_prefix("o:")
+        .and_then(|n| n.parse::<i64>().ok())
+        .filter(|offset| (0..=MAX_OFFSET).contains(offset))
+        .ok_or(AppError::BadRequest(INVALID_CURSOR))
+}
+
+/// `$sort` stage body for a field with `_id` as tie breaker
+pub fn sort_doc(field: &str, descending: bool) -> Document {
+    let dir = if descending { -1 } else { 1 };
+    doc! { field: dir, "_id": dir }
+}
+
+/// Response envelope shared by every paginated endpoint
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+impl<T> Page<T> {
+    /// Build a page from a query that fetched `limit + 1` rows.
+    /// The extra row only tells us whether another page exists.
+    pub fn from_overfetch(items: Vec<T>, limit: i64, cursor_of: impl Fn(&T) -> Cursor) -> Self {
+        Self::build(items, limit, |last| cursor_of(last).encode())
+    }
+
+    /// Same as `from_overfetch`, for results fetched with `$skip offset`.
+    /// Paging stops at MAX_OFFSET, so no cursor is handed out that would be rejected.
+    pub fn from_overfetch_at(items: Vec<T>, limit: i64, offset: i64) -> Self {
+        let next = offset.saturating_add(limit);
+        let mut page = Self::build(items, limit, |_| encode_offset(next));
+        if next > MAX_OFFSET {
+            page.next_cursor = None;
+            page.has_more = false;
+        }
+        page
+    }
+
+    pub fn empty() -> Self {
+        Self { items: Vec::new(), next_cursor: None, has_more: false }
+    }
+
+    fn build(mut items: Vec<T>, limit: i64, next: impl FnOnce(&T) -> String) -> Self {
+        let has_more = items.len() as i64 > limit;
+        if has_more {
+            items.truncate(limit as usize);
+        }
+
+        let next_cursor = if has_more { items.last().map(next) } else { None };
+
+        Self { items, next_cursor, has_more }
+    }
+
+    /// Convert the items while keeping the cursor state
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+            has_more: self.has_more,
+        }
+    }
+}
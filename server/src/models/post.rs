@@ -8,7 +8,32 @@ use mongodb::bson::oid::ObjectId;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
-use mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use crate::models::media::ImageRef;
+use mongodb::bson::serde_helpers::{chrono_datetime_as_bson_datetime, chrono_datetime_as_bson_datetime_optional};
+
+
+/// Lifecycle of a post. Only `Published` posts whose `publish_at` has passed are public.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    Draft,
+    Scheduled,
+    #[default]
+    Published,
+    Archived,
+}
+
+impl PostStatus {
+    /// Stored representation, for use inside `doc!` filters
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::Scheduled => "scheduled",
+            Self::Published => "published",
+            Self::Archived => "archived",
+        }
+    }
+}
 
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,11 +42,53 @@ pub struct Post {
     pub id: Option<ObjectId>,
     pub author_id: ObjectId, // Linked to User._id
     pub title: String,
-    pub content: String,
+    pub content: String, // Markdown source
+    #[serde(default)]
+    pub content_html: String, // Rendered and sanitized, see markdown::render
+    #[serde(default)]
+    pub toc: Vec<TocEntry>,
+    #[serde(default)]
+    pub excerpt: String,
+    #[serde(default)]
+    pub reading_time_minutes: i64,
     #[serde(with = "chrono_datetime_as_bson_datetime")]
     pub created_at: DateTime<Utc>,
     #[serde(with = "chrono_datetime_as_bson_datetime")]
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub status: PostStatus,
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub publish_at: Option<DateTime<Utc>>, // When the post becomes (or became) public
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub published_at: Option<DateTime<Utc>>, // When it was actually published
+    #[serde(default)]
+    pub slug: String, // Canonical URL slug, unique across posts
+    #[serde(default)]
+    pub slug_history: Vec<String>, // Previous slugs, still resolved to this post
+    #[serde(default)]
+    pub tags: Vec<String>, // Normalized slugs, see models::tag::normalize_tags
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub cover_image: Option<ImageRef>, // Refreshed by the image worker once variants exist
+    #[serde(default = "initial_version")]
+    pub version: i64, // Bumped by every write; exposed as the ETag
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub deleted_at: Option<DateTime<Utc>>, // Set while the post is in the trash
+    #[serde(default)]
+    pub deleted_by: Option<ObjectId>,
+}
+
+fn initial_version() -> i64 {
+    1
+}
+
+/// One heading of a post, linking to its anchor in `content_html`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TocEntry {
+    pub level: i32, // 1 for <h1> ... 6 for <h6>
+    pub title: String,
+    pub anchor: String,
 }
 
 
@@ -29,11 +96,40 @@ pub struct Post {
 pub struct PostWithAuthor {
     #[serde(rename = "_id")]
     pub id: ObjectId,
+    pub author_id: ObjectId,
+    #[serde(default)]
+    pub slug: String,
     pub title: String,
     pub content: String,
+    #[serde(default)]
+    pub content_html: String,
+    #[serde(default)]
+    pub toc: Vec<TocEntry>,
+    #[serde(default)]
+    pub excerpt: String,
+    #[serde(default)]
+    pub reading_time_minutes: i64,
+    #[serde(default)]
+    pub status: PostStatus,
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub publish_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub published_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub cover_image: Option<ImageRef>,
+    #[serde(default = "initial_version")]
+    pub version: i64,
     pub author_name: String, // We'll pull this from the User collection
+    #[serde(default)]
+    pub comment_count: i64,
     #[serde(with = "chrono_datetime_as_bson_datetime")]
     pub created_at: DateTime<Utc>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
 }
 
 
@@ -45,10 +141,29 @@ pub struct CreatePostRequest {
     
     #[validate(length(min = 10))]
     pub content: String,
+
+    pub status: Option<PostStatus>, // Defaults to published (or scheduled if publish_at is in the future)
+    pub publish_at: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub category: Option<String>,
+    pub cover_image_id: Option<String>, // One of your uploads, see POST /media
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct UpdatePostRequest {
+    #[validate(length(min = 5, max = 100))]
     pub title: Option<String>,
+    #[validate(length(min = 10))]
     pub content: Option<String>,
+    pub tags: Option<Vec<String>>, // Replaces the whole list
+    pub category: Option<String>,  // An empty string clears the category
+    pub cover_image_id: Option<String>, // An empty string removes the cover
+}
+
+/// Optional body for POST /posts/:id/publish; a future `publish_at` schedules the post
+#[derive(Deserialize, Default)]
+pub struct PublishRequest {
+    pub publish_at: Option<DateTime<Utc>>,
 }
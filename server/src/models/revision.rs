@@ -0,0 +1,73 @@
+/*
+ * Immutable history of a post's editable content.
+ * Revision 1 is the post as created; every edit or restore appends the next one.
+ */
+
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use serde::{Deserialize, Serialize};
+
+use crate::models::post::Post;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PostSnapshot {
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+impl From<&Post> for PostSnapshot {
+    fn from(post: &Post) -> Self {
+        Self {
+            title: post.title.clone(),
+            content: post.content.clone(),
+            tags: post.tags.clone(),
+            category: post.category.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostRevision {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub post_id: ObjectId,
+    pub revision: i64, // 1-based, unique per post
+    pub editor_id: ObjectId,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    pub snapshot: PostSnapshot,
+    pub restored_from: Option<i64>, // Set when this revision was produced by a restore
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    pub from: i64,
+    pub to: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Serialize)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+#[derive(Serialize)]
+pub struct RevisionDiff {
+    pub from: i64,
+    pub to: i64,
+    pub title: Vec<DiffLine>,
+    pub content: Vec<DiffLine>,
+}
@@ -0,0 +1,30 @@
+/*
+ * Full-text search over posts.
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::post::PostWithAuthor;
+
+/// Query string for GET /posts/search.
+/// `q` uses MongoDB text search syntax: `"exact phrase"` and `-excluded` terms are supported.
+#[derive(Debug, Deserialize, Default)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    pub author: Option<String>, // Author ObjectId or username
+    pub tag: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub post: PostWithAuthor,
+    pub score: f64,                // MongoDB textScore, higher is more relevant
+    pub title_highlight: String,   // HTML-escaped title with <mark> around matches
+    pub snippets: Vec<String>,     // HTML-escaped excerpts of the content with <mark> around matches
+}
@@ -0,0 +1,62 @@
+/*
+ * Tags and categories. Both are stored on the post as slugs.
+ */
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::error::AppError;
+use crate::slug::slugify;
+
+pub const MAX_TAGS: usize = 10;
+pub const MAX_TAG_LEN: usize = 32;
+
+/// The same bound for `#[validate]`, which wants a u64
+const MAX_TAG_NAME_LEN: u64 = MAX_TAG_LEN as u64;
+
+/// Slug, dedupe (keeping the first occurrence) and bound a list of user supplied tags
+pub fn normalize_tags(raw: &[String]) -> Result<Vec<String>, AppError> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in raw {
+        let slug = slugify(tag);
+        if slug.is_empty() || tags.contains(&slug) {
+            continue;
+        }
+        if slug.chars().count() > MAX_TAG_LEN {
+            return Err(AppError::BadRequest("Tag is too long"));
+        }
+        tags.push(slug);
+    }
+
+    if tags.len() > MAX_TAGS {
+        return Err(AppError::BadRequest("Too many tags"));
+    }
+    Ok(tags)
+}
+
+/// An empty category clears it
+pub fn normalize_category(raw: &str) -> Option<String> {
+    Some(slugify(raw)).filter(|c| !c.is_empty())
+}
+
+/// One row of GET /tags
+#[derive(Serialize, Deserialize)]
+pub struct TagCount {
+    #[serde(rename = "_id")]
+    pub slug: String,
+    pub post_count: i64,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RenameTagRequest {
+    #[validate(length(min = 1, max = MAX_TAG_NAME_LEN))]
+    pub name: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct MergeTagsRequest {
+    #[validate(length(min = 1, message = "At least one source tag is required"))]
+    pub sources: Vec<String>,
+    #[validate(length(min = 1, max = MAX_TAG_NAME_LEN))]
+    pub target: String,
+}
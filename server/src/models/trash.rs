@@ -0,0 +1,81 @@
+/*
+ * Soft-deleted posts and users. They stay restorable until the purge job
+ * removes them after the retention period.
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::post::Post;
+use crate::models::user::{User, UserResponse};
+
+/// Query string for GET /trash, which always lists the latest deletions first
+#[derive(Debug, Deserialize, Default)]
+pub struct TrashQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashedPost {
+    pub id: String,
+    pub author_id: String,
+    pub title: String,
+    pub slug: String,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub deleted_by: Option<String>,
+    pub purge_after: Option<DateTime<Utc>>, // Restorable until then
+}
+
+impl TrashedPost {
+    pub fn new(post: Post, retention: chrono::Duration) -> Self {
+        Self {
+            id: post.id.map(|id| id.to_hex()).unwrap_or_default(),
+            author_id: post.author_id.to_hex(),
+            title: post.title,
+            slug: post.slug,
+            deleted_at: post.deleted_at,
+            deleted_by: post.deleted_by.map(|id| id.to_hex()),
+            purge_after: post.deleted_at.map(|at| at + retention),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashedUser {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub deleted_by: Option<String>,
+    pub purge_after: Option<DateTime<Utc>>,
+}
+
+impl TrashedUser {
+    pub fn new(user: User, retention: chrono::Duration) -> Self {
+        Self {
+            id: user.id.map(|id| id.to_hex()).unwrap_or_default(),
+            username: user.username,
+            email: user.email,
+            deleted_at: user.deleted_at,
+            deleted_by: user.deleted_by.map(|id| id.to_hex()),
+            purge_after: user.deleted_at.map(|at| at + retention),
+        }
+    }
+}
+
+/// One item of GET /trash; users are only listed for admins
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TrashEntry {
+    Post(TrashedPost),
+    User(TrashedUser),
+}
+
+/// POST /trash/users/:id/restore: the account, and how many of its posts came back with it
+#[derive(Serialize)]
+pub struct RestoredUser {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    pub posts_restored: u64,
+}
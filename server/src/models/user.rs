@@ -1,7 +1,11 @@
+use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime_optional;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use validator::Validate;
+use crate::models::media::ImageRef;
+use crate::models::session::TokenPair;
 
 // --- Enums ---
 
@@ -37,6 +41,12 @@ pub struct User {
     pub email: String,
     pub password: String,
     pub role: UserRole,
+    #[serde(default)]
+    pub avatar: Option<ImageRef>,
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub deleted_at: Option<DateTime<Utc>>, // Set while the account is in the trash
+    #[serde(default)]
+    pub deleted_by: Option<ObjectId>,
 }
 
 // --- Request/Response DTOs ---
@@ -57,16 +67,18 @@ pub struct RegisterUserRequest {
     pub role: UserRole,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct LoginRequest {
+    #[validate(email(message = "Invalid email format"))]
     pub email: String,
+    #[validate(length(min = 1, message = "Password is required"))]
     pub password: String,
 }
 
 #[derive(Serialize)]
 pub struct AuthBody {
-    pub access_token: String,
-    pub token_type: String,
+    #[serde(flatten)]
+    pub tokens: TokenPair, // access_token, refresh_token, token_type, expires_in
     pub user: UserResponse, // <--- Add this
 }
 
@@ -77,20 +89,61 @@ pub struct UserResponse {
     pub username: String,
     pub email: String,
     pub role: String,
-}
-
-// --- Auth Context ---
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Claims {
-    pub sub: String,    // User ID
-    pub role: String,   // Store as string for easy decoding in middleware
-    pub exp: usize,     // Expiration
+    pub avatar: Option<ImageRef>,
 }
 
 #[derive(Deserialize, Validate)]
 pub struct UpdateProfileRequest {
     #[validate(length(min = 3, message = "Username must be at least 3 characters"))]
     pub username: Option<String>,
+    #[validate(email(message = "Invalid email format"))]
     pub email: Option<String>,
-}
\ No newline at end of file
+    pub avatar_id: Option<String>, // One of your uploads; an empty string removes the avatar
+}
+/// Placeholder author that anonymized posts are handed to
+pub const GHOST_USERNAME: &str = "deleted-user";
+pub const GHOST_EMAIL: &str = "deleted-user@users.invalid";
+
+/// What happens to a user's posts when an admin deletes the account
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostsPolicy {
+    Delete,             // Move the posts to the trash with the account
+    Reassign(ObjectId), // Hand them to another active user
+    Anonymize,          // Keep them, attributed to the ghost user
+}
+
+impl PostsPolicy {
+    /// Parses `delete`, `reassign:<user_id>` or `anonymize`
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "delete" => Some(Self::Delete),
+            "anonymize" => Some(Self::Anonymize),
+            _ => raw
+                .strip_prefix("reassign:")
+                .and_then(|id| ObjectId::parse_str(id).ok())
+                .map(Self::Reassign),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Delete => "delete",
+            Self::Reassign(_) => "reassign",
+            Self::Anonymize => "anonymize",
+        }
+    }
+}
+
+/// Query string for DELETE /users/admin/users/:id
+#[derive(Deserialize)]
+pub struct DeleteUserQuery {
+    pub posts: Option<String>, // Defaults to anonymize
+}
+
+#[derive(Serialize)]
+pub struct DeleteUserResponse {
+    pub deleted_user_id: String,
+    pub posts_policy: &'static str,
+    pub posts_reassigned_to: Option<String>,
+    pub posts_affected: u64,
+}
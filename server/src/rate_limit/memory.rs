@@ -0,0 +1,130 @@
+/*
+ * Rate limit state in process memory. Fast and dependency-free, but every
+ * instance counts on its own; use the MongoDB store behind a load balancer.
+ */
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use super::{Decision, LockoutPolicy, RateLimitStore, Rule, StoreError};
+
+/// Entries are swept once a map grows past this many keys (and then past twice what survived)
+const SWEEP_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    full_at: Instant, // Idle buckets are full again by then and can be dropped
+}
+
+struct Failures {
+    count: u32,
+    locked_until: Option<DateTime<Utc>>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<Keyed<Bucket>>,
+    failures: Mutex<Keyed<Failures>>,
+}
+
+struct Keyed<T> {
+    entries: HashMap<String, T>,
+    sweep_at: usize,
+}
+
+impl<T> Default for Keyed<T> {
+    fn default() -> Self {
+        Self { entries: HashMap::new(), sweep_at: SWEEP_THRESHOLD }
+    }
+}
+
+impl<T> Keyed<T> {
+    /// Drop entries that no longer matter once the map has grown enough
+    fn sweep(&mut self, keep: impl FnMut(&String, &mut T) -> bool) {
+        if self.entries.len() < self.sweep_at {
+            return;
+        }
+        self.entries.retain(keep);
+        self.sweep_at = (self.entries.len() * 2).max(SWEEP_THRESHOLD);
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, rule: Rule) -> Result<Decision, StoreError> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+        buckets.sweep(|_, bucket| bucket.full_at > now);
+
+        let bucket = buckets.entries.entry(key.to_string()).or_insert_with(|| Bucket {
+            tokens: rule.burst as f64,
+            updated: now,
+            full_at: now,
+        });
+
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * rule.per_second();
+        bucket.tokens = (bucket.tokens + refilled).min(rule.burst as f64);
+        bucket.updated = now;
+
+        let decision = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Decision::Allowed
+        } else {
+            Decision::Limited { retry_after: rule.wait_for_token(bucket.tokens) }
+        };
+
+        let missing = rule.burst as f64 - bucket.tokens;
+        bucket.full_at = now + std::time::Duration::from_secs_f64(missing / rule.per_second());
+        Ok(decision)
+    }
+
+    async fn locked_until(&self, key: &str) -> Result<Option<DateTime<Utc>>, StoreError> {
+        let now = Utc::now();
+        let failures = self.failures.lock().expect("rate limit lock poisoned");
+        Ok(failures
+            .entries
+            .get(key)
+            .filter(|f| f.expires_at > now)
+            .and_then(|f| f.locked_until)
+            .filter(|until| *until > now))
+    }
+
+    async fn record_failure(&self, key: &str, policy: &LockoutPolicy) -> Result<Option<DateTime<Utc>>, StoreError> {
+        let now = Utc::now();
+        let mut failures = self.failures.lock().expect("rate limit lock poisoned");
+        failures.sweep(|_, f| f.expires_at > now);
+
+        let entry = failures.entries.entry(key.to_string()).or_insert(Failures {
+            count: 0,
+            locked_until: None,
+            expires_at: now,
+        });
+        if entry.expires_at <= now {
+            entry.count = 0;
+            entry.locked_until = None;
+        }
+
+        entry.count += 1;
+        entry.expires_at = now + policy.reset_after();
+        if let Some(lock) = policy.lock_for(entry.count) {
+            entry.locked_until = Some(now + lock);
+        }
+        Ok(entry.locked_until.filter(|until| *until > now))
+    }
+
+    async fn reset_failures(&self, key: &str) -> Result<(), StoreError> {
+        self.failures.lock().expect("rate limit lock poisoned").entries.remove(key);
+        Ok(())
+    }
+}
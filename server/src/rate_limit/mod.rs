@@ -0,0 +1,282 @@
+/*
+ * Rate limiting: token buckets per client IP, per account and per user, plus
+ * progressive lockout of accounts after repeated failed logins.
+ * Bucket state lives in one of the stores below: process memory for a single
+ * instance, or MongoDB when several instances must share it.
+ */
+
+pub mod memory;
+pub mod mongo;
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use memory::MemoryStore;
+pub use mongo::MongoStore;
+
+use crate::config::{RateLimitBackend, RateLimitConfig};
+use crate::error::AppError;
+use crate::AppState;
+
+/// Routes limited per client IP under `rate_limit.auth` instead of `rate_limit.write`
+const AUTH_ROUTES: [&str; 3] = ["/users/register", "/users/login", "/users/token/refresh"];
+
+/// A token bucket: holds up to `burst` requests and refills at `per_minute`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Rule {
+    pub fn per_second(&self) -> f64 {
+        self.per_minute as f64 / 60.0
+    }
+
+    /// How long an empty bucket takes to refill completely
+    pub fn refill_time(&self) -> Duration {
+        Duration::from_secs_f64(self.burst as f64 / self.per_second())
+    }
+
+    /// Wait until a bucket holding `tokens` has a whole token again
+    pub fn wait_for_token(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64(((1.0 - tokens) / self.per_second()).max(0.0))
+    }
+}
+
+/// Locks an account for `base_secs` once `threshold` logins in a row failed,
+/// doubling with every further failure up to `max_secs`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutPolicy {
+    pub threshold: u32,
+    pub base_secs: u64,
+    pub max_secs: u64,
+    pub reset_after_secs: u64, // Failures are forgotten after this long without a new one
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self { threshold: 5, base_secs: 60, max_secs: 60 * 60, reset_after_secs: 60 * 60 }
+    }
+}
+
+impl LockoutPolicy {
+    /// The lock earned by `failures` consecutive failed logins, if any
+    pub fn lock_for(&self, failures: u32) -> Option<Duration> {
+        let over = failures.checked_sub(self.threshold)?;
+        let secs = self.base_secs.saturating_mul(1u64 << over.min(32)).min(self.max_secs);
+        Some(Duration::from_secs(secs))
+    }
+
+    pub fn reset_after(&self) -> Duration {
+        Duration::from_secs(self.reset_after_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+#[derive(Debug)]
+pub struct StoreError(pub String);
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rate limit store error: {}", self.0)
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<mongodb::error::Error> for StoreError {
+    fn from(e: mongodb::error::Error) -> Self {
+        Self(e.to_string())
+    }
+}
+
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Take one token from the bucket under `key`, which starts out full
+    async fn take(&self, key: &str, rule: Rule) -> Result<Decision, StoreError>;
+
+    /// When the account under `key` is locked, until when
+    async fn locked_until(&self, key: &str) -> Result<Option<DateTime<Utc>>, StoreError>;
+
+    /// Count a failed login; returns the lock expiry once the policy's threshold is reached
+    async fn record_failure(&self, key: &str, policy: &LockoutPolicy) -> Result<Option<DateTime<Utc>>, StoreError>;
+
+    /// Forget the failures after a successful login
+    async fn reset_failures(&self, key: &str) -> Result<(), StoreError>;
+}
+
+/// The limits and the store behind them, kept in AppState
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, store: Arc<dyn RateLimitStore>) -> Self {
+        Self { config, store }
+    }
+
+    /// Process memory, or the `rate_limits` and `login_failures` collections
+    pub fn from_config(config: &RateLimitConfig, db: &mongodb::Database) -> Self {
+        let store: Arc<dyn RateLimitStore> = match config.store {
+            RateLimitBackend::Memory => Arc::new(MemoryStore::new()),
+            RateLimitBackend::Mongodb => Arc::new(MongoStore::new(db)),
+        };
+        Self::new(config.clone(), store)
+    }
+
+    /// Take a token from `client`'s bucket in `bucket`. A store failure lets the
+    /// request through: an outage must not lock everybody out.
+    /// Only the bucket is logged; the client may be an email address.
+    async fn check(&self, bucket: &'static str, client: &str, rule: Rule) -> Result<(), AppError> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        match self.store.take(&format!("{}:{}", bucket, client), rule).await {
+            Ok(Decision::Allowed) => Ok(()),
+            Ok(Decision::Limited { retry_after }) => {
+                tracing::info!(bucket, retry_after = ?retry_after, "rate limited");
+                Err(AppError::TooManyRequests { retry_after: retry_after_secs(retry_after).max(1) })
+            }
+            Err(e) => {
+                tracing::warn!(bucket, error = %e, "rate limit check failed, allowing the request");
+                Ok(())
+            }
+        }
+    }
+
+    /// Before verifying a password: the per-account bucket, then the lockout
+    pub async fn check_login(&self, email: &str) -> Result<(), AppError> {
+        let account = account_key(email);
+        self.check("login", &account, self.config.login).await?;
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        match self.store.locked_until(&account).await {
+            Ok(Some(until)) => Err(AppError::AccountLocked { retry_after: seconds_until(until) }),
+            Ok(None) => Ok(()),
+            Err(e) => {
+                tracing::warn!(error = %e, "lockout check failed, allowing the login");
+                Ok(())
+            }
+        }
+    }
+
+    /// After WrongCredentials; returns the lock it triggered, if any
+    pub async fn login_failed(&self, email: &str) -> Option<DateTime<Utc>> {
+        if !self.config.enabled {
+            return None;
+        }
+        match self.store.record_failure(&account_key(email), &self.config.lockout).await {
+            Ok(locked) => locked,
+            Err(e) => {
+                tracing::warn!(error = %e, "could not record the failed login");
+                None
+            }
+        }
+    }
+
+    pub async fn login_succeeded(&self, email: &str) {
+        if !self.config.enabled {
+            return;
+        }
+        if let Err(e) = self.store.reset_failures(&account_key(email)).await {
+            tracing::warn!(error = %e, "could not reset failed logins");
+        }
+    }
+}
+
+/// Middleware limiting auth endpoints per client IP, and every other write
+/// (POST, PUT, PATCH, DELETE) per signed-in user, or per IP when anonymous
+pub async fn enforce(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let limiter = &state.limiter;
+    if !limiter.config.enabled || is_safe(request.method()) {
+        return next.run(request).await;
+    }
+
+    let route = request.extensions().get::<MatchedPath>().map(MatchedPath::as_str).unwrap_or_default();
+    let ip = client_ip(&request, limiter.config.trust_forwarded_for);
+
+    let checked = if AUTH_ROUTES.contains(&route) {
+        limiter.check("auth", &ip, limiter.config.auth).await
+    } else {
+        let caller = bearer_subject(&state, &request).map_or_else(|| format!("ip:{}", ip), |id| format!("user:{}", id));
+        limiter.check("write", &caller, limiter.config.write).await
+    };
+
+    match checked {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// The peer address, or with `trust_forwarded_for` the address our proxy saw
+/// (the last X-Forwarded-For entry, the only one a client cannot forge)
+fn client_ip(request: &Request, trust_forwarded_for: bool) -> String {
+    if trust_forwarded_for
+        && let Some(ip) = request
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty())
+    {
+        return ip.to_string();
+    }
+
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The user id of a valid access token. Only the signature is checked, not
+/// the session: good enough to pick a bucket, and it costs no database trip.
+fn bearer_subject(state: &AppState, request: &Request) -> Option<String> {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))?;
+    state.auth.verify(token).ok().map(|claims| claims.sub)
+}
+
+/// Emails are matched case-insensitively, so the key is too
+fn account_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Whole seconds, rounded up so a client that waits exactly this long gets through
+fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
+
+fn seconds_until(until: DateTime<Utc>) -> u64 {
+    retry_after_secs((until - Utc::now()).to_std().unwrap_or_default()).max(1)
+}
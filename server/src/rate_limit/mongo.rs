@@ -0,0 +1,131 @@
+/*
+ * Rate limit state in MongoDB, shared by every instance. Each check is one atomic
+ * update (a pipeline computing the refill server-side with $$NOW, so instance clocks
+ * don't matter); TTL indexes from init_db drop idle buckets and old failures.
+ */
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Document};
+use mongodb::options::ReturnDocument;
+use mongodb::{Collection, Database};
+
+use super::{Decision, LockoutPolicy, RateLimitStore, Rule, StoreError};
+use crate::error::is_duplicate_key;
+
+pub struct MongoStore {
+    buckets: Collection<Document>,
+    failures: Collection<Document>,
+}
+
+impl MongoStore {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            buckets: db.collection("rate_limits"),
+            failures: db.collection("login_failures"),
+        }
+    }
+
+    async fn take_once(&self, key: &str, rule: Rule) -> mongodb::error::Result<Document> {
+        let burst = rule.burst as f64;
+        let per_milli = rule.per_second() / 1000.0;
+        let idle_millis = rule.refill_time().as_millis() as i64;
+
+        let update = vec![
+            // Refill for the time since the last request; new buckets start full
+            doc! { "$set": {
+                "tokens": { "$min": [burst, { "$add": [
+                    { "$ifNull": ["$tokens", burst] },
+                    { "$multiply": [{ "$subtract": ["$$NOW", { "$ifNull": ["$updated_at", "$$NOW"] }] }, per_milli] },
+                ] }] },
+                "updated_at": "$$NOW",
+            } },
+            doc! { "$set": { "allowed": { "$gte": ["$tokens", 1.0] } } },
+            doc! { "$set": {
+                "tokens": { "$cond": ["$allowed", { "$subtract": ["$tokens", 1.0] }, "$tokens"] },
+                "expires_at": { "$add": ["$$NOW", idle_millis] },
+            } },
+        ];
+
+        self.buckets
+            .find_one_and_update(doc! { "_id": key }, update)
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await
+            .map(|bucket| bucket.unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MongoStore {
+    async fn take(&self, key: &str, rule: Rule) -> Result<Decision, StoreError> {
+        // Two first requests for the same key can race to insert it; the loser retries as an update
+        let bucket = match self.take_once(key, rule).await {
+            Err(e) if is_duplicate_key(&e) => self.take_once(key, rule).await?,
+            result => result?,
+        };
+
+        if bucket.get_bool("allowed").unwrap_or(true) {
+            return Ok(Decision::Allowed);
+        }
+        let tokens = bucket.get_f64("tokens").unwrap_or(0.0);
+        Ok(Decision::Limited { retry_after: rule.wait_for_token(tokens) })
+    }
+
+    async fn locked_until(&self, key: &str) -> Result<Option<DateTime<Utc>>, StoreError> {
+        let now = Utc::now();
+        let failures = self.failures
+            .find_one(doc! { "_id": key, "locked_until": { "$gt": now } })
+            .await?;
+
+        Ok(failures
+            .and_then(|f| f.get_datetime("locked_until").ok().map(|d| d.to_chrono())))
+    }
+
+    async fn record_failure(&self, key: &str, policy: &LockoutPolicy) -> Result<Option<DateTime<Utc>>, StoreError> {
+        let now = Utc::now();
+        let expires_at = now + policy.reset_after();
+
+        // Failures older than the reset window (not yet removed by the TTL monitor) start over
+        let update = vec![
+            doc! { "$set": {
+                "count": { "$add": [
+                    { "$cond": [{ "$gt": [{ "$ifNull": ["$expires_at", now] }, now] }, { "$ifNull": ["$count", 0] }, 0] },
+                    1,
+                ] },
+                "expires_at": expires_at,
+            } },
+        ];
+
+        let increment = || {
+            self.failures
+                .find_one_and_update(doc! { "_id": key }, update.clone())
+                .upsert(true)
+                .return_document(ReturnDocument::After)
+        };
+        let failures = match increment().await {
+            Err(e) if is_duplicate_key(&e) => increment().await?,
+            result => result?,
+        };
+
+        let count = failures
+            .as_ref()
+            .and_then(|f| f.get_i32("count").ok())
+            .unwrap_or(1)
+            .max(0) as u32;
+
+        let Some(lock) = policy.lock_for(count) else {
+            return Ok(None);
+        };
+        let locked_until = now + lock;
+        self.failures
+            .update_one(doc! { "_id": key }, doc! { "$set": { "locked_until": locked_until } })
+            .await?;
+        Ok(Some(locked_until))
+    }
+
+    async fn reset_failures(&self, key: &str) -> Result<(), StoreError> {
+        self.failures.delete_one(doc! { "_id": key }).await?;
+        Ok(())
+    }
+}
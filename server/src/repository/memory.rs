@@ -0,0 +1,869 @@
+/*
+ * The repositories in process memory, so handlers can be exercised without
+ * a database. Mirrors what the MongoDB implementation guarantees: the unique
+ * indexes (email, username, slug, revision number), version checks and the
+ * all-or-nothing account deletion. Text search is approximated, see `TextQuery`.
+ */
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{oid::ObjectId, DateTime as BsonDateTime};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use super::{
+    Cascade, CommentRepository, MediaRepository, PostFilter, PostKey, PostRepository, PostUpdate, ProfileUpdate,
+    UserRepository,
+};
+use crate::error::AppError;
+use crate::models::comment::{Comment, CommentNode};
+use crate::models::media::Media;
+use crate::models::pagination::{Cursor, SortOrder};
+use crate::models::post::{Post, PostStatus, PostWithAuthor};
+use crate::models::revision::PostRevision;
+use crate::models::session::Session;
+use crate::models::tag::TagCount;
+use crate::models::user::{PostsPolicy, User, UserRole, GHOST_EMAIL, GHOST_USERNAME};
+
+/// Weight of a title match over a content match, as in the `post_text` index
+const TITLE_WEIGHT: f64 = 3.0;
+
+#[derive(Default)]
+pub struct MemoryRepository {
+    data: Mutex<Data>,
+}
+
+/// Keyed by `_id`; ObjectIds grow over time, so the maps iterate in creation order
+#[derive(Default)]
+struct Data {
+    users: BTreeMap<ObjectId, User>,
+    sessions: BTreeMap<ObjectId, Session>,
+    posts: BTreeMap<ObjectId, Post>,
+    revisions: Vec<PostRevision>,
+    comments: BTreeMap<ObjectId, Comment>,
+    media: BTreeMap<ObjectId, Media>,
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn data(&self) -> MutexGuard<'_, Data> {
+        self.data.lock().expect("repository lock poisoned")
+    }
+}
+
+impl Data {
+    fn active_user(&self, id: ObjectId) -> Option<&User> {
+        self.users.get(&id).filter(|u| u.deleted_at.is_none())
+    }
+
+    /// Same shape as the MongoDB author lookup; posts whose author is gone are dropped
+    fn with_author(&self, post: &Post) -> Option<PostWithAuthor> {
+        let author = self.users.get(&post.author_id)?;
+        Some(PostWithAuthor {
+            id: post.id?,
+            author_id: post.author_id,
+            slug: post.slug.clone(),
+            title: post.title.clone(),
+            content: post.content.clone(),
+            content_html: post.content_html.clone(),
+            toc: post.toc.clone(),
+            excerpt: post.excerpt.clone(),
+            reading_time_minutes: post.reading_time_minutes,
+            status: post.status,
+            publish_at: post.publish_at,
+            published_at: post.published_at,
+            tags: post.tags.clone(),
+            category: post.category.clone(),
+            cover_image: post.cover_image.clone(),
+            version: post.version,
+            author_name: author.username.clone(),
+            comment_count: self.comments.values().filter(|c| Some(c.post_id) == post.id).count() as i64,
+            created_at: post.created_at,
+            updated_at: post.updated_at,
+        })
+    }
+
+    fn matching_posts<'a>(&'a self, filter: &'a PostFilter) -> impl Iterator<Item = &'a Post> {
+        let now = Utc::now();
+        self.posts.values().filter(move |post| matches(post, filter, now))
+    }
+
+    fn slug_taken(&self, slug: &str, exclude: Option<ObjectId>) -> bool {
+        self.posts.values().any(|post| {
+            post.id != exclude && (post.slug == slug || post.slug_history.iter().any(|s| s == slug))
+        })
+    }
+
+    /// The ghost account that anonymized posts belong to, created on first use
+    fn ghost_user_id(&mut self) -> ObjectId {
+        if let Some(id) = self.users.values().find(|u| u.username == GHOST_USERNAME).and_then(|u| u.id) {
+            return id;
+        }
+        let id = ObjectId::new();
+        self.users.insert(id, User {
+            id: Some(id),
+            username: GHOST_USERNAME.to_string(),
+            email: GHOST_EMAIL.to_string(),
+            password: "!".to_string(),
+            role: UserRole::User,
+            avatar: None,
+            deleted_at: None,
+            deleted_by: None,
+        });
+        id
+    }
+}
+
+fn matches(post: &Post, filter: &PostFilter, now: DateTime<Utc>) -> bool {
+    let live = post.status == PostStatus::Published && post.publish_at.is_some_and(|at| at <= now);
+
+    post.deleted_at.is_none()
+        && (!filter.published || live)
+        && filter.author_id.is_none_or(|id| id == post.author_id)
+        && filter.status.is_none_or(|status| status == post.status)
+        && filter.tag.as_ref().is_none_or(|tag| post.tags.contains(tag))
+        && filter.category.as_ref().is_none_or(|category| post.category.as_ref() == Some(category))
+        && filter.from.is_none_or(|from| post.created_at >= from)
+        && filter.to.is_none_or(|to| post.created_at <= to)
+}
+
+/// The sort key of a post in a listing, ties broken by `_id`
+fn sort_key(post: &Post, sort: SortOrder) -> (DateTime<Utc>, ObjectId) {
+    let key = match sort {
+        SortOrder::Updated => post.updated_at,
+        _ => post.created_at,
+    };
+    (key, post.id.unwrap_or_default())
+}
+
+fn duplicate(index: &str) -> AppError {
+    AppError::Duplicate { index: index.to_string() }
+}
+
+fn apply(post: &mut Post, update: PostUpdate) {
+    if let Some(title) = update.title { post.title = title; }
+    if let Some(slug) = update.slug { post.slug = slug; }
+    if let Some(history) = update.slug_history { post.slug_history = history; }
+    if let Some(content) = update.content { post.content = content; }
+    if let Some(rendered) = update.rendered {
+        post.content_html = rendered.html;
+        post.toc = rendered.toc;
+        post.excerpt = rendered.excerpt;
+        post.reading_time_minutes = rendered.reading_time_minutes;
+    }
+    if let Some(tags) = update.tags { post.tags = tags; }
+    if let Some(category) = update.category { post.category = category; }
+    if let Some(cover) = update.cover_image { post.cover_image = cover; }
+    if let Some(status) = update.status { post.status = status; }
+    if let Some(at) = update.publish_at { post.publish_at = at; }
+    if let Some(at) = update.published_at { post.published_at = Some(at); }
+    post.updated_at = Utc::now();
+    post.version += 1;
+    truncate_dates(post);
+}
+
+/// BSON dates have millisecond precision, and so do page cursors; keep posts the same
+/// so a cursor taken from a post compares equal to it
+fn truncate_dates(post: &mut Post) {
+    truncate(&mut post.created_at);
+    truncate(&mut post.updated_at);
+    for at in [&mut post.publish_at, &mut post.published_at].into_iter().flatten() {
+        truncate(at);
+    }
+}
+
+fn truncate(at: &mut DateTime<Utc>) {
+    *at = BsonDateTime::from_chrono(*at).to_chrono();
+}
+
+/// The current time at BSON precision, for dates that cursors point at
+fn now() -> DateTime<Utc> {
+    BsonDateTime::now().to_chrono()
+}
+
+/// A search query in MongoDB text search syntax. Unlike the text index there is
+/// no stemming and no stop word list: words match whole, case-insensitively.
+struct TextQuery {
+    words: Vec<String>,
+    phrases: Vec<String>,
+    excluded: Vec<String>, // Negated words and phrases
+}
+
+impl TextQuery {
+    fn parse(q: &str) -> Self {
+        let mut query = Self { words: Vec::new(), phrases: Vec::new(), excluded: Vec::new() };
+
+        // Splitting on quotes leaves phrases at odd positions
+        let parts: Vec<&str> = q.split('"').collect();
+        for (i, part) in parts.iter().enumerate() {
+            if i % 2 == 1 {
+                let phrase = part.trim().to_lowercase();
+                if phrase.is_empty() {
+                    continue;
+                }
+                if parts[i - 1].ends_with('-') { query.excluded.push(phrase) } else { query.phrases.push(phrase) }
+                continue;
+            }
+
+            for word in part.split_whitespace() {
+                match word.strip_prefix('-') {
+                    Some(negated) => query.excluded.extend(words(negated)),
+                    None => query.words.extend(words(word)),
+                }
+            }
+        }
+        query
+    }
+
+    /// Relevance of a post, `None` when it does not match: every phrase has to appear
+    /// and nothing excluded may. Each occurrence counts, title ones TITLE_WEIGHT times.
+    fn score(&self, post: &Post) -> Option<f64> {
+        let title = post.title.to_lowercase();
+        let content = post.content.to_lowercase();
+        let (title_words, content_words) = (words(&title), words(&content));
+
+        let occurrences = |term: &String| {
+            let count = |text: &str, tokens: &[String]| {
+                if term.contains(' ') { text.matches(term.as_str()).count() } else { tokens.iter().filter(|t| *t == term).count() }
+            };
+            TITLE_WEIGHT * count(&title, &title_words) as f64 + count(&content, &content_words) as f64
+        };
+
+        if self.excluded.iter().any(|term| occurrences(term) > 0.0)
+            || self.phrases.iter().any(|phrase| occurrences(phrase) == 0.0)
+        {
+            return None;
+        }
+
+        let score: f64 = self.words.iter().chain(&self.phrases).map(occurrences).sum();
+        Some(score).filter(|score| *score > 0.0)
+    }
+}
+
+/// Lowercase words of `text`, split at anything that is not a letter or digit
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[async_trait]
+impl PostRepository for MemoryRepository {
+    async fn insert(&self, post: &Post) -> Result<ObjectId, AppError> {
+        let mut data = self.data();
+        if data.posts.values().any(|p| p.slug == post.slug) {
+            return Err(duplicate("slug_1"));
+        }
+
+        let id = post.id.unwrap_or_default();
+        let mut stored = Post { id: Some(id), ..post.clone() };
+        truncate_dates(&mut stored);
+        data.posts.insert(id, stored);
+        Ok(id)
+    }
+
+    async fn find(&self, id: ObjectId) -> Result<Option<Post>, AppError> {
+        Ok(self.data().posts.get(&id).filter(|p| p.deleted_at.is_none()).cloned())
+    }
+
+    async fn version_of(&self, id: ObjectId) -> Result<Option<i64>, AppError> {
+        Ok(self.data().posts.get(&id).map(|p| p.version))
+    }
+
+    async fn find_with_author(&self, key: PostKey<'_>) -> Result<Option<PostWithAuthor>, AppError> {
+        let data = self.data();
+        let found = data.posts.values().find(|post| {
+            post.deleted_at.is_none()
+                && match key {
+                    PostKey::Id(id) => post.id == Some(id),
+                    PostKey::Slug(slug) => post.slug == slug,
+                    PostKey::PreviousSlug(slug) => post.slug_history.iter().any(|s| s == slug),
+                }
+        });
+
+        Ok(found.and_then(|post| data.with_author(post)))
+    }
+
+    async fn is_published(&self, id: ObjectId) -> Result<bool, AppError> {
+        let data = self.data();
+        Ok(data.posts.get(&id).is_some_and(|post| matches(post, &PostFilter::published(), Utc::now())))
+    }
+
+    async fn list(
+        &self,
+        filter: &PostFilter,
+        sort: SortOrder,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<PostWithAuthor>, AppError> {
+        let data = self.data();
+        let descending = sort.descending();
+
+        let mut posts: Vec<&Post> = data
+            .matching_posts(filter)
+            .filter(|post| {
+                after.is_none_or(|cursor| {
+                    let position = sort_key(post, sort);
+                    let cursor = (cursor.key, cursor.id);
+                    if descending { position < cursor } else { position > cursor }
+                })
+            })
+            .collect();
+
+        posts.sort_by_key(|post| sort_key(post, sort));
+        if descending {
+            posts.reverse();
+        }
+
+        Ok(posts
+            .into_iter()
+            .filter_map(|post| data.with_author(post))
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn latest(&self, filter: &PostFilter, limit: i64) -> Result<Vec<PostWithAuthor>, AppError> {
+        let data = self.data();
+
+        let mut posts: Vec<&Post> = data.matching_posts(filter).collect();
+        posts.sort_by_key(|post| (post.publish_at, post.id));
+        posts.reverse();
+
+        Ok(posts
+            .into_iter()
+            .filter_map(|post| data.with_author(post))
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn last_change(&self) -> Result<Option<DateTime<Utc>>, AppError> {
+        let now = Utc::now();
+        let data = self.data();
+        let changes = data.posts.values().flat_map(|post| {
+            let live = post.status == PostStatus::Published && post.deleted_at.is_none();
+            let went_live = post.publish_at.filter(|at| live && *at <= now);
+            [Some(post.updated_at), went_live, post.deleted_at]
+        });
+        Ok(changes.flatten().max())
+    }
+
+    async fn search(
+        &self,
+        q: &str,
+        filter: &PostFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<(PostWithAuthor, f64)>, AppError> {
+        let data = self.data();
+        let query = TextQuery::parse(q);
+
+        let mut hits: Vec<(&Post, f64)> = data
+            .matching_posts(filter)
+            .filter_map(|post| query.score(post).map(|score| (post, score)))
+            .collect();
+        hits.sort_by(|(a, a_score), (b, b_score)| b_score.total_cmp(a_score).then(b.id.cmp(&a.id)));
+
+        Ok(hits
+            .into_iter()
+            .filter_map(|(post, score)| data.with_author(post).map(|post| (post, score)))
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn tag_counts(&self, filter: &PostFilter) -> Result<Vec<TagCount>, AppError> {
+        let mut counts: BTreeMap<&str, i64> = BTreeMap::new();
+        let data = self.data();
+        for tag in data.matching_posts(filter).flat_map(|post| &post.tags) {
+            *counts.entry(tag).or_default() += 1;
+        }
+
+        // The map is ordered by slug already; a stable sort keeps that for equal counts
+        let mut tags: Vec<TagCount> = counts
+            .into_iter()
+            .map(|(slug, post_count)| TagCount { slug: slug.to_string(), post_count })
+            .collect();
+        tags.sort_by_key(|tag| Reverse(tag.post_count));
+        Ok(tags)
+    }
+
+    async fn retag(&self, from: &str, to: &str) -> Result<u64, AppError> {
+        let mut modified = 0;
+        for post in self.data().posts.values_mut() {
+            let Some(position) = post.tags.iter().position(|t| t == from) else {
+                continue;
+            };
+            if post.tags.iter().any(|t| t == to) {
+                post.tags.retain(|t| t != from);
+            } else {
+                post.tags[position] = to.to_string();
+            }
+            post.updated_at = now();
+            post.version += 1;
+            modified += 1;
+        }
+        Ok(modified)
+    }
+
+    async fn update(
+        &self,
+        id: ObjectId,
+        expected_version: Option<i64>,
+        update: PostUpdate,
+    ) -> Result<Option<Post>, AppError> {
+        let mut data = self.data();
+
+        if let Some(slug) = &update.slug
+            && data.posts.values().any(|p| p.id != Some(id) && p.slug == *slug)
+        {
+            return Err(duplicate("slug_1"));
+        }
+
+        let Some(post) = data.posts.get_mut(&id).filter(|p| p.deleted_at.is_none()) else {
+            return Ok(None);
+        };
+        if expected_version.is_some_and(|version| post.version != version) {
+            return Ok(None);
+        }
+
+        apply(post, update);
+        Ok(Some(post.clone()))
+    }
+
+    async fn trash(&self, id: ObjectId, expected_version: i64, by: ObjectId) -> Result<bool, AppError> {
+        let mut data = self.data();
+        let Some(post) = data.posts.get_mut(&id) else {
+            return Ok(false);
+        };
+        if post.version != expected_version || post.deleted_at.is_some() {
+            return Ok(false);
+        }
+
+        post.deleted_at = Some(now());
+        post.deleted_by = Some(by);
+        post.version += 1;
+        Ok(true)
+    }
+
+    async fn trashed(
+        &self,
+        author_id: Option<ObjectId>,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Post>, AppError> {
+        let mut posts: Vec<Post> = self.data()
+            .posts
+            .values()
+            .filter(|p| author_id.is_none_or(|id| id == p.author_id))
+            .filter(|p| p.deleted_at.zip(p.id).is_some_and(|key| after.is_none_or(|c| key < (c.key, c.id))))
+            .cloned()
+            .collect();
+
+        posts.sort_by_key(|p| Reverse((p.deleted_at, p.id)));
+        posts.truncate(limit as usize);
+        Ok(posts)
+    }
+
+    async fn find_trashed(&self, id: ObjectId) -> Result<Option<Post>, AppError> {
+        Ok(self.data().posts.get(&id).filter(|p| p.deleted_at.is_some()).cloned())
+    }
+
+    async fn restore(&self, id: ObjectId) -> Result<Option<Post>, AppError> {
+        let mut data = self.data();
+        let Some(post) = data.posts.get_mut(&id).filter(|p| p.deleted_at.is_some()) else {
+            return Ok(None);
+        };
+
+        post.deleted_at = None;
+        post.deleted_by = None;
+        post.updated_at = now();
+        post.version += 1;
+        Ok(Some(post.clone()))
+    }
+
+    async fn slug_taken(&self, slug: &str, exclude: Option<ObjectId>) -> Result<bool, AppError> {
+        Ok(self.data().slug_taken(slug, exclude))
+    }
+
+    async fn last_revision(&self, post_id: ObjectId) -> Result<Option<i64>, AppError> {
+        Ok(self.data().revisions.iter().filter(|r| r.post_id == post_id).map(|r| r.revision).max())
+    }
+
+    async fn insert_revision(&self, revision: &PostRevision) -> Result<(), AppError> {
+        let mut data = self.data();
+        if data.revisions.iter().any(|r| r.post_id == revision.post_id && r.revision == revision.revision) {
+            return Err(duplicate("post_id_1_revision_-1"));
+        }
+
+        data.revisions.push(PostRevision { id: Some(ObjectId::new()), ..revision.clone() });
+        Ok(())
+    }
+
+    async fn revisions(&self, post_id: ObjectId) -> Result<Vec<PostRevision>, AppError> {
+        let mut revisions: Vec<PostRevision> = self.data()
+            .revisions
+            .iter()
+            .filter(|r| r.post_id == post_id)
+            .cloned()
+            .collect();
+
+        revisions.sort_by_key(|r| Reverse(r.revision));
+        Ok(revisions)
+    }
+
+    async fn find_revision(&self, post_id: ObjectId, revision: i64) -> Result<Option<PostRevision>, AppError> {
+        Ok(self.data()
+            .revisions
+            .iter()
+            .find(|r| r.post_id == post_id && r.revision == revision)
+            .cloned())
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryRepository {
+    async fn insert(&self, user: &User) -> Result<ObjectId, AppError> {
+        let mut data = self.data();
+        if data.users.values().any(|u| u.email == user.email) {
+            return Err(duplicate("email_1"));
+        }
+        if data.users.values().any(|u| u.username == user.username) {
+            return Err(duplicate("username_1"));
+        }
+
+        let id = user.id.unwrap_or_default();
+        data.users.insert(id, User { id: Some(id), ..user.clone() });
+        Ok(id)
+    }
+
+    async fn email_registered(&self, email: &str) -> Result<bool, AppError> {
+        Ok(self.data().users.values().any(|u| u.email == email))
+    }
+
+    async fn find(&self, id: ObjectId) -> Result<Option<User>, AppError> {
+        Ok(self.data().active_user(id).cloned())
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        Ok(self.data().users.values().find(|u| u.email == email && u.deleted_at.is_none()).cloned())
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        Ok(self.data().users.values().find(|u| u.username == username && u.deleted_at.is_none()).cloned())
+    }
+
+    async fn update_profile(&self, id: ObjectId, update: ProfileUpdate) -> Result<bool, AppError> {
+        let mut data = self.data();
+        if data.active_user(id).is_none() {
+            return Ok(false);
+        }
+
+        let others = || data.users.values().filter(|u| u.id != Some(id));
+        if let Some(email) = &update.email && others().any(|u| u.email == *email) {
+            return Err(duplicate("email_1"));
+        }
+        if let Some(username) = &update.username && others().any(|u| u.username == *username) {
+            return Err(duplicate("username_1"));
+        }
+
+        let user = data.users.get_mut(&id).expect("checked above");
+        if let Some(username) = update.username { user.username = username; }
+        if let Some(email) = update.email { user.email = email; }
+        if let Some(avatar) = update.avatar { user.avatar = avatar; }
+        Ok(true)
+    }
+
+    async fn list(&self, after: Option<ObjectId>, descending: bool, limit: i64) -> Result<Vec<User>, AppError> {
+        let data = self.data();
+        let active = data.users.values().filter(|u| u.deleted_at.is_none());
+        let after_cursor = |u: &&User| {
+            after.is_none_or(|cursor| {
+                let id = u.id.unwrap_or_default();
+                if descending { id < cursor } else { id > cursor }
+            })
+        };
+
+        let users: Vec<User> = if descending {
+            active.rev().filter(after_cursor).take(limit as usize).cloned().collect()
+        } else {
+            active.filter(after_cursor).take(limit as usize).cloned().collect()
+        };
+        Ok(users)
+    }
+
+    /// One lock covers the whole cascade, so it is as atomic as the MongoDB transaction
+    async fn delete(&self, id: ObjectId, admin_id: ObjectId, policy: PostsPolicy) -> Result<Cascade, AppError> {
+        let mut data = self.data();
+        let now = now();
+
+        let Some(user) = data.active_user(id) else {
+            return Ok(Cascade::UserNotFound);
+        };
+        if user.username == GHOST_USERNAME {
+            return Ok(Cascade::Rejected);
+        }
+
+        // Where the posts go, if they change hands
+        let new_owner = match policy {
+            PostsPolicy::Delete => None,
+            PostsPolicy::Reassign(target) => {
+                if data.active_user(target).is_none() {
+                    return Ok(Cascade::Rejected);
+                }
+                Some(target)
+            }
+            PostsPolicy::Anonymize => Some(data.ghost_user_id()),
+        };
+
+        // 1. The posts (trashed ones move too, so the new owner can still restore them)
+        let mut posts_affected = 0;
+        for post in data.posts.values_mut().filter(|p| p.author_id == id) {
+            match new_owner {
+                None if post.deleted_at.is_some() => continue,
+                None => {
+                    post.deleted_at = Some(now);
+                    post.deleted_by = Some(admin_id);
+                }
+                Some(owner) => {
+                    post.author_id = owner;
+                    post.updated_at = now;
+                }
+            }
+            post.version += 1;
+            truncate_dates(post);
+            posts_affected += 1;
+        }
+
+        // 2. The account goes to the trash
+        if let Some(user) = data.users.get_mut(&id) {
+            user.deleted_at = Some(now);
+            user.deleted_by = Some(admin_id);
+        }
+
+        // 3. Sign them out everywhere
+        for session in data.sessions.values_mut().filter(|s| s.user_id == id && s.revoked_at.is_none()) {
+            session.revoked_at = Some(now);
+        }
+
+        Ok(Cascade::Done { posts_affected, new_owner })
+    }
+
+    async fn trashed(&self, after: Option<&Cursor>, limit: i64) -> Result<Vec<User>, AppError> {
+        let mut users: Vec<User> = self.data()
+            .users
+            .values()
+            .filter(|u| u.deleted_at.zip(u.id).is_some_and(|key| after.is_none_or(|c| key < (c.key, c.id))))
+            .cloned()
+            .collect();
+
+        users.sort_by_key(|u| Reverse((u.deleted_at, u.id)));
+        users.truncate(limit as usize);
+        Ok(users)
+    }
+
+    /// Posts come back when their deletion time and admin match the account's
+    async fn restore(&self, id: ObjectId) -> Result<Option<(User, u64)>, AppError> {
+        let mut data = self.data();
+        let Some(user) = data.users.get_mut(&id).filter(|u| u.deleted_at.is_some()) else {
+            return Ok(None);
+        };
+
+        let deleted = (user.deleted_at.take(), user.deleted_by.take());
+        let user = user.clone();
+
+        let mut posts_restored = 0;
+        for post in data.posts.values_mut().filter(|p| p.author_id == id && (p.deleted_at, p.deleted_by) == deleted) {
+            post.deleted_at = None;
+            post.deleted_by = None;
+            post.updated_at = now();
+            post.version += 1;
+            posts_restored += 1;
+        }
+
+        Ok(Some((user, posts_restored)))
+    }
+
+    async fn create_session(&self, session: &Session) -> Result<ObjectId, AppError> {
+        let id = session.id.unwrap_or_default();
+        self.data().sessions.insert(id, Session { id: Some(id), ..session.clone() });
+        Ok(id)
+    }
+
+    async fn session_is_live(&self, id: ObjectId) -> Result<bool, AppError> {
+        let now = Utc::now();
+        Ok(self.data()
+            .sessions
+            .get(&id)
+            .is_some_and(|s| s.revoked_at.is_none() && s.expires_at > now))
+    }
+
+    async fn rotate_refresh_token(&self, presented_hash: &str, new_hash: &str) -> Result<Option<Session>, AppError> {
+        let now = Utc::now();
+        let mut data = self.data();
+        let Some(session) = data.sessions.values_mut().find(|s| {
+            s.refresh_token_hash == presented_hash && s.revoked_at.is_none() && s.expires_at > now
+        }) else {
+            return Ok(None);
+        };
+
+        let before = session.clone();
+        session.refresh_token_hash = new_hash.to_string();
+        session.last_used_at = now;
+        session.rotated_token_hashes.push(presented_hash.to_string());
+        Ok(Some(before))
+    }
+
+    async fn revoke_reused_token(&self, presented_hash: &str) -> Result<bool, AppError> {
+        let mut data = self.data();
+        let reused = data.sessions.values_mut().find(|s| {
+            s.revoked_at.is_none() && s.rotated_token_hashes.iter().any(|h| h == presented_hash)
+        });
+
+        match reused {
+            Some(session) => {
+                session.revoked_at = Some(Utc::now());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn revoke_session(&self, id: ObjectId) -> Result<(), AppError> {
+        if let Some(session) = self.data().sessions.get_mut(&id).filter(|s| s.revoked_at.is_none()) {
+            session.revoked_at = Some(Utc::now());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CommentRepository for MemoryRepository {
+    async fn insert(&self, comment: &Comment) -> Result<ObjectId, AppError> {
+        let id = comment.id.unwrap_or_default();
+        let mut stored = Comment { id: Some(id), ..comment.clone() };
+        truncate(&mut stored.created_at);
+        truncate(&mut stored.updated_at);
+        self.data().comments.insert(id, stored);
+        Ok(id)
+    }
+
+    async fn find(&self, post_id: ObjectId, id: ObjectId) -> Result<Option<Comment>, AppError> {
+        Ok(self.data().comments.get(&id).filter(|c| c.post_id == post_id).cloned())
+    }
+
+    /// Comments whose author is gone are dropped, like the MongoDB author lookup does
+    async fn list(&self, post_id: ObjectId) -> Result<Vec<CommentNode>, AppError> {
+        let data = self.data();
+        let mut comments: Vec<&Comment> = data.comments.values().filter(|c| c.post_id == post_id).collect();
+        comments.sort_by_key(|c| (c.created_at, c.id));
+
+        Ok(comments
+            .into_iter()
+            .filter_map(|c| {
+                let author = data.users.get(&c.author_id)?;
+                Some(CommentNode {
+                    id: c.id?,
+                    post_id: c.post_id,
+                    author_id: c.author_id,
+                    parent_id: c.parent_id,
+                    author_name: author.username.clone(),
+                    content: c.content.clone(),
+                    created_at: c.created_at,
+                    updated_at: c.updated_at,
+                    replies: Vec::new(),
+                })
+            })
+            .collect())
+    }
+
+    async fn update_content(&self, id: ObjectId, content: String) -> Result<(), AppError> {
+        if let Some(comment) = self.data().comments.get_mut(&id) {
+            comment.content = content;
+            comment.updated_at = Utc::now();
+            truncate(&mut comment.updated_at);
+        }
+        Ok(())
+    }
+
+    async fn delete_thread(&self, id: ObjectId) -> Result<u64, AppError> {
+        let mut data = self.data();
+        let before = data.comments.len();
+        data.comments.retain(|_, c| c.id != Some(id) && !c.ancestor_ids.contains(&id));
+        Ok((before - data.comments.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl MediaRepository for MemoryRepository {
+    async fn insert(&self, media: &Media) -> Result<(), AppError> {
+        let id = media.id.unwrap_or_default();
+        let mut stored = Media { id: Some(id), ..media.clone() };
+        truncate(&mut stored.created_at);
+        self.data().media.insert(id, stored);
+        Ok(())
+    }
+
+    async fn find(&self, id: ObjectId) -> Result<Option<Media>, AppError> {
+        Ok(self.data().media.get(&id).cloned())
+    }
+
+    async fn list(
+        &self,
+        owner_id: ObjectId,
+        descending: bool,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Media>, AppError> {
+        let data = self.data();
+        let position = |m: &Media| (m.created_at, m.id.unwrap_or_default());
+
+        let mut items: Vec<&Media> = data
+            .media
+            .values()
+            .filter(|m| m.owner_id == owner_id)
+            .filter(|m| {
+                after.is_none_or(|cursor| {
+                    let cursor = (cursor.key, cursor.id);
+                    if descending { position(m) < cursor } else { position(m) > cursor }
+                })
+            })
+            .collect();
+
+        items.sort_by_key(|m| position(m));
+        if descending {
+            items.reverse();
+        }
+        Ok(items.into_iter().take(limit as usize).cloned().collect())
+    }
+
+    async fn used_as_avatar(&self, id: ObjectId) -> Result<bool, AppError> {
+        Ok(self.data().users.values().any(|u| u.avatar.as_ref().is_some_and(|a| a.media_id == id)))
+    }
+
+    async fn delete_unreferenced(&self, id: ObjectId) -> Result<bool, AppError> {
+        let mut data = self.data();
+        if !data.media.get(&id).is_some_and(|m| m.references.is_empty()) {
+            return Ok(false);
+        }
+        data.media.remove(&id);
+        Ok(true)
+    }
+
+    async fn sync_references(&self, post_id: ObjectId, media_ids: &[ObjectId]) -> Result<(), AppError> {
+        for (id, media) in self.data().media.iter_mut() {
+            let linked = media_ids.contains(id);
+            let listed = media.references.contains(&post_id);
+            if linked && !listed {
+                media.references.push(post_id);
+            } else if !linked && listed {
+                media.references.retain(|r| *r != post_id);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+/*
+ * Persistence behind the handlers. The handlers keep the rules (ownership,
+ * versions, publication states); a repository only stores and queries.
+ * MongoDB in production, process memory for tests.
+ */
+
+pub mod memory;
+pub mod mongo;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use std::fmt;
+
+pub use memory::MemoryRepository;
+pub use mongo::MongoRepository;
+
+use crate::error::AppError;
+use crate::markdown::{self, Rendered};
+use crate::models::comment::{Comment, CommentNode};
+use crate::models::media::{ImageRef, Media};
+use crate::models::pagination::{Cursor, SortOrder};
+use crate::models::post::{Post, PostStatus, PostWithAuthor};
+use crate::models::revision::PostRevision;
+use crate::models::session::Session;
+use crate::models::tag::TagCount;
+use crate::models::user::{PostsPolicy, User};
+
+/// How a single post is looked up
+#[derive(Debug, Clone, Copy)]
+pub enum PostKey<'a> {
+    Id(ObjectId),
+    Slug(&'a str),
+    PreviousSlug(&'a str), // One of the post's retired slugs
+}
+
+/// The id or slug, to name the post in a 404
+impl fmt::Display for PostKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{}", id.to_hex()),
+            Self::Slug(slug) | Self::PreviousSlug(slug) => write!(f, "{}", slug),
+        }
+    }
+}
+
+/// Which posts a listing covers. Posts in the trash never match.
+#[derive(Debug, Clone, Default)]
+pub struct PostFilter {
+    pub published: bool, // Only posts the public may see right now
+    pub author_id: Option<ObjectId>,
+    pub status: Option<PostStatus>,
+    pub tag: Option<String>,      // Already a slug
+    pub category: Option<String>, // Already a slug
+    pub from: Option<DateTime<Utc>>, // created_at >= from
+    pub to: Option<DateTime<Utc>>,   // created_at <= to
+}
+
+impl PostFilter {
+    /// Posts the public may see right now
+    pub fn published() -> Self {
+        Self { published: true, ..Self::default() }
+    }
+}
+
+/// The fields a write changes; `None` leaves a field as it is.
+/// Every applied update also sets `updated_at` and bumps `version`.
+#[derive(Debug, Default)]
+pub struct PostUpdate {
+    pub title: Option<String>,
+    pub slug: Option<String>,
+    pub slug_history: Option<Vec<String>>,
+    pub content: Option<String>,
+    pub rendered: Option<Rendered>, // Always set together with `content`, see `set_content`
+    pub tags: Option<Vec<String>>,
+    pub category: Option<Option<String>>,
+    pub cover_image: Option<Option<ImageRef>>,
+    pub status: Option<PostStatus>,
+    pub publish_at: Option<Option<DateTime<Utc>>>, // Some(None) removes the date
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+impl PostUpdate {
+    /// New Markdown source along with everything rendered from it
+    pub fn set_content(&mut self, content: String) {
+        self.rendered = Some(markdown::render(&content));
+        self.content = Some(content);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.slug.is_none()
+            && self.slug_history.is_none()
+            && self.content.is_none()
+            && self.tags.is_none()
+            && self.category.is_none()
+            && self.cover_image.is_none()
+            && self.status.is_none()
+            && self.publish_at.is_none()
+            && self.published_at.is_none()
+    }
+}
+
+/// Profile fields a user may change; `None` leaves a field as it is
+#[derive(Debug, Default)]
+pub struct ProfileUpdate {
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub avatar: Option<Option<ImageRef>>, // Some(None) removes the avatar
+}
+
+impl ProfileUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.username.is_none() && self.email.is_none() && self.avatar.is_none()
+    }
+}
+
+/// Result of deleting an account together with its posts and sessions
+#[derive(Debug)]
+pub enum Cascade {
+    UserNotFound,
+    Rejected, // The ghost account itself, or an unusable reassignment target
+    Done { posts_affected: u64, new_owner: Option<ObjectId> },
+}
+
+#[async_trait]
+pub trait PostRepository: Send + Sync {
+    /// Store a new post and return its id; a slug already in use is a `Duplicate`
+    async fn insert(&self, post: &Post) -> Result<ObjectId, AppError>;
+
+    /// A post that is not in the trash
+    async fn find(&self, id: ObjectId) -> Result<Option<Post>, AppError>;
+
+    /// The current version of a post, in the trash or not
+    async fn version_of(&self, id: ObjectId) -> Result<Option<i64>, AppError>;
+
+    /// A post that is not in the trash, joined with its author and comment count
+    async fn find_with_author(&self, key: PostKey<'_>) -> Result<Option<PostWithAuthor>, AppError>;
+
+    /// Whether the public may see the post right now
+    async fn is_published(&self, id: ObjectId) -> Result<bool, AppError>;
+
+    /// Up to `limit` posts matching `filter` that come after `after` in `sort` order
+    async fn list(
+        &self,
+        filter: &PostFilter,
+        sort: SortOrder,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<PostWithAuthor>, AppError>;
+
+    /// The `limit` most recently published posts matching `filter` (for feeds)
+    async fn latest(&self, filter: &PostFilter, limit: i64) -> Result<Vec<PostWithAuthor>, AppError>;
+
+    /// When any post last changed: edited, gone live or moved into the trash. Feeds use it,
+    /// since a post that left them (trashed, unpublished, retagged) is no longer in them.
+    async fn last_change(&self) -> Result<Option<DateTime<Utc>>, AppError>;
+
+    /// Posts matching `filter` and the text query `q` (`"phrases"` and `-negations` included),
+    /// best match first, with their relevance score. Skips `offset` matches, returns up to `limit`.
+    async fn search(
+        &self,
+        q: &str,
+        filter: &PostFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<(PostWithAuthor, f64)>, AppError>;
+
+    /// Every tag on posts matching `filter`, most used first
+    async fn tag_counts(&self, filter: &PostFilter) -> Result<Vec<TagCount>, AppError>;
+
+    /// Replace tag `from` with `to` on every post, without duplicating `to`; returns the posts changed.
+    /// Like every change that can move a post in or out of a feed, this sets `updated_at`.
+    async fn retag(&self, from: &str, to: &str) -> Result<u64, AppError>;
+
+    /// Apply `update` to a post outside the trash and return it as it is now. With
+    /// `expected_version`, only that version is updated; `None` when nothing matched.
+    async fn update(
+        &self,
+        id: ObjectId,
+        expected_version: Option<i64>,
+        update: PostUpdate,
+    ) -> Result<Option<Post>, AppError>;
+
+    /// Move `expected_version` of a post to the trash; false when it no longer matched
+    async fn trash(&self, id: ObjectId, expected_version: i64, by: ObjectId) -> Result<bool, AppError>;
+
+    /// Up to `limit` posts in the trash, of `author_id` or everyone's, most recently deleted
+    /// first, after `after` (keyed by `deleted_at`)
+    async fn trashed(
+        &self,
+        author_id: Option<ObjectId>,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Post>, AppError>;
+
+    /// A post that is in the trash
+    async fn find_trashed(&self, id: ObjectId) -> Result<Option<Post>, AppError>;
+
+    /// Take a post out of the trash and return it; `None` when it is not there (any more)
+    async fn restore(&self, id: ObjectId) -> Result<Option<Post>, AppError>;
+
+    /// Whether a post other than `exclude` uses `slug`, currently or in its history
+    async fn slug_taken(&self, slug: &str, exclude: Option<ObjectId>) -> Result<bool, AppError>;
+
+    /// Number of the post's latest revision, if it has any
+    async fn last_revision(&self, post_id: ObjectId) -> Result<Option<i64>, AppError>;
+
+    /// Append a revision; a number another writer took first is a `Duplicate`
+    async fn insert_revision(&self, revision: &PostRevision) -> Result<(), AppError>;
+
+    /// Every revision of the post, newest first
+    async fn revisions(&self, post_id: ObjectId) -> Result<Vec<PostRevision>, AppError>;
+
+    async fn find_revision(&self, post_id: ObjectId, revision: i64) -> Result<Option<PostRevision>, AppError>;
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    /// Store a new account and return its id; a taken username or email is a `Duplicate`
+    async fn insert(&self, user: &User) -> Result<ObjectId, AppError>;
+
+    /// Whether any account, deleted ones included, uses `email`
+    async fn email_registered(&self, email: &str) -> Result<bool, AppError>;
+
+    /// An account that is not in the trash
+    async fn find(&self, id: ObjectId) -> Result<Option<User>, AppError>;
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError>;
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>, AppError>;
+
+    /// Apply `update` to an account outside the trash; false when there is none
+    async fn update_profile(&self, id: ObjectId, update: ProfileUpdate) -> Result<bool, AppError>;
+
+    /// Up to `limit` accounts outside the trash, ordered by `_id`, after `after`
+    async fn list(&self, after: Option<ObjectId>, descending: bool, limit: i64) -> Result<Vec<User>, AppError>;
+
+    /// Trash the account, apply `policy` to its posts and revoke its sessions, all or nothing
+    async fn delete(&self, id: ObjectId, admin_id: ObjectId, policy: PostsPolicy) -> Result<Cascade, AppError>;
+
+    /// Up to `limit` accounts in the trash, most recently deleted first, after `after`
+    async fn trashed(&self, after: Option<&Cursor>, limit: i64) -> Result<Vec<User>, AppError>;
+
+    /// Take an account out of the trash along with the posts its deletion trashed, all or nothing.
+    /// Returns the account and the number of posts restored; `None` when it is not in the trash.
+    async fn restore(&self, id: ObjectId) -> Result<Option<(User, u64)>, AppError>;
+
+    async fn create_session(&self, session: &Session) -> Result<ObjectId, AppError>;
+
+    /// Not logged out, revoked or expired
+    async fn session_is_live(&self, id: ObjectId) -> Result<bool, AppError>;
+
+    /// Swap a live session's refresh token hash for `new_hash`, keeping the old one as rotated.
+    /// Returns the session as it was, or `None` when no live session holds `presented_hash`.
+    async fn rotate_refresh_token(&self, presented_hash: &str, new_hash: &str) -> Result<Option<Session>, AppError>;
+
+    /// Revoke the live session that already rotated `presented_hash` out; false if there is none
+    async fn revoke_reused_token(&self, presented_hash: &str) -> Result<bool, AppError>;
+
+    async fn revoke_session(&self, id: ObjectId) -> Result<(), AppError>;
+}
+
+#[async_trait]
+pub trait CommentRepository: Send + Sync {
+    /// Store a new comment and return its id
+    async fn insert(&self, comment: &Comment) -> Result<ObjectId, AppError>;
+
+    /// A comment on `post_id`
+    async fn find(&self, post_id: ObjectId, id: ObjectId) -> Result<Option<Comment>, AppError>;
+
+    /// Every comment on the post with its author's name, oldest first, not yet nested
+    async fn list(&self, post_id: ObjectId) -> Result<Vec<CommentNode>, AppError>;
+
+    async fn update_content(&self, id: ObjectId, content: String) -> Result<(), AppError>;
+
+    /// Delete the comment and every reply below it; returns how many were removed
+    async fn delete_thread(&self, id: ObjectId) -> Result<u64, AppError>;
+}
+
+#[async_trait]
+pub trait MediaRepository: Send + Sync {
+    /// Store the metadata of an upload whose bytes are already in storage
+    async fn insert(&self, media: &Media) -> Result<(), AppError>;
+
+    async fn find(&self, id: ObjectId) -> Result<Option<Media>, AppError>;
+
+    /// Up to `limit` uploads of `owner_id` that come after `after`, ordered by upload time
+    async fn list(
+        &self,
+        owner_id: ObjectId,
+        descending: bool,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Media>, AppError>;
+
+    /// Whether any account, deleted ones included, has the file as its avatar
+    async fn used_as_avatar(&self, id: ObjectId) -> Result<bool, AppError>;
+
+    /// Delete the metadata unless a post references the file; false when one does or it is gone
+    async fn delete_unreferenced(&self, id: ObjectId) -> Result<bool, AppError>;
+
+    /// Make `media_ids` the files referencing the post, and only those
+    async fn sync_references(&self, post_id: ObjectId, media_ids: &[ObjectId]) -> Result<(), AppError>;
+}
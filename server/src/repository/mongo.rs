@@ -0,0 +1,919 @@
+/*
+ * The repositories on MongoDB: the `users`, `sessions`, `posts`, `post_revisions`,
+ * `comments` and `media` collections. Listings join authors and comment counts in one
+ * aggregation; deleting an account runs as a multi-document transaction.
+ */
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use futures::FutureExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
+use mongodb::options::ReturnDocument;
+use mongodb::{ClientSession, Collection, Database};
+
+use super::{
+    Cascade, CommentRepository, MediaRepository, PostFilter, PostKey, PostRepository, PostUpdate, ProfileUpdate,
+    UserRepository,
+};
+use crate::error::AppError;
+use crate::models::comment::{Comment, CommentNode};
+use crate::models::media::Media;
+use crate::models::pagination::{sort_doc, Cursor, SortOrder};
+use crate::models::post::{Post, PostStatus, PostWithAuthor};
+use crate::models::revision::PostRevision;
+use crate::models::session::Session;
+use crate::models::tag::TagCount;
+use crate::models::user::{PostsPolicy, User, UserRole, GHOST_EMAIL, GHOST_USERNAME};
+
+pub struct MongoRepository {
+    db: Database,
+    users: Collection<User>,
+    sessions: Collection<Session>,
+    posts: Collection<Post>,
+    revisions: Collection<PostRevision>,
+    comments: Collection<Comment>,
+    media: Collection<Media>,
+}
+
+impl MongoRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            db: db.clone(),
+            users: db.collection("users"),
+            sessions: db.collection("sessions"),
+            posts: db.collection("posts"),
+            revisions: db.collection("post_revisions"),
+            comments: db.collection("comments"),
+            media: db.collection("media"),
+        }
+    }
+
+    /// Run `pipeline` (ending in the author lookup) over the posts
+    async fn aggregate_posts(&self, pipeline: Vec<Document>) -> Result<Vec<PostWithAuthor>, AppError> {
+        let mut cursor = self.posts.aggregate(pipeline).await?;
+
+        let mut results = Vec::new();
+        while let Some(doc) = cursor.try_next().await? {
+            // Convert BSON document to our Rust struct
+            let post: PostWithAuthor = bson::from_document(doc)?;
+            results.push(post);
+        }
+        Ok(results)
+    }
+
+    /// Trash the account, apply the posts policy and revoke sessions, all inside `session`'s transaction
+    async fn delete_cascade(
+        &self,
+        session: &mut ClientSession,
+        user_id: ObjectId,
+        admin_id: ObjectId,
+        policy: PostsPolicy,
+    ) -> mongodb::error::Result<Cascade> {
+        let now = Utc::now();
+
+        let Some(user) = self.users
+            .find_one(doc! { "_id": user_id, "deleted_at": null })
+            .session(&mut *session)
+            .await?
+        else {
+            return Ok(Cascade::UserNotFound);
+        };
+        if user.username == GHOST_USERNAME {
+            return Ok(Cascade::Rejected);
+        }
+
+        // Where the posts go, if they change hands
+        let new_owner = match policy {
+            PostsPolicy::Delete => None,
+            PostsPolicy::Reassign(target) => {
+                let active = self.users
+                    .count_documents(doc! { "_id": target, "deleted_at": null })
+                    .session(&mut *session)
+                    .await?;
+                if active == 0 {
+                    return Ok(Cascade::Rejected);
+                }
+                Some(target)
+            }
+            PostsPolicy::Anonymize => Some(self.ghost_user_id(session).await?),
+        };
+
+        // 1. The posts (trashed ones move too, so the new owner can still restore them)
+        let posts_affected = match new_owner {
+            None => self.posts
+                .update_many(
+                    doc! { "author_id": user_id, "deleted_at": null },
+                    doc! {
+                        "$set": { "deleted_at": now, "deleted_by": admin_id },
+                        "$inc": { "version": 1 },
+                    },
+                )
+                .session(&mut *session)
+                .await?
+                .modified_count,
+            Some(owner) => self.posts
+                .update_many(
+                    doc! { "author_id": user_id },
+                    doc! {
+                        "$set": { "author_id": owner, "updated_at": now },
+                        "$inc": { "version": 1 },
+                    },
+                )
+                .session(&mut *session)
+                .await?
+                .modified_count,
+        };
+
+        // 2. The account goes to the trash
+        self.users
+            .update_one(
+                doc! { "_id": user_id },
+                doc! { "$set": { "deleted_at": now, "deleted_by": admin_id } },
+            )
+            .session(&mut *session)
+            .await?;
+
+        // 3. Sign them out everywhere
+        self.sessions
+            .update_many(
+                doc! { "user_id": user_id, "revoked_at": null },
+                doc! { "$set": { "revoked_at": now } },
+            )
+            .session(&mut *session)
+            .await?;
+
+        Ok(Cascade::Done { posts_affected, new_owner })
+    }
+
+    /// Take the account out of the trash, and the posts that were trashed with it: same
+    /// deletion time and admin. Posts their author had deleted before stay in the trash.
+    async fn restore_cascade(
+        &self,
+        session: &mut ClientSession,
+        user_id: ObjectId,
+    ) -> mongodb::error::Result<Option<(User, u64)>> {
+        let Some(mut user) = self.users
+            .find_one_and_update(
+                doc! { "_id": user_id, "deleted_at": { "$ne": null } },
+                doc! { "$unset": { "deleted_at": "", "deleted_by": "" } },
+            )
+            .session(&mut *session)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let posts_restored = self.posts
+            .update_many(
+                doc! {
+                    "author_id": user_id,
+                    "deleted_at": user.deleted_at.take(),
+                    "deleted_by": user.deleted_by.take(),
+                },
+                doc! {
+                    "$set": { "updated_at": Utc::now() },
+                    "$unset": { "deleted_at": "", "deleted_by": "" },
+                    "$inc": { "version": 1 },
+                },
+            )
+            .session(&mut *session)
+            .await?
+            .modified_count;
+
+        Ok(Some((user, posts_restored)))
+    }
+
+    /// The ghost account that anonymized posts belong to, created on first use.
+    /// Its password is not a bcrypt hash, so nobody can log in as it.
+    async fn ghost_user_id(&self, session: &mut ClientSession) -> mongodb::error::Result<ObjectId> {
+        let ghost = self.users
+            .find_one_and_update(
+                doc! { "username": GHOST_USERNAME },
+                doc! {
+                    "$setOnInsert": {
+                        "email": GHOST_EMAIL,
+                        "password": "!",
+                        "role": UserRole::User.to_string(),
+                    }
+                },
+            )
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .session(&mut *session)
+            .await?;
+
+        ghost
+            .and_then(|u| u.id)
+            .ok_or_else(|| mongodb::error::Error::custom("ghost user upsert returned nothing"))
+    }
+}
+
+#[async_trait]
+impl PostRepository for MongoRepository {
+    async fn insert(&self, post: &Post) -> Result<ObjectId, AppError> {
+        // The unique index catches two posts racing for the same slug (409)
+        let result = self.posts.insert_one(post).await?;
+        result
+            .inserted_id
+            .as_object_id()
+            .ok_or_else(|| AppError::internal("inserted post id is not an ObjectId"))
+    }
+
+    async fn find(&self, id: ObjectId) -> Result<Option<Post>, AppError> {
+        Ok(self.posts.find_one(doc! { "_id": id, "deleted_at": null }).await?)
+    }
+
+    async fn version_of(&self, id: ObjectId) -> Result<Option<i64>, AppError> {
+        Ok(self.posts.find_one(doc! { "_id": id }).await?.map(|post| post.version))
+    }
+
+    async fn find_with_author(&self, key: PostKey<'_>) -> Result<Option<PostWithAuthor>, AppError> {
+        let filter = match key {
+            PostKey::Id(id) => doc! { "_id": id },
+            PostKey::Slug(slug) => doc! { "slug": slug },
+            PostKey::PreviousSlug(slug) => doc! { "slug_history": slug },
+        };
+
+        // Trashed posts are only reachable via /trash
+        let mut pipeline = vec![
+            doc! { "$match": { "$and": [filter, { "deleted_at": null }] } },
+            doc! { "$limit": 1 },
+        ];
+        pipeline.extend(author_lookup_stages());
+
+        Ok(self.aggregate_posts(pipeline).await?.into_iter().next())
+    }
+
+    async fn is_published(&self, id: ObjectId) -> Result<bool, AppError> {
+        let mut filter = published_filter();
+        filter.insert("_id", id);
+        Ok(self.posts.count_documents(filter).await? > 0)
+    }
+
+    /// Filters, cursor, sort and limit happen before the author $lookup
+    /// so the join only touches the returned page
+    async fn list(
+        &self,
+        filter: &PostFilter,
+        sort: SortOrder,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<PostWithAuthor>, AppError> {
+        let mut conditions = vec![filter_doc(filter)];
+        if let Some(cursor) = after {
+            conditions.push(cursor.after(sort.field(), sort.descending()));
+        }
+
+        let mut pipeline = vec![
+            doc! { "$match": { "$and": conditions } },
+            doc! { "$sort": sort_doc(sort.field(), sort.descending()) },
+            doc! { "$limit": limit },
+        ];
+        pipeline.extend(author_lookup_stages());
+
+        self.aggregate_posts(pipeline).await
+    }
+
+    async fn latest(&self, filter: &PostFilter, limit: i64) -> Result<Vec<PostWithAuthor>, AppError> {
+        let mut pipeline = vec![
+            doc! { "$match": filter_doc(filter) },
+            doc! { "$sort": { "publish_at": -1, "_id": -1 } },
+            doc! { "$limit": limit },
+        ];
+        pipeline.extend(author_lookup_stages());
+
+        self.aggregate_posts(pipeline).await
+    }
+
+    /// Three indexed lookups rather than a scan: the latest edit, go-live and deletion
+    async fn last_change(&self) -> Result<Option<DateTime<Utc>>, AppError> {
+        let posts = self.posts.clone_with_type::<Document>();
+        let lookups = [
+            ("updated_at", doc! {}),
+            ("publish_at", published_filter()),
+            ("deleted_at", doc! { "deleted_at": { "$ne": null } }),
+        ];
+
+        let mut latest = None;
+        for (field, filter) in lookups {
+            let found = posts
+                .find_one(filter)
+                .sort(doc! { field: -1 })
+                .projection(doc! { field: 1 })
+                .await?;
+            if let Some(at) = found.as_ref().and_then(|post| post.get_datetime(field).ok()) {
+                latest = latest.max(Some(at.to_chrono()));
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Phrases and negations are handled by the text index; scores have no stable
+    /// cursor key, hence the offset
+    async fn search(
+        &self,
+        q: &str,
+        filter: &PostFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<(PostWithAuthor, f64)>, AppError> {
+        let mut matched = doc! { "$text": { "$search": q } };
+        matched.extend(filter_doc(filter));
+
+        let mut pipeline = vec![
+            doc! { "$match": matched },
+            doc! { "$addFields": { "score": { "$meta": "textScore" } } },
+            doc! { "$sort": { "score": -1, "_id": -1 } },
+            doc! { "$skip": offset },
+            doc! { "$limit": limit },
+        ];
+        pipeline.extend(author_lookup_stages_with(doc! { "score": 1 }));
+
+        let mut cursor = self.posts.aggregate(pipeline).await?;
+        let mut hits = Vec::new();
+        while let Some(doc) = cursor.try_next().await? {
+            let score = doc.get_f64("score").unwrap_or_default();
+            hits.push((bson::from_document(doc)?, score));
+        }
+        Ok(hits)
+    }
+
+    async fn tag_counts(&self, filter: &PostFilter) -> Result<Vec<TagCount>, AppError> {
+        let pipeline = vec![
+            doc! { "$match": filter_doc(filter) },
+            doc! { "$unwind": "$tags" },
+            doc! { "$group": { "_id": "$tags", "post_count": { "$sum": 1 } } },
+            doc! { "$sort": { "post_count": -1, "_id": 1 } },
+        ];
+
+        let mut cursor = self.posts.aggregate(pipeline).await?;
+        let mut tags = Vec::new();
+        while let Some(doc) = cursor.try_next().await? {
+            tags.push(bson::from_document(doc)?);
+        }
+        Ok(tags)
+    }
+
+    async fn retag(&self, from: &str, to: &str) -> Result<u64, AppError> {
+        let now = Utc::now();
+
+        // 1. Posts that already carry the target just lose the old tag
+        let pulled = self.posts
+            .update_many(
+                doc! { "tags": { "$all": [from, to] } },
+                doc! { "$pull": { "tags": from }, "$set": { "updated_at": now }, "$inc": { "version": 1 } },
+            )
+            .await?;
+
+        // 2. Everywhere else the old tag is replaced in place, keeping its position
+        let replaced = self.posts
+            .update_many(
+                doc! { "tags": from },
+                doc! { "$set": { "tags.$": to, "updated_at": now }, "$inc": { "version": 1 } },
+            )
+            .await?;
+
+        Ok(pulled.modified_count + replaced.modified_count)
+    }
+
+    async fn update(
+        &self,
+        id: ObjectId,
+        expected_version: Option<i64>,
+        update: PostUpdate,
+    ) -> Result<Option<Post>, AppError> {
+        // Conditioned on the version the caller checked, so a concurrent save cannot be overwritten;
+        // a post trashed since the caller loaded it is never changed
+        let mut filter = doc! { "_id": id, "deleted_at": null };
+        if let Some(version) = expected_version {
+            filter.insert("version", version);
+        }
+
+        let updated = self.posts
+            .find_one_and_update(filter, update_doc(update)?)
+            .return_document(ReturnDocument::After)
+            .await?; // A slug taken by a concurrent write is a 409
+
+        Ok(updated)
+    }
+
+    /// Comments and revisions stay until the purge job removes the post for good
+    async fn trash(&self, id: ObjectId, expected_version: i64, by: ObjectId) -> Result<bool, AppError> {
+        let result = self.posts
+            .update_one(
+                doc! { "_id": id, "version": expected_version, "deleted_at": null },
+                doc! {
+                    "$set": { "deleted_at": Utc::now(), "deleted_by": by },
+                    "$inc": { "version": 1 },
+                },
+            )
+            .await?;
+
+        Ok(result.matched_count > 0)
+    }
+
+    async fn trashed(
+        &self,
+        author_id: Option<ObjectId>,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Post>, AppError> {
+        let mut filter = doc! { "deleted_at": { "$ne": null } };
+        if let Some(author_id) = author_id {
+            filter.insert("author_id", author_id);
+        }
+        if let Some(cursor) = after {
+            filter = doc! { "$and": [filter, cursor.after("deleted_at", true)] };
+        }
+
+        let posts = self.posts
+            .find(filter)
+            .sort(sort_doc("deleted_at", true))
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(posts)
+    }
+
+    async fn find_trashed(&self, id: ObjectId) -> Result<Option<Post>, AppError> {
+        Ok(self.posts.find_one(doc! { "_id": id, "deleted_at": { "$ne": null } }).await?)
+    }
+
+    async fn restore(&self, id: ObjectId) -> Result<Option<Post>, AppError> {
+        let restored = self.posts
+            .find_one_and_update(
+                doc! { "_id": id, "deleted_at": { "$ne": null } },
+                doc! {
+                    "$set": { "updated_at": Utc::now() },
+                    "$unset": { "deleted_at": "", "deleted_by": "" },
+                    "$inc": { "version": 1 },
+                },
+            )
+            .return_document(ReturnDocument::After)
+            .await?;
+
+        Ok(restored)
+    }
+
+    async fn slug_taken(&self, slug: &str, exclude: Option<ObjectId>) -> Result<bool, AppError> {
+        Ok(self.posts.count_documents(slug_filter(slug, exclude)).await? > 0)
+    }
+
+    async fn last_revision(&self, post_id: ObjectId) -> Result<Option<i64>, AppError> {
+        let last = self.revisions
+            .find_one(doc! { "post_id": post_id })
+            .sort(doc! { "revision": -1 })
+            .await?;
+
+        Ok(last.map(|r| r.revision))
+    }
+
+    async fn insert_revision(&self, revision: &PostRevision) -> Result<(), AppError> {
+        // The unique index on (post_id, revision) serializes concurrent edits
+        self.revisions.insert_one(revision).await?;
+        Ok(())
+    }
+
+    async fn revisions(&self, post_id: ObjectId) -> Result<Vec<PostRevision>, AppError> {
+        let revisions = self.revisions
+            .find(doc! { "post_id": post_id })
+            .sort(doc! { "revision": -1 })
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(revisions)
+    }
+
+    async fn find_revision(&self, post_id: ObjectId, revision: i64) -> Result<Option<PostRevision>, AppError> {
+        Ok(self.revisions.find_one(doc! { "post_id": post_id, "revision": revision }).await?)
+    }
+}
+
+#[async_trait]
+impl UserRepository for MongoRepository {
+    async fn insert(&self, user: &User) -> Result<ObjectId, AppError> {
+        // The unique indexes catch races on username and email (409)
+        let result = self.users.insert_one(user).await?;
+        result
+            .inserted_id
+            .as_object_id()
+            .ok_or_else(|| AppError::internal("inserted user id is not an ObjectId"))
+    }
+
+    async fn email_registered(&self, email: &str) -> Result<bool, AppError> {
+        Ok(self.users.count_documents(doc! { "email": email }).await? > 0)
+    }
+
+    async fn find(&self, id: ObjectId) -> Result<Option<User>, AppError> {
+        Ok(self.users.find_one(doc! { "_id": id, "deleted_at": null }).await?)
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        Ok(self.users.find_one(doc! { "email": email, "deleted_at": null }).await?)
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        Ok(self.users.find_one(doc! { "username": username, "deleted_at": null }).await?)
+    }
+
+    async fn update_profile(&self, id: ObjectId, update: ProfileUpdate) -> Result<bool, AppError> {
+        let mut set = doc! {};
+        if let Some(username) = update.username { set.insert("username", username); }
+        if let Some(email) = update.email { set.insert("email", email); }
+        if let Some(avatar) = update.avatar { set.insert("avatar", bson::to_bson(&avatar)?); }
+
+        // A username or email that is already taken is a 409
+        let result = self.users
+            .update_one(doc! { "_id": id, "deleted_at": null }, doc! { "$set": set })
+            .await?;
+
+        Ok(result.matched_count > 0)
+    }
+
+    /// Users have no timestamps of their own, so both the order and the cursor use _id
+    async fn list(&self, after: Option<ObjectId>, descending: bool, limit: i64) -> Result<Vec<User>, AppError> {
+        let mut filter = doc! { "deleted_at": null };
+        if let Some(id) = after {
+            let op = if descending { "$lt" } else { "$gt" };
+            filter.insert("_id", doc! { op: id });
+        }
+
+        let users = self.users
+            .find(filter)
+            .sort(sort_doc("_id", descending))
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(users)
+    }
+
+    /// Runs the cascade in a transaction. The driver retries transient conflicts and
+    /// commits with an unknown outcome, for up to two minutes in total.
+    async fn delete(&self, id: ObjectId, admin_id: ObjectId, policy: PostsPolicy) -> Result<Cascade, AppError> {
+        let mut session = self.db.client()
+            .start_session()
+            .await?;
+
+        let outcome = session
+            .start_transaction()
+            .and_run(self, |session, repo| repo.delete_cascade(session, id, admin_id, policy).boxed())
+            .await?;
+
+        Ok(outcome)
+    }
+
+    async fn trashed(&self, after: Option<&Cursor>, limit: i64) -> Result<Vec<User>, AppError> {
+        let mut filter = doc! { "deleted_at": { "$ne": null } };
+        if let Some(cursor) = after {
+            filter = doc! { "$and": [filter, cursor.after("deleted_at", true)] };
+        }
+
+        let users = self.users
+            .find(filter)
+            .sort(sort_doc("deleted_at", true))
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(users)
+    }
+
+    /// In a transaction, like the deletion it undoes
+    async fn restore(&self, id: ObjectId) -> Result<Option<(User, u64)>, AppError> {
+        let mut session = self.db.client()
+            .start_session()
+            .await?;
+
+        let outcome = session
+            .start_transaction()
+            .and_run(self, |session, repo| repo.restore_cascade(session, id).boxed())
+            .await?;
+
+        Ok(outcome)
+    }
+
+    async fn create_session(&self, session: &Session) -> Result<ObjectId, AppError> {
+        let result = self.sessions.insert_one(session).await?;
+        result
+            .inserted_id
+            .as_object_id()
+            .ok_or_else(|| AppError::internal("inserted session id is not an ObjectId"))
+    }
+
+    async fn session_is_live(&self, id: ObjectId) -> Result<bool, AppError> {
+        let live = self.sessions
+            .count_documents(doc! {
+                "_id": id,
+                "revoked_at": null,
+                "expires_at": { "$gt": Utc::now() },
+            })
+            .await?;
+
+        Ok(live > 0)
+    }
+
+    async fn rotate_refresh_token(&self, presented_hash: &str, new_hash: &str) -> Result<Option<Session>, AppError> {
+        let now = Utc::now();
+        let rotated = self.sessions
+            .find_one_and_update(
+                doc! {
+                    "refresh_token_hash": presented_hash,
+                    "revoked_at": null,
+                    "expires_at": { "$gt": now },
+                },
+                doc! {
+                    "$set": { "refresh_token_hash": new_hash, "last_used_at": now },
+                    "$push": { "rotated_token_hashes": presented_hash },
+                },
+            )
+            .await?;
+
+        Ok(rotated)
+    }
+
+    async fn revoke_reused_token(&self, presented_hash: &str) -> Result<bool, AppError> {
+        let reused = self.sessions
+            .update_one(
+                doc! { "rotated_token_hashes": presented_hash, "revoked_at": null },
+                doc! { "$set": { "revoked_at": Utc::now() } },
+            )
+            .await?;
+
+        Ok(reused.modified_count > 0)
+    }
+
+    async fn revoke_session(&self, id: ObjectId) -> Result<(), AppError> {
+        self.sessions
+            .update_one(
+                doc! { "_id": id, "revoked_at": null },
+                doc! { "$set": { "revoked_at": Utc::now() } },
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CommentRepository for MongoRepository {
+    async fn insert(&self, comment: &Comment) -> Result<ObjectId, AppError> {
+        let result = self.comments.insert_one(comment).await?;
+        result
+            .inserted_id
+            .as_object_id()
+            .ok_or_else(|| AppError::internal("inserted comment id is not an ObjectId"))
+    }
+
+    async fn find(&self, post_id: ObjectId, id: ObjectId) -> Result<Option<Comment>, AppError> {
+        Ok(self.comments.find_one(doc! { "_id": id, "post_id": post_id }).await?)
+    }
+
+    async fn list(&self, post_id: ObjectId) -> Result<Vec<CommentNode>, AppError> {
+        let pipeline = vec![
+            doc! { "$match": { "post_id": post_id } },
+            doc! { "$sort": { "created_at": 1, "_id": 1 } },
+            doc! {
+                "$lookup": {
+                    "from": "users",
+                    "localField": "author_id",
+                    "foreignField": "_id",
+                    "as": "author_info"
+                }
+            },
+            doc! { "$unwind": "$author_info" },
+            doc! {
+                "$project": {
+                    "_id": 1,
+                    "post_id": 1,
+                    "author_id": 1,
+                    "parent_id": 1,
+                    "content": 1,
+                    "created_at": 1,
+                    "updated_at": 1,
+                    "author_name": "$author_info.username"
+                }
+            },
+        ];
+
+        let mut cursor = self.comments.aggregate(pipeline).await?;
+        let mut flat = Vec::new();
+        while let Some(doc) = cursor.try_next().await? {
+            flat.push(bson::from_document(doc)?);
+        }
+        Ok(flat)
+    }
+
+    async fn update_content(&self, id: ObjectId, content: String) -> Result<(), AppError> {
+        self.comments
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "content": content, "updated_at": Utc::now() } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_thread(&self, id: ObjectId) -> Result<u64, AppError> {
+        let result = self.comments
+            .delete_many(doc! { "$or": [{ "_id": id }, { "ancestor_ids": id }] })
+            .await?;
+
+        Ok(result.deleted_count)
+    }
+}
+
+#[async_trait]
+impl MediaRepository for MongoRepository {
+    async fn insert(&self, media: &Media) -> Result<(), AppError> {
+        self.media.insert_one(media).await?;
+        Ok(())
+    }
+
+    async fn find(&self, id: ObjectId) -> Result<Option<Media>, AppError> {
+        Ok(self.media.find_one(doc! { "_id": id }).await?)
+    }
+
+    async fn list(
+        &self,
+        owner_id: ObjectId,
+        descending: bool,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Media>, AppError> {
+        let mut filter = doc! { "owner_id": owner_id };
+        if let Some(cursor) = after {
+            filter.extend(cursor.after("created_at", descending));
+        }
+
+        let items = self.media
+            .find(filter)
+            .sort(sort_doc("created_at", descending))
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(items)
+    }
+
+    async fn used_as_avatar(&self, id: ObjectId) -> Result<bool, AppError> {
+        Ok(self.users.count_documents(doc! { "avatar.media_id": id }).await? > 0)
+    }
+
+    /// Checked in the filter, in case a post started linking to the file meanwhile
+    async fn delete_unreferenced(&self, id: ObjectId) -> Result<bool, AppError> {
+        let result = self.media
+            .delete_one(doc! { "_id": id, "references": { "$size": 0 } })
+            .await?;
+
+        Ok(result.deleted_count > 0)
+    }
+
+    async fn sync_references(&self, post_id: ObjectId, media_ids: &[ObjectId]) -> Result<(), AppError> {
+        self.media
+            .update_many(
+                doc! { "_id": { "$in": media_ids } },
+                doc! { "$addToSet": { "references": post_id } },
+            )
+            .await?;
+
+        self.media
+            .update_many(
+                doc! { "references": post_id, "_id": { "$nin": media_ids } },
+                doc! { "$pull": { "references": post_id } },
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Filter matching posts the public may see right now
+fn published_filter() -> Document {
+    doc! {
+        "status": PostStatus::Published.as_str(),
+        "publish_at": { "$lte": Utc::now() },
+        "deleted_at": null,
+    }
+}
+
+/// Posts other than `exclude` that use `slug`, currently or in their history
+pub fn slug_filter(slug: &str, exclude: Option<ObjectId>) -> Document {
+    let mut filter = doc! { "$or": [{ "slug": slug }, { "slug_history": slug }] };
+    if let Some(id) = exclude {
+        filter.insert("_id", doc! { "$ne": id });
+    }
+    filter
+}
+
+/// Stages joining a post with its author and shaping it into a PostWithAuthor
+fn author_lookup_stages() -> Vec<Document> {
+    author_lookup_stages_with(doc! {})
+}
+
+/// Same as `author_lookup_stages`, projecting `extra` fields on top (e.g. a search score)
+fn author_lookup_stages_with(extra: Document) -> Vec<Document> {
+    let mut projection = doc! {
+        "_id": 1,
+        "author_id": 1,
+        "slug": 1,
+        "title": 1,
+        "content": 1,
+        "content_html": 1,
+        "toc": 1,
+        "excerpt": 1,
+        "reading_time_minutes": 1,
+        "status": 1,
+        "publish_at": 1,
+        "published_at": 1,
+        "tags": 1,
+        "category": 1,
+        "cover_image": 1,
+        "version": 1,
+        "created_at": 1,
+        "updated_at": 1,
+        "author_name": "$author_info.username", // Map username to author_name
+        "comment_count": { "$ifNull": [{ "$arrayElemAt": ["$comment_stats.count", 0] }, 0] }
+    };
+    projection.extend(extra);
+
+    vec![
+        // 1. Join with users collection
+        doc! {
+            "$lookup": {
+                "from": "users",
+                "localField": "author_id",
+                "foreignField": "_id",
+                "as": "author_info"
+            }
+        },
+        // 2. lookup returns an array, "unwind" it to a single object
+        doc! { "$unwind": "$author_info" },
+        // 3. Count the comments (only the count travels back, not the comments)
+        doc! {
+            "$lookup": {
+                "from": "comments",
+                "localField": "_id",
+                "foreignField": "post_id",
+                "pipeline": [{ "$count": "count" }],
+                "as": "comment_stats"
+            }
+        },
+        // 4. Project only the fields we want
+        doc! { "$project": projection },
+    ]
+}
+
+fn filter_doc(filter: &PostFilter) -> Document {
+    let mut doc = if filter.published { published_filter() } else { doc! { "deleted_at": null } };
+
+    if let Some(author_id) = filter.author_id { doc.insert("author_id", author_id); }
+    if let Some(status) = filter.status { doc.insert("status", status.as_str()); }
+    if let Some(tag) = &filter.tag { doc.insert("tags", tag); }
+    if let Some(category) = &filter.category { doc.insert("category", category); }
+
+    let mut created = doc! {};
+    if let Some(from) = filter.from { created.insert("$gte", BsonDateTime::from_chrono(from)); }
+    if let Some(to) = filter.to { created.insert("$lte", BsonDateTime::from_chrono(to)); }
+    if !created.is_empty() {
+        doc.insert("created_at", created);
+    }
+    doc
+}
+
+/// `$set` (and `$unset`) for a PostUpdate, plus the timestamp and version bump
+fn update_doc(update: PostUpdate) -> Result<Document, AppError> {
+    let mut set = doc! {};
+    let mut unset = doc! {};
+
+    if let Some(title) = update.title { set.insert("title", title); }
+    if let Some(slug) = update.slug { set.insert("slug", slug); }
+    if let Some(history) = update.slug_history { set.insert("slug_history", history); }
+    if let Some(content) = update.content { set.insert("content", content); }
+    if let Some(rendered) = update.rendered { set.extend(rendered.to_document()); }
+    if let Some(tags) = update.tags { set.insert("tags", tags); }
+    if let Some(category) = update.category { set.insert("category", category); }
+    if let Some(cover) = update.cover_image { set.insert("cover_image", bson::to_bson(&cover)?); }
+    if let Some(status) = update.status { set.insert("status", status.as_str()); }
+    match update.publish_at {
+        Some(Some(at)) => { set.insert("publish_at", at); }
+        Some(None) => { unset.insert("publish_at", ""); }
+        None => {}
+    }
+    if let Some(at) = update.published_at { set.insert("published_at", at); }
+    set.insert("updated_at", Utc::now());
+
+    let mut doc = doc! { "$set": set, "$inc": { "version": 1 } };
+    if !unset.is_empty() {
+        doc.insert("$unset", unset);
+    }
+    Ok(doc)
+}
+
+
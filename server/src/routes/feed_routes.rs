@@ -0,0 +1,16 @@
+/*
+ * Routes for the site-wide feeds. Per-author and per-tag feeds live under
+ * /users and /tags.
+ */
+
+use axum::{routing::get, Router};
+use std::sync::Arc;
+use crate::handlers::feed_handler::site_feed;
+use crate::AppState;
+
+pub fn feed_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/feed.rss", get(site_feed))
+        .route("/feed.atom", get(site_feed))
+        .route("/feed.json", get(site_feed))
+}
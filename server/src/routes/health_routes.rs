@@ -0,0 +1,14 @@
+/*
+ * Liveness and readiness probes, outside any API prefix.
+ */
+
+use axum::{routing::get, Router};
+use std::sync::Arc;
+use crate::handlers::health_handler::{healthz, readyz};
+use crate::AppState;
+
+pub fn health_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+}
@@ -0,0 +1,24 @@
+/*
+ * Routes for uploaded media files.
+ */
+
+use axum::{extract::DefaultBodyLimit, routing::{get, post}, Router};
+use std::sync::Arc;
+use crate::handlers::media_handler::{upload_media, list_media, get_media, get_media_variant, delete_media};
+use crate::config::MediaConfig;
+use crate::AppState;
+
+/// Room for the multipart boundaries and part headers around the file itself
+const MULTIPART_OVERHEAD: usize = 64 * 1024;
+
+pub fn media_routes(config: &MediaConfig) -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/",
+            post(upload_media)
+                .layer(DefaultBodyLimit::max(config.max_upload_bytes + MULTIPART_OVERHEAD))
+                .get(list_media),
+        )
+        .route("/:id", get(get_media).delete(delete_media))
+        .route("/:id/:variant", get(get_media_variant))
+}
@@ -0,0 +1,13 @@
+/*
+ * Prometheus scrape endpoint, outside any API prefix. Mounted unless
+ * features.metrics is off; keep it off the public internet at the proxy.
+ */
+
+use axum::{routing::get, Router};
+use std::sync::Arc;
+use crate::handlers::metrics_handler::metrics;
+use crate::AppState;
+
+pub fn metrics_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/metrics", get(metrics))
+}
@@ -1,12 +1,30 @@
 pub mod post_routes;
 pub mod user_routes;
+pub mod tag_routes;
+pub mod trash_routes;
+pub mod media_routes;
+pub mod feed_routes;
+pub mod health_routes;
+pub mod metrics_routes;
 
 use axum::Router;
 use std::sync::Arc;
+use crate::config::Config;
 use crate::AppState;
 
-pub fn create_routes() -> Router<Arc<AppState>> {
-    Router::new()
+pub fn create_routes(config: &Config) -> Router<Arc<AppState>> {
+    let router = Router::new()
         .nest("/users", user_routes::user_routes())
         .nest("/posts", post_routes::post_routes())
+        .nest("/tags", tag_routes::tag_routes())
+        .nest("/trash", trash_routes::trash_routes())
+        .nest("/media", media_routes::media_routes(&config.media))
+        .merge(feed_routes::feed_routes())
+        .merge(health_routes::health_routes());
+
+    if config.features.metrics {
+        router.merge(metrics_routes::metrics_routes())
+    } else {
+        router
+    }
 }
\ No newline at end of file
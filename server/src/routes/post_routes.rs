@@ -5,9 +5,12 @@
  */
 
 
-use axum::{routing::{get, post }, Router};
+use axum::{routing::{get, patch, post}, Router};
 use std::sync::Arc;
-use crate::handlers::post_handler::{create_post, get_posts, update_post, get_post_by_id, get_post_by_author, delete_post};
+use crate::handlers::post_handler::{create_post, get_posts, update_post, get_post_by_id, get_post_by_author, get_post_by_slug, delete_post, publish_post, unpublish_post, archive_post};
+use crate::handlers::search_handler::search_posts;
+use crate::handlers::revision_handler::{get_revisions, diff_revisions, restore_revision};
+use crate::handlers::comment_handler::{create_comment, get_comments, update_comment, delete_comment};
 use crate::AppState;
 
 pub fn post_routes() -> Router<Arc<AppState>> {
@@ -15,8 +18,18 @@ pub fn post_routes() -> Router<Arc<AppState>> {
         .route("/", post(create_post))
         .route("/", get(get_posts))
         .route("/me", get(get_post_by_author))
+        .route("/search", get(search_posts))
+        .route("/by-slug/:slug", get(get_post_by_slug))
         .route("/:id", get(get_post_by_id)
         .patch(update_post)
         .delete(delete_post)) // Protected
+        .route("/:id/publish", post(publish_post))
+        .route("/:id/unpublish", post(unpublish_post))
+        .route("/:id/archive", post(archive_post))
+        .route("/:id/revisions", get(get_revisions))
+        .route("/:id/revisions/diff", get(diff_revisions))
+        .route("/:id/revisions/:rev/restore", post(restore_revision))
+        .route("/:id/comments", get(get_comments).post(create_comment))
+        .route("/:id/comments/:comment_id", patch(update_comment).delete(delete_comment))
 
 }
\ No newline at end of file
@@ -0,0 +1,20 @@
+/*
+ * Routes for tags: public listings plus admin maintenance.
+ */
+
+use axum::{routing::{get, patch, post}, Router};
+use std::sync::Arc;
+use crate::handlers::tag_handler::{get_tags, get_posts_by_tag, rename_tag, merge_tags};
+use crate::handlers::feed_handler::tag_feed;
+use crate::AppState;
+
+pub fn tag_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(get_tags))
+        .route("/merge", post(merge_tags)) // Admin
+        .route("/:slug", patch(rename_tag)) // Admin
+        .route("/:slug/posts", get(get_posts_by_tag))
+        .route("/:slug/feed.rss", get(tag_feed))
+        .route("/:slug/feed.atom", get(tag_feed))
+        .route("/:slug/feed.json", get(tag_feed))
+}
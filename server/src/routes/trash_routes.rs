@@ -0,0 +1,15 @@
+/*
+ * Routes for the trash bin: soft-deleted posts and users.
+ */
+
+use axum::{routing::{get, post}, Router};
+use std::sync::Arc;
+use crate::handlers::trash_handler::{get_trash, restore_post, restore_user};
+use crate::AppState;
+
+pub fn trash_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(get_trash))
+        .route("/posts/:id/restore", post(restore_post))
+        .route("/users/:id/restore", post(restore_user)) // Admin
+}
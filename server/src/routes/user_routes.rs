@@ -6,15 +6,21 @@
 
 use axum::{Router, routing::{get, patch, post, delete}};
 use std::sync::Arc;
-use crate::handlers::user_handler::{get_current_user, login_user, register_user, update_profile, get_all_users, delete_user};
+use crate::handlers::user_handler::{get_current_user, login_user, logout_user, refresh_token, register_user, update_profile, get_all_users, delete_user};
+use crate::handlers::feed_handler::author_feed;
 use crate::AppState;
 
 pub fn user_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/register", post(register_user))
         .route("/login", post(login_user)) 
+        .route("/token/refresh", post(refresh_token))
+        .route("/logout", post(logout_user))
         .route("/me", get(get_current_user))
         .route("/edit_profile", patch(update_profile)) 
         .route("/admin/users", get(get_all_users))
         .route("/admin/users/:id", delete(delete_user))
+        .route("/:author/feed.rss", get(author_feed))
+        .route("/:author/feed.atom", get(author_feed))
+        .route("/:author/feed.json", get(author_feed))
 }
\ No newline at end of file
@@ -0,0 +1,59 @@
+/*
+ * URL slugs shared by tags, categories and post URLs.
+ */
+
+use mongodb::bson::oid::ObjectId;
+
+use crate::error::AppError;
+use crate::repository::PostRepository;
+
+/// Long titles make unwieldy URLs; the collision suffix is added after this
+const MAX_POST_SLUG_LEN: usize = 80;
+
+/// Lowercase, keep letters and digits, turn every other run of characters into a single '-'.
+/// e.g. "Rust & MongoDB: Part 2!" -> "rust-mongodb-part-2"
+pub fn slugify(input: &str) -> String {
+    let mut slug = String::with_capacity(input.len());
+    let mut pending_dash = false;
+
+    for c in input.chars() {
+        if c.is_alphanumeric() {
+            if pending_dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_dash = false;
+            slug.extend(c.to_lowercase());
+        } else {
+            pending_dash = true;
+        }
+    }
+
+    slug
+}
+
+/// A post slug derived from `title` that no other post uses, currently or in its history.
+/// Collisions get a numeric suffix: "hello-world", "hello-world-2", ...
+/// `exclude` is the post being renamed, which may take back one of its own old slugs.
+pub async fn unique_post_slug(
+    posts: &dyn PostRepository,
+    title: &str,
+    exclude: Option<ObjectId>,
+) -> Result<String, AppError> {
+    for candidate in post_slug_candidates(title) {
+        if !posts.slug_taken(&candidate, exclude).await? {
+            return Ok(candidate);
+        }
+    }
+    unreachable!("the candidates never run out")
+}
+
+/// Slugs to try for a post titled `title`, in order of preference
+pub fn post_slug_candidates(title: &str) -> impl Iterator<Item = String> {
+    let mut base: String = slugify(title).chars().take(MAX_POST_SLUG_LEN).collect();
+    base = base.trim_end_matches('-').to_string();
+    if base.is_empty() {
+        base = "post".to_string();
+    }
+
+    (1..).map(move |suffix| if suffix == 1 { base.clone() } else { format!("{}-{}", base, suffix) })
+}
@@ -0,0 +1,78 @@
+/*
+ * Media storage in an S3-compatible bucket (AWS S3, MinIO, R2, ...).
+ */
+
+use axum::{async_trait, body::Bytes};
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::{Bucket, Region};
+
+use super::{MediaStorage, StorageError};
+use crate::config::S3Config;
+
+pub struct S3Storage {
+    bucket: Box<Bucket>,
+}
+
+impl S3Storage {
+    /// With an endpoint set (e.g. http://localhost:9000 for MinIO) path-style URLs are used
+    pub fn new(config: &S3Config) -> Result<Self, String> {
+        let name = config.bucket.as_deref().ok_or("S3_BUCKET must be set when MEDIA_STORAGE=s3")?;
+
+        let region = match &config.endpoint {
+            Some(endpoint) => Region::Custom { region: config.region.clone(), endpoint: endpoint.clone() },
+            None => config.region.parse().map_err(|e| format!("Invalid S3_REGION: {}", e))?,
+        };
+        let custom_endpoint = matches!(region, Region::Custom { .. });
+
+        let credentials = Credentials::new(
+            config.access_key.as_deref(),
+            config.secret_key.as_deref(),
+            None,
+            None,
+            None,
+        )
+        .map_err(|e| format!("Invalid S3 credentials: {}", e))?;
+
+        let bucket = Bucket::new(name, region, credentials)
+            .map_err(|e| format!("Invalid S3 bucket configuration: {}", e))?;
+        let bucket = if custom_endpoint { bucket.with_path_style() } else { bucket };
+
+        Ok(Self { bucket })
+    }
+}
+
+fn s3_error(e: S3Error) -> StorageError {
+    match e {
+        S3Error::HttpFailWithBody(404, _) => StorageError::NotFound,
+        e => StorageError::Backend(e.to_string()),
+    }
+}
+
+#[async_trait]
+impl MediaStorage for S3Storage {
+    async fn put(&self, key: &str, data: &[u8], content_type: &str) -> Result<(), StorageError> {
+        self.bucket
+            .put_object_with_content_type(key, data, content_type)
+            .await
+            .map(|_| ())
+            .map_err(s3_error)
+    }
+
+    async fn get(&self, key: &str, range: Option<(u64, u64)>) -> Result<Bytes, StorageError> {
+        let response = match range {
+            Some((start, end)) => self.bucket.get_object_range(key, start, Some(end)).await,
+            None => self.bucket.get_object(key).await,
+        }
+        .map_err(s3_error)?;
+
+        Ok(response.bytes().clone())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match self.bucket.delete_object(key).await.map_err(s3_error) {
+            Ok(_) | Err(StorageError::NotFound) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
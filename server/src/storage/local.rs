@@ -0,0 +1,74 @@
+/*
+ * Media storage on the local filesystem, one file per key under a root directory.
+ */
+
+use axum::{async_trait, body::Bytes};
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use super::{MediaStorage, StorageError};
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Keys are relative paths; anything that could escape the root is refused
+    fn path_for(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let relative = Path::new(key);
+        if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(StorageError::Backend(format!("invalid key: {}", key)));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+fn io_error(e: std::io::Error) -> StorageError {
+    match e.kind() {
+        ErrorKind::NotFound => StorageError::NotFound,
+        _ => StorageError::Backend(e.to_string()),
+    }
+}
+
+#[async_trait]
+impl MediaStorage for LocalStorage {
+    async fn put(&self, key: &str, data: &[u8], _content_type: &str) -> Result<(), StorageError> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await.map_err(io_error)?;
+        }
+
+        // Write next to the target and rename, so readers never see a partial file
+        let tmp = path.with_extension("part");
+        fs::write(&tmp, data).await.map_err(io_error)?;
+        fs::rename(&tmp, &path).await.map_err(io_error)
+    }
+
+    async fn get(&self, key: &str, range: Option<(u64, u64)>) -> Result<Bytes, StorageError> {
+        let path = self.path_for(key)?;
+
+        let Some((start, end)) = range else {
+            return fs::read(&path).await.map(Bytes::from).map_err(io_error);
+        };
+
+        let mut file = fs::File::open(&path).await.map_err(io_error)?;
+        file.seek(SeekFrom::Start(start)).await.map_err(io_error)?;
+
+        let mut buf = vec![0; (end - start + 1) as usize];
+        file.read_exact(&mut buf).await.map_err(io_error)?;
+        Ok(Bytes::from(buf))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match fs::remove_file(self.path_for(key)?).await.map_err(io_error) {
+            Err(StorageError::NotFound) => Ok(()),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+/*
+ * Where uploaded media bytes live. The `media` collection only keeps metadata
+ * and the storage key; the bytes go to one of the backends below.
+ */
+
+pub mod local;
+pub mod bucket;
+
+use axum::{async_trait, body::Bytes};
+use std::fmt;
+use std::sync::Arc;
+
+pub use local::LocalStorage;
+pub use bucket::S3Storage;
+
+use crate::config::{MediaConfig, StorageBackend};
+
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound,
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "object not found"),
+            Self::Backend(e) => write!(f, "storage backend error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+#[async_trait]
+pub trait MediaStorage: Send + Sync {
+    /// Store `data` under `key`, replacing anything already there
+    async fn put(&self, key: &str, data: &[u8], content_type: &str) -> Result<(), StorageError>;
+
+    /// Read the whole object, or only the inclusive byte `range`
+    async fn get(&self, key: &str, range: Option<(u64, u64)>) -> Result<Bytes, StorageError>;
+
+    /// Remove the object; deleting a missing key is not an error
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// Local disk under `media.dir`, or an S3-compatible bucket
+pub fn from_config(config: &MediaConfig) -> Result<Arc<dyn MediaStorage>, String> {
+    Ok(match config.storage {
+        StorageBackend::Local => Arc::new(LocalStorage::new(&config.dir)),
+        StorageBackend::S3 => Arc::new(S3Storage::new(&config.s3)?),
+    })
+}
@@ -0,0 +1,120 @@
+/*
+ * Logging: the tracing subscriber, request ids and one span per request,
+ * plus helpers that keep personal data and secrets out of the logs.
+ */
+
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request},
+    http::header::{AUTHORIZATION, COOKIE, SET_COOKIE},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use sha2::{Digest, Sha256};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    sensitive_headers::{SetSensitiveRequestHeadersLayer, SetSensitiveResponseHeadersLayer},
+    trace::{DefaultOnResponse, TraceLayer},
+    LatencyUnit,
+};
+use tracing::{Level, Span};
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogConfig, LogFormat};
+
+/// Taken from the client when it sends one, generated otherwise, echoed on every response
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    /// The current request's id, for code that has no access to the request (see error.rs)
+    static REQUEST_ID: String;
+}
+
+/// Install the global subscriber; RUST_LOG overrides `log.level`
+pub fn init(config: &LogConfig) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.level));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match config.format {
+        LogFormat::Json => subscriber.json().flatten_event(true).with_span_list(false).init(),
+        LogFormat::Text => subscriber.init(),
+    }
+}
+
+/// Wrap the app in request ids, per-request spans and header redaction.
+/// Layers run outermost first: the id exists before the span that records it.
+pub fn instrument<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(middleware::from_fn(scope_request_id))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO).latency_unit(LatencyUnit::Millis))
+                // AppError already logs server errors together with their cause
+                .on_failure(()),
+        )
+        .layer(SetSensitiveResponseHeadersLayer::new([SET_COOKIE]))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetSensitiveRequestHeadersLayer::new([AUTHORIZATION, COOKIE]))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+/// The span every event of a request is logged under. The route is the matched
+/// template (`/posts/:id`), never the raw URI, so ids and query strings stay out.
+/// `user_id` is filled in by the `AuthUser` extractor.
+fn make_span(request: &Request<Body>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        .unwrap_or("unmatched");
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        route,
+        request_id = header_request_id(request).unwrap_or_default(),
+        user_id = tracing::field::Empty,
+    )
+}
+
+async fn scope_request_id(request: Request, next: Next) -> Response {
+    let id = header_request_id(&request).unwrap_or_default().to_string();
+    REQUEST_ID.scope(id, next.run(request)).await
+}
+
+fn header_request_id(request: &Request<Body>) -> Option<&str> {
+    request.headers().get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok())
+}
+
+/// The id of the request being handled, if any
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok().filter(|id| !id.is_empty())
+}
+
+/// Attach the authenticated user to the current request span
+pub fn record_user(user_id: impl std::fmt::Display) {
+    Span::current().record("user_id", tracing::field::display(user_id));
+}
+
+/// "jane.doe@example.com" -> "j***@example.com": enough to spot a pattern, not to identify anyone
+pub fn redact_email(email: &str) -> String {
+    match email.trim().split_once('@') {
+        Some((local, domain)) => {
+            let first = local.chars().next().map(String::from).unwrap_or_default();
+            format!("{}***@{}", first, domain)
+        }
+        None => "***".to_string(),
+    }
+}
+
+/// A short fingerprint of a secret token. It is the start of the token's SHA-256,
+/// so a refresh token can be matched against `sessions.refresh_token_hash`.
+pub fn redact_token(token: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(token.as_bytes()));
+    format!("sha256:{}", &digest[..12])
+}
@@ -0,0 +1,98 @@
+/*
+ * Request body validation: `ValidatedJson<T>` parses the body like `Json<T>` and then
+ * runs the DTO's `validator` rules, so handlers only ever see valid input.
+ */
+
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::error::AppError;
+
+/// Drop-in replacement for `Json<T>` on DTOs that derive `Validate`
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidatedJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Validate,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection: JsonRejection| AppError::InvalidBody {
+                status: rejection.status(),
+                detail: rejection.body_text(),
+            })?;
+
+        value.validate().map_err(AppError::Validation)?;
+        Ok(Self(value))
+    }
+}
+
+/// One failed rule, as listed in the problem body
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub field: String,   // Dotted path, e.g. "tags[2]" or "author.name"
+    pub pointer: String, // The same as a JSON Pointer into the request body
+    pub code: String,    // The validator rule: "length", "email", ...
+    pub message: String,
+}
+
+/// Flatten nested validation errors into one list, sorted by field
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut out = Vec::new();
+    collect(errors, "", "", &mut out);
+    out.sort_by(|a, b| a.field.cmp(&b.field).then_with(|| a.code.cmp(&b.code)));
+    out
+}
+
+fn collect(errors: &ValidationErrors, field: &str, pointer: &str, out: &mut Vec<FieldError>) {
+    for (name, kind) in errors.errors() {
+        let field = if field.is_empty() { name.to_string() } else { format!("{}.{}", field, name) };
+        let pointer = format!("{}/{}", pointer, name);
+
+        match kind {
+            ValidationErrorsKind::Field(list) => {
+                out.extend(list.iter().map(|e| FieldError {
+                    field: field.clone(),
+                    pointer: format!("#{}", pointer),
+                    code: e.code.to_string(),
+                    message: describe(e),
+                }));
+            }
+            ValidationErrorsKind::Struct(inner) => collect(inner, &field, &pointer, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, inner) in items {
+                    collect(inner, &format!("{}[{}]", field, index), &format!("{}/{}", pointer, index), out);
+                }
+            }
+        }
+    }
+}
+
+/// The rule's own message, or one built from its parameters
+fn describe(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+
+    let param = |name: &str| error.params.get(name).and_then(Value::as_u64);
+    match (error.code.as_ref(), param("min"), param("max")) {
+        ("length", Some(min), Some(max)) => format!("Must be between {} and {} characters", min, max),
+        ("length", Some(min), None) => format!("Must be at least {} characters", min),
+        ("length", None, Some(max)) => format!("Must be at most {} characters", max),
+        ("email", _, _) => "Must be a valid email address".to_string(),
+        ("url", _, _) => "Must be a valid URL".to_string(),
+        (code, _, _) => format!("Failed the {} rule", code),
+    }
+}
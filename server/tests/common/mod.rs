@@ -0,0 +1,388 @@
+/*
+ * Harness for the integration tests: the full application from `server::app`, driven
+ * in process without a socket. Posts, users and sessions live in the in-memory
+ * repositories; with TEST_MONGODB_URI set every test gets its own database on that
+ * server instead, and the tests of handlers that still query MongoDB directly run too.
+ */
+
+#![allow(dead_code)] // Each test binary uses its own share of the helpers
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, watch};
+use tower::ServiceExt;
+
+use server::auth::AuthService;
+use server::config::{Config, DatabaseConfig, Environment};
+use server::db::{connect_db, spawn_init_db, InitStatus};
+use server::models::user::{User, UserRole};
+use server::rate_limit::RateLimiter;
+use server::repository::{
+    CommentRepository, MediaRepository, MemoryRepository, MongoRepository, PostRepository, UserRepository,
+};
+use server::storage::local::LocalStorage;
+use server::AppState;
+
+/// Password of every account the helpers create
+pub const PASSWORD: &str = "correct-horse-battery";
+
+/// A server nothing listens on: the handlers that still query MongoDB directly fail
+/// fast with a 503 instead of hanging when the tests run without TEST_MONGODB_URI
+const UNREACHABLE_MONGODB: &str = "mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=200&connectTimeoutMS=200";
+
+/// Bodies larger than this fail the test rather than being read
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+pub struct TestApp {
+    pub state: Arc<AppState>,
+    router: Router,
+    mongodb: Option<(String, String)>, // URI and name of the per-test database, dropped afterwards
+    media_dir: PathBuf,
+    _image_queue: mpsc::Receiver<ObjectId>, // Keeps the upload queue open
+}
+
+/// An account created through the API, with its current tokens
+#[derive(Debug, Clone)]
+pub struct TestUser {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+pub struct TestRequest<'a> {
+    app: &'a TestApp,
+    request: axum::http::request::Builder,
+    body: Body,
+}
+
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl TestApp {
+    /// The application with test settings: fast bcrypt, no rate limits
+    pub async fn new() -> Self {
+        Self::with_config(|_| {}).await
+    }
+
+    /// Same as `new`, with `configure` applied to the settings first
+    pub async fn with_config(configure: impl FnOnce(&mut Config)) -> Self {
+        let mut config = test_config();
+        configure(&mut config);
+
+        let (db, mongodb, db_init) = match std::env::var("TEST_MONGODB_URI") {
+            Ok(uri) if !uri.trim().is_empty() => {
+                let name = format!("blog_test_{}", ObjectId::new().to_hex());
+                let database_config = DatabaseConfig {
+                    uri: uri.clone(),
+                    name: name.clone(),
+                    connect_attempts: 1,
+                    ..DatabaseConfig::default()
+                };
+                let db = connect_db(&database_config).await.expect("TEST_MONGODB_URI does not answer");
+
+                // Indexes first: the unique ones back the 409s, the text index backs search
+                let mut db_init = spawn_init_db(db.clone());
+                db_init
+                    .wait_for(|status| matches!(status, InitStatus::Complete { .. }))
+                    .await
+                    .expect("init_db stopped");
+                (db, Some((uri, name)), db_init)
+            }
+            _ => {
+                let client = mongodb::Client::with_uri_str(UNREACHABLE_MONGODB).await.expect("valid URI");
+                let (_, db_init) = watch::channel(InitStatus::Complete { finished_at: Utc::now(), failures: Vec::new() });
+                (client.database("unused"), None, db_init)
+            }
+        };
+
+        let repositories = if mongodb.is_some() {
+            Repositories::from(Arc::new(MongoRepository::new(&db)))
+        } else {
+            Repositories::from(Arc::new(MemoryRepository::new()))
+        };
+
+        let media_dir = std::env::temp_dir().join(format!("blog-test-media-{}", ObjectId::new().to_hex()));
+        config.media.dir = media_dir.clone();
+        let (image_jobs, image_queue) = mpsc::channel(16);
+
+        let state = Arc::new(AppState {
+            auth: AuthService::from_config(&config),
+            limiter: RateLimiter::from_config(&config.rate_limit, &db),
+            media: Arc::new(LocalStorage::new(&media_dir)),
+            config,
+            db,
+            posts: repositories.posts,
+            users: repositories.users,
+            comments: repositories.comments,
+            uploads: repositories.uploads,
+            db_init,
+            image_jobs,
+        });
+
+        Self {
+            router: server::app(state.clone()),
+            state,
+            mongodb,
+            media_dir,
+            _image_queue: image_queue,
+        }
+    }
+
+    pub fn has_mongodb(&self) -> bool {
+        self.mongodb.is_some()
+    }
+
+    pub fn request(&self, method: Method, uri: &str) -> TestRequest<'_> {
+        TestRequest {
+            app: self,
+            request: Request::builder().method(method).uri(uri),
+            body: Body::empty(),
+        }
+    }
+
+    pub fn get(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::GET, uri)
+    }
+
+    pub fn post(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::POST, uri)
+    }
+
+    pub fn patch(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::PATCH, uri)
+    }
+
+    pub fn delete(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::DELETE, uri)
+    }
+
+    /// Register `username` (email `<username>@example.com`, password `PASSWORD`)
+    pub async fn register(&self, username: &str) -> TestUser {
+        let body = self
+            .post("/users/register")
+            .json(json!({ "username": username, "email": email_of(username), "password": PASSWORD }))
+            .send()
+            .await
+            .expect_status(StatusCode::CREATED)
+            .json();
+        TestUser::from_auth_body(&body)
+    }
+
+    /// Log in with `PASSWORD`
+    pub async fn login(&self, email: &str) -> TestUser {
+        let body = self
+            .post("/users/login")
+            .json(json!({ "email": email, "password": PASSWORD }))
+            .send()
+            .await
+            .expect_status(StatusCode::OK)
+            .json();
+        TestUser::from_auth_body(&body)
+    }
+
+    /// An admin account (registration only ever creates users), logged in through the API
+    pub async fn admin(&self, username: &str) -> TestUser {
+        let admin = User {
+            id: None,
+            username: username.to_string(),
+            email: email_of(username),
+            password: bcrypt::hash(PASSWORD, self.state.config.auth.bcrypt_cost).expect("bcrypt"),
+            role: UserRole::Admin,
+            avatar: None,
+            deleted_at: None,
+            deleted_by: None,
+        };
+        assert!(self.state.users.insert(&admin).await.is_ok(), "could not insert the admin");
+        self.login(&admin.email).await
+    }
+
+    /// Create a post as `author` and return its id
+    pub async fn create_post(&self, author: &TestUser, post: Value) -> String {
+        let body = self
+            .post("/posts")
+            .token(author)
+            .json(post)
+            .send()
+            .await
+            .expect_status(StatusCode::CREATED)
+            .json();
+        oid(&body)
+    }
+
+    /// A published post titled `title`
+    pub async fn publish(&self, author: &TestUser, title: &str) -> String {
+        self.create_post(author, json!({ "title": title, "content": format!("All about {}.", title) })).await
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.media_dir);
+
+        // The test's runtime may already be gone, so the database is dropped from a runtime of its own
+        if let Some((uri, name)) = self.mongodb.take() {
+            let dropped = std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+                runtime.block_on(async {
+                    let client = mongodb::Client::with_uri_str(&uri).await.map_err(std::io::Error::other)?;
+                    client.database(&name).drop().await.map_err(std::io::Error::other)
+                })
+            });
+            if let Ok(Err(e)) = dropped.join() {
+                eprintln!("could not drop the test database: {}", e);
+            }
+        }
+    }
+}
+
+/// One backend behind every repository of the state
+struct Repositories {
+    posts: Arc<dyn PostRepository>,
+    users: Arc<dyn UserRepository>,
+    comments: Arc<dyn CommentRepository>,
+    uploads: Arc<dyn MediaRepository>,
+}
+
+impl<R> From<Arc<R>> for Repositories
+where
+    R: PostRepository + UserRepository + CommentRepository + MediaRepository + 'static,
+{
+    fn from(repository: Arc<R>) -> Self {
+        Self {
+            posts: repository.clone(),
+            users: repository.clone(),
+            comments: repository.clone(),
+            uploads: repository,
+        }
+    }
+}
+
+impl TestUser {
+    fn from_auth_body(body: &Value) -> Self {
+        Self {
+            id: str_at(body, "/user/id"),
+            username: str_at(body, "/user/username"),
+            email: str_at(body, "/user/email"),
+            access_token: str_at(body, "/access_token"),
+            refresh_token: str_at(body, "/refresh_token"),
+        }
+    }
+}
+
+impl<'a> TestRequest<'a> {
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.request = self.request.header(name, value);
+        self
+    }
+
+    /// `Authorization: Bearer` with the user's access token
+    pub fn token(self, user: &TestUser) -> Self {
+        let value = format!("Bearer {}", user.access_token);
+        self.header(header::AUTHORIZATION.as_str(), &value)
+    }
+
+    /// `If-Match` for a post version
+    pub fn if_match(self, version: i64) -> Self {
+        self.header(header::IF_MATCH.as_str(), &format!("\"{}\"", version))
+    }
+
+    pub fn json(mut self, body: Value) -> Self {
+        self.request = self.request.header(header::CONTENT_TYPE, "application/json");
+        self.body = Body::from(body.to_string());
+        self
+    }
+
+    pub fn body(mut self, content_type: &str, body: impl Into<Body>) -> Self {
+        self.request = self.request.header(header::CONTENT_TYPE, content_type);
+        self.body = body.into();
+        self
+    }
+
+    pub async fn send(self) -> TestResponse {
+        let request = self.request.body(self.body).expect("valid request");
+        let response = self.app.router.clone().oneshot(request).await.expect("infallible");
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = to_bytes(response.into_body(), MAX_BODY_BYTES).await.expect("readable body");
+        TestResponse { status, headers, body: body.to_vec() }
+    }
+}
+
+impl TestResponse {
+    /// Panics with the body when the status is not `expected`
+    #[track_caller]
+    pub fn expect_status(self, expected: StatusCode) -> Self {
+        assert_eq!(self.status, expected, "unexpected status, body: {}", self.text());
+        self
+    }
+
+    /// An application/problem+json error with `status` and `code`
+    #[track_caller]
+    pub fn expect_problem(self, status: StatusCode, code: &str) -> Self {
+        let response = self.expect_status(status);
+        assert_eq!(response.header("content-type"), Some("application/problem+json"));
+        assert_eq!(response.json()["code"], code, "body: {}", response.text());
+        response
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    #[track_caller]
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, self.text()))
+    }
+}
+
+/// Settings for tests: a fixed JWT secret, the cheapest bcrypt cost, no rate limits
+pub fn test_config() -> Config {
+    let mut config = Config {
+        environment: Environment::Development,
+        ..Config::default()
+    };
+    config.auth.jwt_secret = Some("integration-test-secret".to_string());
+    config.auth.bcrypt_cost = 4;
+    config.rate_limit.enabled = false;
+    config
+}
+
+pub fn email_of(username: &str) -> String {
+    format!("{}@example.com", username)
+}
+
+/// The hex id of an ObjectId as serialized by bson (`{"$oid": "..."}`), or of a plain string
+#[track_caller]
+pub fn oid(value: &Value) -> String {
+    value["$oid"]
+        .as_str()
+        .or_else(|| value.as_str())
+        .unwrap_or_else(|| panic!("not an ObjectId: {}", value))
+        .to_string()
+}
+
+#[track_caller]
+fn str_at(body: &Value, pointer: &str) -> String {
+    body.pointer(pointer)
+        .and_then(Value::as_str)
+        .unwrap_or_else(|| panic!("no {} in {}", pointer, body))
+        .to_string()
+}
@@ -0,0 +1,627 @@
+/*
+ * Every route in post_routes: writing posts under the ownership and version rules,
+ * their publication states, public listings and lookups, revisions, comments and search.
+ */
+
+mod common;
+
+use axum::http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use serde_json::{json, Value};
+
+use common::{oid, TestApp, TestUser};
+use server::models::comment::MAX_DEPTH;
+use server::repository::PostUpdate;
+
+/// The post as its author sees it, drafts included
+async fn fetch(app: &TestApp, user: &TestUser, id: &str) -> Value {
+    app.get(&format!("/posts/{}", id)).token(user).send().await.expect_status(StatusCode::OK).json()
+}
+
+fn titles(page: &Value) -> Vec<String> {
+    page["items"]
+        .as_array()
+        .expect("a page of posts")
+        .iter()
+        .map(|p| p["title"].as_str().unwrap_or_default().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn creating_a_post_requires_an_account_and_valid_input() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+
+    let post = json!({ "title": "Hello Rust", "content": "Rust and MongoDB couldn't be better!" });
+    app.post("/posts").json(post.clone()).send().await.expect_problem(StatusCode::UNAUTHORIZED, "invalid_token");
+
+    let id = app.create_post(&alice, post).await;
+    let body = fetch(&app, &alice, &id).await;
+    assert_eq!(body["slug"], "hello-rust");
+    assert_eq!(body["status"], "published");
+    assert_eq!(body["version"], 1);
+    assert_eq!(body["author_name"], "alice");
+
+    let body = app
+        .post("/posts")
+        .token(&alice)
+        .json(json!({ "title": "Hi", "content": "short" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed")
+        .json();
+    assert_eq!(body["errors"].as_array().unwrap().len(), 2);
+
+    app.post("/posts")
+        .token(&alice)
+        .json(json!({ "title": "Archived already", "content": "Can't start archived", "status": "archived" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+    app.post("/posts")
+        .token(&alice)
+        .json(json!({ "title": "Scheduled for when", "content": "Needs a publish date", "status": "scheduled" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+}
+
+#[tokio::test]
+async fn posts_with_the_same_title_get_numbered_slugs() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+
+    let first = app.publish(&alice, "Same title").await;
+    let second = app.publish(&alice, "Same title").await;
+
+    assert_eq!(fetch(&app, &alice, &first).await["slug"], "same-title");
+    assert_eq!(fetch(&app, &alice, &second).await["slug"], "same-title-2");
+}
+
+#[tokio::test]
+async fn public_listing_only_shows_live_posts() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    app.publish(&alice, "Live post").await;
+    app.create_post(&alice, json!({ "title": "Draft post", "content": "Still writing this", "status": "draft" })).await;
+    app.create_post(&alice, json!({
+        "title": "Coming soon",
+        "content": "This post goes live at the publish date.",
+        "publish_at": "2030-01-01T09:00:00Z"
+    }))
+    .await;
+
+    let page = app.get("/posts").send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(titles(&page), ["Live post"]);
+
+    // The author sees everything, optionally narrowed down by status
+    app.get("/posts/me").send().await.expect_status(StatusCode::UNAUTHORIZED);
+    let mine = app.get("/posts/me").token(&alice).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(titles(&mine), ["Coming soon", "Draft post", "Live post"]);
+    let scheduled = app.get("/posts/me?status=scheduled").token(&alice).send().await.json();
+    assert_eq!(titles(&scheduled), ["Coming soon"]);
+}
+
+#[tokio::test]
+async fn listings_filter_sort_and_paginate() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.create_post(&alice, json!({ "title": "First post", "content": "About Rust things", "tags": ["Rust"], "category": "Tutorials" })).await;
+    app.create_post(&alice, json!({ "title": "Second post", "content": "About MongoDB things", "tags": ["MongoDB"] })).await;
+    app.create_post(&bob, json!({ "title": "Third post", "content": "Rust from Bob", "tags": ["rust"] })).await;
+
+    let newest = app.get("/posts").send().await.json();
+    assert_eq!(titles(&newest), ["Third post", "Second post", "First post"]);
+
+    let first = app.get("/posts?limit=2&sort=oldest").send().await.json();
+    assert_eq!(titles(&first), ["First post", "Second post"]);
+    assert_eq!(first["has_more"], true);
+    let next = format!("/posts?limit=2&sort=oldest&cursor={}", first["next_cursor"].as_str().unwrap());
+    let second = app.get(&next).send().await.json();
+    assert_eq!(titles(&second), ["Third post"]);
+    assert_eq!(second["has_more"], false);
+    assert!(second["next_cursor"].is_null());
+
+    // A cursor only continues the order it was made for
+    let newest_cursor = format!("/posts?limit=2&cursor={}", first["next_cursor"].as_str().unwrap());
+    app.get(&newest_cursor).send().await.expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+    let updated_cursor = format!("/posts?limit=2&sort=updated&cursor={}", first["next_cursor"].as_str().unwrap());
+    app.get(&updated_cursor).send().await.expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+
+    let by_name = app.get("/posts?author=alice&sort=oldest").send().await.json();
+    assert_eq!(titles(&by_name), ["First post", "Second post"]);
+    let by_id = app.get(&format!("/posts?author={}", bob.id)).send().await.json();
+    assert_eq!(titles(&by_id), ["Third post"]);
+    let unknown = app.get("/posts?author=nobody").send().await.expect_status(StatusCode::OK).json();
+    assert!(titles(&unknown).is_empty());
+
+    let tagged = app.get("/posts?tag=RUST&sort=oldest").send().await.json();
+    assert_eq!(titles(&tagged), ["First post", "Third post"]);
+    let category = app.get("/posts?category=tutorials").send().await.json();
+    assert_eq!(titles(&category), ["First post"]);
+    let future = app.get("/posts?from=2100-01-01T00:00:00Z").send().await.json();
+    assert!(titles(&future).is_empty());
+    let past = app.get("/posts?to=2000-01-01T00:00:00Z").send().await.json();
+    assert!(titles(&past).is_empty());
+
+    app.get("/posts?cursor=not-a-cursor").send().await.expect_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn drafts_are_only_visible_to_their_author_and_admins() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let admin = app.admin("root").await;
+    let id = app.create_post(&alice, json!({ "title": "Secret draft", "content": "Nobody may see this", "status": "draft" })).await;
+
+    let uri = format!("/posts/{}", id);
+    app.get(&uri).send().await.expect_problem(StatusCode::NOT_FOUND, "not_found");
+    app.get(&uri).token(&bob).send().await.expect_status(StatusCode::NOT_FOUND);
+    app.get(&uri).token(&alice).send().await.expect_status(StatusCode::OK);
+    app.get(&uri).token(&admin).send().await.expect_status(StatusCode::OK);
+
+    app.get("/posts/by-slug/secret-draft").send().await.expect_status(StatusCode::NOT_FOUND);
+    app.get("/posts/by-slug/secret-draft").token(&alice).send().await.expect_status(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn get_by_id_answers_with_an_etag_and_304_when_unchanged() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let id = app.publish(&alice, "Cached post").await;
+
+    let response = app.get(&format!("/posts/{}", id)).send().await.expect_status(StatusCode::OK);
+    let etag = response.header("etag").unwrap().to_string();
+    assert!(etag.starts_with("\"1-"), "tag of version 1, got {}", etag);
+    assert_eq!(oid(&response.json()["_id"]), id);
+
+    app.get(&format!("/posts/{}", id))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .expect_status(StatusCode::NOT_MODIFIED);
+    // The bare version no longer matches: it says nothing about the rest of the body
+    app.get(&format!("/posts/{}", id))
+        .header("If-None-Match", "\"1\"")
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+
+    // Renaming the author changes the body but not the post's version
+    app.patch("/users/edit_profile").token(&alice).json(json!({ "username": "alice_2" })).send().await.expect_status(StatusCode::OK);
+    let renamed = app
+        .get(&format!("/posts/{}", id))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+    assert_eq!(renamed.json()["author_name"], "alice_2");
+    assert_ne!(renamed.header("etag"), Some(etag.as_str()));
+
+    // Every error is problem+json with a stable code and a correlation id
+    let body = app
+        .get("/posts/not-an-id")
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "invalid_id")
+        .json();
+    assert!(body["correlation_id"].as_str().is_some_and(|id| !id.is_empty()));
+    app.get("/posts/65a000000000000000000000").send().await.expect_problem(StatusCode::NOT_FOUND, "not_found");
+}
+
+#[tokio::test]
+async fn the_etag_of_a_get_is_accepted_as_if_match() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let id = app.publish(&alice, "Round trip").await;
+    let uri = format!("/posts/{}", id);
+
+    let etag = app.get(&uri).send().await.expect_status(StatusCode::OK).header("etag").unwrap().to_string();
+    let saved = app
+        .patch(&uri)
+        .token(&alice)
+        .header("If-Match", &etag)
+        .json(json!({ "title": "Round trip, edited" }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+    assert_eq!(saved.json()["version"], 2);
+
+    // The same tag now names a stale version
+    let stale = app
+        .patch(&uri)
+        .token(&alice)
+        .header("If-Match", &etag)
+        .json(json!({ "title": "Lost update" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::PRECONDITION_FAILED, "version_mismatch");
+    assert_eq!(stale.json()["current_version"], 2);
+}
+
+#[tokio::test]
+async fn updating_a_post_needs_the_owner_and_the_current_version() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let admin = app.admin("root").await;
+    let id = app.publish(&alice, "My Second Rust Blog Post").await;
+    let uri = format!("/posts/{}", id);
+    let change = json!({ "content": "Can't wait to see what's next!" });
+
+    app.patch(&uri).if_match(1).json(change.clone()).send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.patch(&uri)
+        .token(&alice)
+        .json(change.clone())
+        .send()
+        .await
+        .expect_problem(StatusCode::PRECONDITION_REQUIRED, "precondition_required");
+    app.patch(&uri)
+        .token(&alice)
+        .header("If-Match", "W/\"1\"")
+        .json(change.clone())
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+    app.patch(&uri)
+        .token(&bob)
+        .if_match(1)
+        .json(change.clone())
+        .send()
+        .await
+        .expect_problem(StatusCode::FORBIDDEN, "forbidden");
+
+    let response = app.patch(&uri).token(&alice).if_match(1).json(change.clone()).send().await.expect_status(StatusCode::OK);
+    assert_eq!(response.header("etag"), Some("\"2\""));
+    assert_eq!(response.json()["version"], 2);
+
+    // Saving from a stale copy tells the client which version to re-read
+    let stale = app
+        .patch(&uri)
+        .token(&alice)
+        .if_match(1)
+        .json(json!({ "content": "An edit based on version one" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::PRECONDITION_FAILED, "version_mismatch");
+    assert_eq!(stale.header("etag"), Some("\"2\""));
+    assert_eq!(stale.json()["current_version"], 2);
+
+    // Admins may edit anyone's post; * skips the version check
+    app.patch(&uri)
+        .token(&admin)
+        .header("If-Match", "*")
+        .json(json!({ "tags": ["Rust", "rust", "Web Dev"], "category": "Tutorials" }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+    let post = fetch(&app, &alice, &id).await;
+    assert_eq!(post["content"], "Can't wait to see what's next!");
+    assert_eq!(post["tags"], json!(["rust", "web-dev"]));
+    assert_eq!(post["category"], "tutorials");
+    assert_eq!(post["version"], 3);
+
+    app.patch("/posts/65a000000000000000000000")
+        .token(&alice)
+        .if_match(1)
+        .json(change)
+        .send()
+        .await
+        .expect_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn renaming_a_post_keeps_the_old_slug_as_a_redirect() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let id = app.publish(&alice, "My Second Rust Blog Post").await;
+
+    app.patch(&format!("/posts/{}", id))
+        .token(&alice)
+        .if_match(1)
+        .json(json!({ "title": "Updated Rust Blog Post" }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+
+    let current = app.get("/posts/by-slug/updated-rust-blog-post").send().await.expect_status(StatusCode::OK);
+    assert_eq!(current.json()["title"], "Updated Rust Blog Post");
+
+    let old = app.get("/posts/by-slug/my-second-rust-blog-post").send().await.expect_status(StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(old.header("location"), Some("/posts/by-slug/updated-rust-blog-post"));
+    assert_eq!(old.json()["slug"], "updated-rust-blog-post");
+
+    // The retired slug stays reserved for this post
+    let other = app.publish(&alice, "My Second Rust Blog Post").await;
+    assert_eq!(fetch(&app, &alice, &other).await["slug"], "my-second-rust-blog-post-2");
+
+    app.get("/posts/by-slug/never-existed").send().await.expect_problem(StatusCode::NOT_FOUND, "not_found");
+}
+
+#[tokio::test]
+async fn deleting_a_post_moves_it_to_the_trash() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let id = app.publish(&alice, "Short lived").await;
+    let uri = format!("/posts/{}", id);
+
+    app.delete(&uri).if_match(1).send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.delete(&uri).token(&alice).send().await.expect_status(StatusCode::PRECONDITION_REQUIRED);
+    app.delete(&uri).token(&bob).if_match(1).send().await.expect_problem(StatusCode::FORBIDDEN, "forbidden");
+    app.delete(&uri).token(&alice).if_match(7).send().await.expect_problem(StatusCode::PRECONDITION_FAILED, "version_mismatch");
+
+    app.delete(&uri).token(&alice).if_match(1).send().await.expect_status(StatusCode::OK);
+    app.get(&uri).token(&alice).send().await.expect_status(StatusCode::NOT_FOUND);
+    assert!(titles(&app.get("/posts").send().await.json()).is_empty());
+    app.delete(&uri).token(&alice).if_match(1).send().await.expect_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn trashed_posts_are_never_updated() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let id = app.publish(&alice, "Going away").await;
+    let post_id = ObjectId::parse_str(&id).unwrap();
+    assert!(matches!(app.state.posts.trash(post_id, 1, post_id).await, Ok(true)));
+
+    // Writes that skip the version check must still leave the trash alone
+    let update = PostUpdate { title: Some("Back again".to_string()), ..Default::default() };
+    assert!(matches!(app.state.posts.update(post_id, None, update).await, Ok(None)));
+    app.get(&format!("/posts/{}", id)).token(&alice).send().await.expect_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn publication_states_follow_the_owner_actions() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let id = app.create_post(&alice, json!({ "title": "State machine", "content": "Draft to archive", "status": "draft" })).await;
+
+    for action in ["publish", "unpublish", "archive"] {
+        let uri = format!("/posts/{}/{}", id, action);
+        app.post(&uri).send().await.expect_status(StatusCode::UNAUTHORIZED);
+        app.post(&uri).token(&bob).send().await.expect_problem(StatusCode::FORBIDDEN, "forbidden");
+    }
+
+    // A future date schedules instead of publishing
+    let scheduled = app
+        .post(&format!("/posts/{}/publish", id))
+        .token(&alice)
+        .json(json!({ "publish_at": "2030-01-01T09:00:00Z" }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    assert_eq!(scheduled["status"], "scheduled");
+    assert!(titles(&app.get("/posts").send().await.json()).is_empty());
+
+    let published = app.post(&format!("/posts/{}/publish", id)).token(&alice).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(published["status"], "published");
+    assert_eq!(titles(&app.get("/posts").send().await.json()), ["State machine"]);
+
+    let draft = app.post(&format!("/posts/{}/unpublish", id)).token(&alice).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(draft["status"], "draft");
+    assert!(draft.get("publish_at").is_none_or(Value::is_null));
+    app.get(&format!("/posts/{}", id)).send().await.expect_status(StatusCode::NOT_FOUND);
+
+    let archived = app.post(&format!("/posts/{}/archive", id)).token(&alice).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(archived["status"], "archived");
+    assert_eq!(archived["version"], 5);
+
+    app.post("/posts/not-an-id/publish").token(&alice).send().await.expect_problem(StatusCode::BAD_REQUEST, "invalid_id");
+}
+
+#[tokio::test]
+async fn revisions_record_diff_and_restore_edits() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let id = app.create_post(&alice, json!({ "title": "Versioned post", "content": "line one\nline two" })).await;
+
+    app.patch(&format!("/posts/{}", id))
+        .token(&alice)
+        .if_match(1)
+        .json(json!({ "title": "Versioned post, edited", "content": "line one\nline 2" }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+
+    let uri = format!("/posts/{}/revisions", id);
+    app.get(&uri).send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.get(&uri).token(&bob).send().await.expect_problem(StatusCode::FORBIDDEN, "forbidden");
+
+    let revisions = app.get(&uri).token(&alice).send().await.expect_status(StatusCode::OK).json();
+    let numbers: Vec<_> = revisions.as_array().unwrap().iter().map(|r| r["revision"].clone()).collect();
+    assert_eq!(numbers, [2, 1]);
+    assert_eq!(revisions[1]["snapshot"]["content"], "line one\nline two");
+
+    let diff = app
+        .get(&format!("/posts/{}/revisions/diff?from=1&to=2", id))
+        .token(&alice)
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    assert_eq!(diff["content"], json!([
+        { "op": "equal", "text": "line one" },
+        { "op": "delete", "text": "line two" },
+        { "op": "insert", "text": "line 2" },
+    ]));
+    app.get(&format!("/posts/{}/revisions/diff?from=1&to=9", id))
+        .token(&alice)
+        .send()
+        .await
+        .expect_problem(StatusCode::NOT_FOUND, "not_found");
+    app.get(&format!("/posts/{}/revisions/diff?from=1&to=2", id)).token(&bob).send().await.expect_status(StatusCode::FORBIDDEN);
+
+    // Restoring is itself a new revision, and brings the old title (and slug) back
+    let restore = format!("/posts/{}/revisions/1/restore", id);
+    app.post(&restore).token(&bob).send().await.expect_problem(StatusCode::FORBIDDEN, "forbidden");
+    let restored = app.post(&restore).token(&alice).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(restored["revision"], 3);
+
+    let post = fetch(&app, &alice, &id).await;
+    assert_eq!(post["title"], "Versioned post");
+    assert_eq!(post["slug"], "versioned-post");
+    assert_eq!(post["content"], "line one\nline two");
+    let latest = app.get(&uri).token(&alice).send().await.json();
+    assert_eq!(latest[0]["restored_from"], 1);
+
+    app.post(&format!("/posts/{}/revisions/9/restore", id)).token(&alice).send().await.expect_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn comments_and_search_reject_bad_requests_before_the_database() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let id = app.publish(&alice, "Discussed post").await;
+
+    app.post(&format!("/posts/{}/comments", id))
+        .json(json!({ "content": "Great write-up!" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNAUTHORIZED, "invalid_token");
+    app.patch(&format!("/posts/{}/comments/65a000000000000000000000", id))
+        .json(json!({ "content": "Edited" }))
+        .send()
+        .await
+        .expect_status(StatusCode::UNAUTHORIZED);
+    app.delete(&format!("/posts/{}/comments/65a000000000000000000000", id))
+        .send()
+        .await
+        .expect_status(StatusCode::UNAUTHORIZED);
+    app.get("/posts/not-an-id/comments").send().await.expect_problem(StatusCode::BAD_REQUEST, "invalid_id");
+    app.patch(&format!("/posts/{}/comments/not-an-id", id))
+        .token(&alice)
+        .json(json!({ "content": "Edited" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "invalid_id");
+
+    app.get("/posts/search?q=%20").send().await.expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+
+    // Offset cursors for o:18446744073709551615, o:10001 (past the deepest page) and o:-20
+    for cursor in ["bzoxODQ0Njc0NDA3MzcwOTU1MTYxNQ", "bzoxMDAwMQ", "bzotMjA"] {
+        app.get(&format!("/posts/search?q=rust&cursor={}", cursor))
+            .send()
+            .await
+            .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+    }
+}
+
+#[tokio::test]
+async fn comment_threads_follow_the_ownership_rules() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let admin = app.admin("root").await;
+    let id = app.publish(&alice, "Discussed post").await;
+    let comments = format!("/posts/{}/comments", id);
+
+    let created = app
+        .post(&comments)
+        .token(&bob)
+        .json(json!({ "content": "Great write-up!" }))
+        .send()
+        .await
+        .expect_status(StatusCode::CREATED)
+        .json();
+    let top = oid(&created);
+    let reply = app
+        .post(&comments)
+        .token(&alice)
+        .json(json!({ "content": "Thanks!", "parent_id": top }))
+        .send()
+        .await
+        .expect_status(StatusCode::CREATED)
+        .json();
+    let reply = oid(&reply);
+
+    let thread = app.get(&comments).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(thread[0]["author_name"], "bob");
+    assert_eq!(thread[0]["replies"][0]["content"], "Thanks!");
+    assert_eq!(fetch(&app, &alice, &id).await["comment_count"], 2);
+
+    // Only the comment's author (or an admin) may change it
+    let top_uri = format!("{}/{}", comments, top);
+    app.patch(&top_uri)
+        .token(&alice)
+        .json(json!({ "content": "Hijacked" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::FORBIDDEN, "forbidden");
+    app.patch(&top_uri).token(&bob).json(json!({ "content": "Great write-up, thanks" })).send().await.expect_status(StatusCode::OK);
+    app.delete(&format!("{}/{}", comments, reply)).token(&bob).send().await.expect_status(StatusCode::FORBIDDEN);
+
+    // Deleting a comment takes its replies along
+    let deleted = app.delete(&top_uri).token(&admin).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(deleted["deleted_count"], 2);
+    assert_eq!(app.get(&comments).send().await.json(), json!([]));
+
+    // Drafts can't be discussed
+    let draft = app.create_post(&alice, json!({ "title": "Quiet draft", "content": "No comments here", "status": "draft" })).await;
+    app.post(&format!("/posts/{}/comments", draft))
+        .token(&bob)
+        .json(json!({ "content": "First!" }))
+        .send()
+        .await
+        .expect_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn replies_nest_no_deeper_than_the_limit() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let id = app.publish(&alice, "Long discussion").await;
+    let comments = format!("/posts/{}/comments", id);
+
+    // Each comment answers the previous one, two more times than the limit allows
+    let mut parent: Option<String> = None;
+    let mut chain = Vec::new();
+    for n in 0..=MAX_DEPTH + 2 {
+        let created = app
+            .post(&comments)
+            .token(&alice)
+            .json(json!({ "content": format!("Reply {}", n), "parent_id": parent }))
+            .send()
+            .await
+            .expect_status(StatusCode::CREATED)
+            .json();
+        parent = Some(oid(&created));
+        chain.push(oid(&created));
+    }
+
+    let thread = app.get(&comments).send().await.expect_status(StatusCode::OK).json();
+    let mut node = &thread[0];
+    for id in &chain[..MAX_DEPTH - 1] {
+        assert_eq!(&oid(&node["_id"]), id);
+        assert_eq!(node["replies"].as_array().unwrap().len(), 1);
+        node = &node["replies"][0];
+    }
+
+    // Replies to the deepest comment join it at the same depth instead of nesting further
+    assert_eq!(oid(&node["_id"]), chain[MAX_DEPTH - 1]);
+    let deepest: Vec<String> = node["replies"].as_array().unwrap().iter().map(|c| oid(&c["_id"])).collect();
+    assert_eq!(deepest, chain[MAX_DEPTH..]);
+    assert!(node["replies"].as_array().unwrap().iter().all(|c| c["replies"] == json!([])));
+}
+
+#[tokio::test]
+async fn search_ranks_live_posts_and_highlights_matches() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    app.create_post(&alice, json!({ "title": "My Rust blog", "content": "Rust blog posts about ownership" })).await;
+    app.create_post(&alice, json!({ "title": "Gardening notes", "content": "Nothing about programming" })).await;
+    app.create_post(&alice, json!({ "title": "Rust draft", "content": "Rust blog draft", "status": "draft" })).await;
+
+    let page = app.get("/posts/search?q=%22rust%20blog%22&limit=10").send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(titles(&page), ["My Rust blog"]);
+    assert_eq!(page["items"][0]["title_highlight"], "My <mark>Rust blog</mark>");
+    assert!(page["items"][0]["score"].as_f64().is_some_and(|s| s > 0.0));
+
+    let excluded = app.get("/posts/search?q=rust%20-ownership").send().await.json();
+    assert!(titles(&excluded).is_empty());
+}
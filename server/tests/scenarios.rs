@@ -0,0 +1,487 @@
+/*
+ * The requests in routes/api.http that reach beyond users and posts: tags, the trash,
+ * uploads, feeds, probes, metrics and the rate limits.
+ */
+
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+
+use common::{TestApp, PASSWORD};
+
+/// A small PNG, as a browser would upload it
+fn png() -> Vec<u8> {
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image::RgbImage::from_pixel(4, 3, image::Rgb([200, 80, 20]))
+        .write_to(&mut bytes, image::ImageFormat::Png)
+        .expect("encodable image");
+    bytes.into_inner()
+}
+
+fn multipart(file_name: &str, content_type: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = format!(
+        "--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+        file_name, content_type
+    )
+    .into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(b"\r\n--boundary--\r\n");
+    body
+}
+
+const MULTIPART: &str = "multipart/form-data; boundary=boundary";
+
+#[tokio::test]
+async fn probes_report_liveness_and_database_readiness() {
+    let app = TestApp::new().await;
+
+    let health = app.get("/healthz").send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(health["status"], "ok");
+
+    let ready = app.get("/readyz").send().await;
+    let body = ready.json();
+    if app.has_mongodb() {
+        assert_eq!(ready.status, StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["checks"]["database"]["status"], "up");
+    } else {
+        assert_eq!(ready.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "not_ready");
+        // The driver's error names hosts and topology; it is logged, never served
+        assert_eq!(body["checks"]["database"], json!({ "status": "down" }));
+    }
+}
+
+#[tokio::test]
+async fn metrics_count_requests_per_route() {
+    let app = TestApp::new().await;
+    app.get("/posts").send().await.expect_status(StatusCode::OK);
+
+    let metrics = app.get("/metrics").send().await.expect_status(StatusCode::OK).text();
+    let requests = metrics
+        .lines()
+        .find(|line| line.starts_with("http_requests_total") && line.contains("route=\"/posts\""))
+        .expect("GET /posts is counted");
+    assert!(requests.contains("status=\"200\""));
+
+    let disabled = TestApp::with_config(|config| config.features.metrics = false).await;
+    disabled.get("/metrics").send().await.expect_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn repeated_wrong_passwords_lock_the_account() {
+    let app = TestApp::with_config(|config| {
+        config.rate_limit.enabled = true;
+        config.rate_limit.lockout.threshold = 3;
+    })
+    .await;
+    let alice = app.register("alice").await;
+    let wrong = json!({ "email": alice.email, "password": "wrong-password" });
+
+    for _ in 0..3 {
+        app.post("/users/login").json(wrong.clone()).send().await.expect_problem(StatusCode::UNAUTHORIZED, "wrong_credentials");
+    }
+
+    // Locked: even the right password is refused until the lock expires
+    let locked = app
+        .post("/users/login")
+        .json(json!({ "email": "ALICE@example.com", "password": PASSWORD }))
+        .send()
+        .await
+        .expect_problem(StatusCode::TOO_MANY_REQUESTS, "account_locked");
+    let retry_after: u64 = locked.header("retry-after").unwrap().parse().unwrap();
+    assert!(retry_after > 0);
+
+    // Other accounts are not affected
+    let bob = app.register("bob").await;
+    app.login(&bob.email).await;
+}
+
+#[tokio::test]
+async fn writes_are_limited_per_user() {
+    let app = TestApp::with_config(|config| {
+        config.rate_limit.enabled = true;
+        config.rate_limit.write.burst = 2;
+        config.rate_limit.write.per_minute = 1;
+    })
+    .await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+
+    app.publish(&alice, "First of many").await;
+    app.publish(&alice, "Second of many").await;
+    let limited = app
+        .post("/posts")
+        .token(&alice)
+        .json(json!({ "title": "Third of many", "content": "One post too many" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::TOO_MANY_REQUESTS, "rate_limited");
+    assert!(limited.header("retry-after").is_some());
+
+    // Reads are never limited, and every user has their own budget
+    app.get("/posts").send().await.expect_status(StatusCode::OK);
+    app.publish(&bob, "Bob is fine").await;
+}
+
+#[tokio::test]
+async fn site_and_tag_feeds_list_live_posts() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    app.create_post(&alice, json!({ "title": "Rust & <XML>", "content": "Escaped in every feed", "tags": ["Rust"] })).await;
+    app.create_post(&alice, json!({ "title": "Untagged post", "content": "No tags on this one" })).await;
+    app.create_post(&alice, json!({ "title": "Rust draft", "content": "Not in any feed", "tags": ["rust"], "status": "draft" })).await;
+
+    let rss = app.get("/feed.rss").send().await.expect_status(StatusCode::OK);
+    assert!(rss.text().contains("Rust &amp; &lt;XML&gt;"));
+    assert!(!rss.text().contains("Rust draft"));
+    app.get("/feed.atom").send().await.expect_status(StatusCode::OK);
+
+    let site = app.get("/feed.json").send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(site["items"].as_array().unwrap().len(), 2);
+
+    let tag = app.get("/tags/rust/feed.json").send().await.expect_status(StatusCode::OK).json();
+    let items = tag["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["tags"], json!(["rust"]));
+
+    let posts = app.get("/tags/Rust/posts?limit=10").send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(posts["items"][0]["title"], "Rust & <XML>");
+    assert_eq!(posts["items"].as_array().unwrap().len(), 1);
+
+    // ?author= narrows a tag listing just like GET /posts
+    let bob = app.register("bob").await;
+    app.create_post(&bob, json!({ "title": "Bob on Rust", "content": "Same tag, another author", "tags": ["rust"] })).await;
+    let both = app.get("/tags/rust/posts").send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(both["items"].as_array().unwrap().len(), 2);
+    let alices = app.get("/tags/rust/posts?author=alice").send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(alices["items"].as_array().unwrap().len(), 1);
+    assert_eq!(alices["items"][0]["title"], "Rust & <XML>");
+    let nobody = app.get("/tags/rust/posts?author=nobody").send().await.expect_status(StatusCode::OK).json();
+    assert!(nobody["items"].as_array().unwrap().is_empty());
+
+    app.get("/feed.rss")
+        .header("If-Modified-Since", "Sat, 01 Aug 2099 10:00:00 GMT")
+        .send()
+        .await
+        .expect_status(StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn tag_administration_is_admin_only() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+
+    app.patch("/tags/rustlang").json(json!({ "name": "rust" })).send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.patch("/tags/rustlang")
+        .token(&alice)
+        .json(json!({ "name": "rust" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::FORBIDDEN, "forbidden");
+    app.post("/tags/merge")
+        .token(&alice)
+        .json(json!({ "sources": ["mongo"], "target": "mongodb" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::FORBIDDEN, "forbidden");
+
+    let admin = app.admin("root").await;
+    app.post("/tags/merge")
+        .token(&admin)
+        .json(json!({ "sources": [], "target": "mongodb" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed");
+    app.post("/tags/merge")
+        .token(&admin)
+        .json(json!({ "sources": ["mongo"], "target": "!!!" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+    app.patch("/tags/rustlang")
+        .token(&admin)
+        .body("application/json", b"{ not json".to_vec())
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "invalid_body");
+}
+
+#[tokio::test]
+async fn tags_can_be_counted_renamed_and_merged() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let admin = app.admin("root").await;
+    app.create_post(&alice, json!({ "title": "Post one", "content": "Tagged twice over", "tags": ["rustlang", "mongo"] })).await;
+    app.create_post(&alice, json!({ "title": "Post two", "content": "Tagged once only", "tags": ["rust", "mongo-db"] })).await;
+
+    let renamed = app
+        .patch("/tags/rustlang")
+        .token(&admin)
+        .json(json!({ "name": "Rust" }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    assert_eq!(renamed["posts_updated"], 1);
+
+    let merged = app
+        .post("/tags/merge")
+        .token(&admin)
+        .json(json!({ "sources": ["mongo", "mongo-db"], "target": "mongodb" }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    assert_eq!(merged["posts_updated"], 2);
+
+    let tags = app.get("/tags").send().await.expect_status(StatusCode::OK).json();
+    let counts: Vec<_> = tags
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| (t["_id"].as_str().unwrap_or_default().to_string(), t["post_count"].clone()))
+        .collect();
+    assert_eq!(counts, [("mongodb".to_string(), json!(2)), ("rust".to_string(), json!(2))]);
+}
+
+#[tokio::test]
+async fn trash_requires_an_account() {
+    let app = TestApp::new().await;
+
+    app.get("/trash").send().await.expect_problem(StatusCode::UNAUTHORIZED, "invalid_token");
+    app.post("/trash/posts/65a000000000000000000000/restore").send().await.expect_status(StatusCode::UNAUTHORIZED);
+
+    let alice = app.register("alice").await;
+    app.post("/trash/users/65a000000000000000000000/restore")
+        .token(&alice)
+        .send()
+        .await
+        .expect_problem(StatusCode::FORBIDDEN, "forbidden");
+}
+
+#[tokio::test]
+async fn trashed_posts_and_users_can_be_restored() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let admin = app.admin("root").await;
+    let id = app.publish(&alice, "Deleted by mistake").await;
+
+    app.delete(&format!("/posts/{}", id)).token(&alice).if_match(1).send().await.expect_status(StatusCode::OK);
+
+    let trash = app.get("/trash").token(&alice).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(trash["items"][0]["kind"], "post");
+    assert_eq!(trash["items"][0]["title"], "Deleted by mistake");
+    assert!(trash["items"][0]["purge_after"].is_string());
+    assert_eq!(trash["has_more"], false);
+    let others = app.get("/trash").token(&bob).send().await.json();
+    assert_eq!(others["items"], json!([]));
+
+    let restore = format!("/trash/posts/{}/restore", id);
+    app.post(&restore).token(&bob).send().await.expect_problem(StatusCode::FORBIDDEN, "forbidden");
+    app.post(&restore).token(&alice).send().await.expect_status(StatusCode::OK);
+    app.get(&format!("/posts/{}", id)).send().await.expect_status(StatusCode::OK);
+    app.post(&restore).token(&alice).send().await.expect_status(StatusCode::NOT_FOUND);
+
+    // Deleted accounts show up for admins only, and come back without their sessions
+    app.delete(&format!("/users/admin/users/{}", bob.id)).token(&admin).send().await.expect_status(StatusCode::OK);
+    let trash = app.get("/trash").token(&admin).send().await.json();
+    assert_eq!(trash["items"][0]["kind"], "user");
+    assert_eq!(trash["items"][0]["username"], "bob");
+    assert_eq!(app.get("/trash").token(&alice).send().await.json()["items"], json!([]));
+
+    app.post(&format!("/trash/users/{}/restore", bob.id)).token(&admin).send().await.expect_status(StatusCode::OK);
+    app.get("/users/me").token(&bob).send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.login(&bob.email).await;
+}
+
+#[tokio::test]
+async fn restoring_a_user_brings_back_the_posts_deleted_with_them() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let admin = app.admin("root").await;
+    let kept = app.publish(&alice, "Deleted with the account").await;
+    let own = app.publish(&alice, "Deleted by its author").await;
+    app.delete(&format!("/posts/{}", own)).token(&alice).if_match(1).send().await.expect_status(StatusCode::OK);
+
+    app.delete(&format!("/users/admin/users/{}?posts=delete", alice.id)).token(&admin).send().await.expect_status(StatusCode::OK);
+    app.get(&format!("/posts/{}", kept)).send().await.expect_status(StatusCode::NOT_FOUND);
+
+    let restored = app
+        .post(&format!("/trash/users/{}/restore", alice.id))
+        .token(&admin)
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    assert_eq!(restored["username"], "alice");
+    assert_eq!(restored["posts_restored"], 1);
+    app.get(&format!("/posts/{}", kept)).send().await.expect_status(StatusCode::OK);
+
+    // What the author had thrown away before stays in the trash
+    app.get(&format!("/posts/{}", own)).send().await.expect_status(StatusCode::NOT_FOUND);
+    let trash = app.get("/trash").token(&admin).send().await.json();
+    assert_eq!(trash["items"].as_array().unwrap().len(), 1);
+    assert_eq!(trash["items"][0]["title"], "Deleted by its author");
+}
+
+#[tokio::test]
+async fn the_trash_is_paged_by_deletion_time() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let admin = app.admin("root").await;
+    for title in ["First to go", "Second to go", "Third to go"] {
+        let id = app.publish(&alice, title).await;
+        app.delete(&format!("/posts/{}", id)).token(&alice).if_match(1).send().await.expect_status(StatusCode::OK);
+    }
+    let bob = app.register("bob").await;
+    app.delete(&format!("/users/admin/users/{}", bob.id)).token(&admin).send().await.expect_status(StatusCode::OK);
+
+    let mut seen = Vec::new();
+    let mut uri = "/trash?limit=2".to_string();
+    loop {
+        let page = app.get(&uri).token(&admin).send().await.expect_status(StatusCode::OK).json();
+        let items = page["items"].as_array().unwrap();
+        assert!(items.len() <= 2);
+        seen.extend(items.iter().map(|i| i["title"].as_str().or(i["username"].as_str()).unwrap().to_string()));
+        match page["next_cursor"].as_str() {
+            Some(cursor) => uri = format!("/trash?limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(seen, ["bob", "Third to go", "Second to go", "First to go"]);
+
+    app.get("/trash?cursor=garbage").token(&admin).send().await.expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+}
+
+#[tokio::test]
+async fn uploads_require_an_account_and_an_image() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+
+    app.post("/media")
+        .body(MULTIPART, multipart("cover.png", "image/png", &png()))
+        .send()
+        .await
+        .expect_status(StatusCode::UNAUTHORIZED);
+
+    // The bytes decide, not the declared type
+    app.post("/media")
+        .token(&alice)
+        .body(MULTIPART, multipart("cover.png", "image/png", b"just some text, not a picture"))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type");
+
+    let too_large = TestApp::with_config(|config| config.media.max_upload_bytes = 16).await;
+    let bob = too_large.register("bob").await;
+    too_large
+        .post("/media")
+        .token(&bob)
+        .body(MULTIPART, multipart("cover.png", "image/png", &png()))
+        .send()
+        .await
+        .expect_status(StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[test]
+fn stripped_jpegs_keep_only_their_orientation() {
+    let mut plain = std::io::Cursor::new(Vec::new());
+    image::RgbImage::from_pixel(4, 3, image::Rgb([200, 80, 20]))
+        .write_to(&mut plain, image::ImageFormat::Jpeg)
+        .expect("encodable image");
+    let plain = plain.into_inner();
+
+    // A phone photo: rotated a quarter turn, and telling who made the camera
+    let tiff: &[u8] = &[
+        b'M', b'M', 0x00, 0x2A, 0x00, 0x00, 0x00, 0x08,
+        0x00, 0x02,
+        0x01, 0x0F, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x26, // Make, at offset 38
+        0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x06, 0x00, 0x00, // Orientation 6
+        0x00, 0x00, 0x00, 0x00,
+        b'P', b'h', b'o', b'n', b'e', b'C', b'o', 0x00,
+    ];
+    let mut photo = plain[..2].to_vec();
+    photo.extend_from_slice(&[0xFF, 0xE1]);
+    photo.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+    photo.extend_from_slice(b"Exif\0\0");
+    photo.extend_from_slice(tiff);
+    photo.extend_from_slice(&plain[2..]);
+    assert_eq!(server::imaging::exif_orientation(&photo), 6);
+
+    let stripped = server::imaging::strip_metadata(&photo, "image/jpeg").unwrap();
+    assert_eq!(server::imaging::exif_orientation(&stripped), 6);
+    assert!(!stripped.windows(7).any(|w| w == b"PhoneCo"));
+    image::load_from_memory(&stripped).expect("still a valid JPEG");
+
+    // Upright images get no EXIF at all
+    let stripped = server::imaging::strip_metadata(&plain, "image/jpeg").unwrap();
+    assert!(!stripped.windows(4).any(|w| w == b"Exif"));
+}
+
+#[tokio::test]
+async fn uploaded_images_are_served_and_linked_from_posts() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let image = png();
+
+    let uploaded = app
+        .post("/media")
+        .token(&alice)
+        .body(MULTIPART, multipart("cover.png", "image/png", &image))
+        .send()
+        .await
+        .expect_status(StatusCode::CREATED)
+        .json();
+    let media_id = uploaded["id"].as_str().unwrap().to_string();
+    assert_eq!(uploaded["content_type"], "image/png");
+    assert_eq!(uploaded["size"], image.len());
+
+    let mine = app.get("/media").token(&alice).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(mine["items"][0]["id"], media_id.as_str());
+
+    let file = app.get(&format!("/media/{}", media_id)).send().await.expect_status(StatusCode::OK);
+    assert_eq!(file.body, image);
+    let partial = app
+        .get(&format!("/media/{}", media_id))
+        .header("Range", "bytes=0-7")
+        .send()
+        .await
+        .expect_status(StatusCode::PARTIAL_CONTENT);
+    assert_eq!(partial.body, image[..8]);
+
+    // Only your own uploads can become a cover
+    let post = app.publish(&alice, "Post with a cover").await;
+    app.patch(&format!("/posts/{}", post))
+        .token(&alice)
+        .if_match(1)
+        .json(json!({ "cover_image_id": media_id }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+    let bobs = app.publish(&bob, "Borrowed cover").await;
+    app.patch(&format!("/posts/{}", bobs))
+        .token(&bob)
+        .if_match(1)
+        .json(json!({ "cover_image_id": media_id }))
+        .send()
+        .await
+        .expect_status(StatusCode::FORBIDDEN);
+
+    // A file still in use can't be deleted
+    let uri = format!("/media/{}", media_id);
+    app.delete(&uri).token(&alice).send().await.expect_problem(StatusCode::CONFLICT, "conflict");
+    app.patch(&format!("/posts/{}", post))
+        .token(&alice)
+        .if_match(2)
+        .json(json!({ "cover_image_id": "" }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+    app.delete(&uri).token(&bob).send().await.expect_status(StatusCode::FORBIDDEN);
+    app.delete(&uri).token(&alice).send().await.expect_status(StatusCode::NO_CONTENT);
+    app.get(&uri).send().await.expect_status(StatusCode::NOT_FOUND);
+}
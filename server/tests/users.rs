@@ -0,0 +1,440 @@
+/*
+ * Every route in user_routes: registration, login and the token lifecycle, the
+ * profile, and the admin-only listing and deletion of accounts.
+ */
+
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+
+use common::{email_of, oid, TestApp, PASSWORD};
+
+#[tokio::test]
+async fn register_returns_tokens_and_a_plain_user() {
+    let app = TestApp::new().await;
+
+    // Asking for the admin role at registration is ignored
+    let body = app
+        .post("/users/register")
+        .json(json!({ "username": "alice", "email": "alice@example.com", "password": PASSWORD, "role": "admin" }))
+        .send()
+        .await
+        .expect_status(StatusCode::CREATED)
+        .json();
+
+    assert_eq!(body["token_type"], "Bearer");
+    assert!(body["access_token"].as_str().is_some_and(|t| !t.is_empty()));
+    assert!(body["refresh_token"].as_str().is_some_and(|t| !t.is_empty()));
+    assert_eq!(body["user"]["username"], "alice");
+    assert_eq!(body["user"]["role"], "user");
+    assert!(body["user"].get("password").is_none());
+}
+
+#[tokio::test]
+async fn register_rejects_taken_reserved_and_invalid_identities() {
+    let app = TestApp::new().await;
+    app.register("alice").await;
+
+    app.post("/users/register")
+        .json(json!({ "username": "alice2", "email": "alice@example.com", "password": PASSWORD }))
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+
+    let body = app
+        .post("/users/register")
+        .json(json!({ "username": "alice", "email": "other@example.com", "password": PASSWORD }))
+        .send()
+        .await
+        .expect_problem(StatusCode::CONFLICT, "duplicate_key")
+        .json();
+    assert_eq!(body["index"], "username_1");
+
+    app.post("/users/register")
+        .json(json!({ "username": "Deleted-User", "email": "ghost@example.com", "password": PASSWORD }))
+        .send()
+        .await
+        .expect_problem(StatusCode::CONFLICT, "conflict");
+
+    // One entry per failed rule
+    let body = app
+        .post("/users/register")
+        .json(json!({ "username": "jo", "email": "not-an-email", "password": "123" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed")
+        .json();
+    let fields: Vec<_> = body["errors"].as_array().unwrap().iter().map(|e| e["field"].clone()).collect();
+    assert_eq!(fields, ["email", "password", "username"]);
+}
+
+#[tokio::test]
+async fn login_checks_the_password() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+
+    let logged_in = app.login(&alice.email).await;
+    assert_eq!(logged_in.id, alice.id);
+
+    app.post("/users/login")
+        .json(json!({ "email": alice.email, "password": "wrong-password" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNAUTHORIZED, "wrong_credentials");
+
+    // Unknown accounts look exactly like wrong passwords
+    app.post("/users/login")
+        .json(json!({ "email": "nobody@example.com", "password": PASSWORD }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNAUTHORIZED, "wrong_credentials");
+
+    // Bodies that are not a login are rejected as problem+json before any lookup
+    app.post("/users/login")
+        .body("application/json", b"{ \"email\": ".to_vec())
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "invalid_body");
+    let body = app
+        .post("/users/login")
+        .json(json!({ "email": "alice", "password": "" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed")
+        .json();
+    let fields: Vec<_> = body["errors"].as_array().unwrap().iter().map(|e| e["field"].clone()).collect();
+    assert_eq!(fields, ["email", "password"]);
+    app.post("/users/token/refresh")
+        .json(json!({ "token": alice.refresh_token }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNPROCESSABLE_ENTITY, "invalid_body");
+}
+
+#[tokio::test]
+async fn refresh_rotates_the_token_and_reuse_revokes_the_session() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+
+    let pair = app
+        .post("/users/token/refresh")
+        .json(json!({ "refresh_token": alice.refresh_token }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    let new_refresh = pair["refresh_token"].as_str().unwrap();
+    assert_ne!(new_refresh, alice.refresh_token);
+
+    let mut rotated = alice.clone();
+    rotated.access_token = pair["access_token"].as_str().unwrap().to_string();
+    app.get("/users/me").token(&rotated).send().await.expect_status(StatusCode::OK);
+
+    // Presenting the old token again means it leaked: the whole session goes
+    app.post("/users/token/refresh")
+        .json(json!({ "refresh_token": alice.refresh_token }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNAUTHORIZED, "invalid_token");
+    app.get("/users/me").token(&rotated).send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.post("/users/token/refresh")
+        .json(json!({ "refresh_token": new_refresh }))
+        .send()
+        .await
+        .expect_status(StatusCode::UNAUTHORIZED);
+
+    app.post("/users/token/refresh")
+        .json(json!({ "refresh_token": "made-up" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNAUTHORIZED, "invalid_token");
+}
+
+#[tokio::test]
+async fn logout_revokes_only_the_current_session() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let other_device = app.login(&alice.email).await;
+
+    app.post("/users/logout").send().await.expect_problem(StatusCode::UNAUTHORIZED, "invalid_token");
+    app.post("/users/logout").token(&alice).send().await.expect_status(StatusCode::OK);
+
+    app.get("/users/me").token(&alice).send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.post("/users/token/refresh")
+        .json(json!({ "refresh_token": alice.refresh_token }))
+        .send()
+        .await
+        .expect_status(StatusCode::UNAUTHORIZED);
+    app.get("/users/me").token(&other_device).send().await.expect_status(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn me_requires_a_valid_token() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+
+    let body = app.get("/users/me").token(&alice).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(body["id"], alice.id.as_str());
+    assert_eq!(body["email"], "alice@example.com");
+    assert_eq!(body["role"], "user");
+
+    app.get("/users/me").send().await.expect_problem(StatusCode::UNAUTHORIZED, "invalid_token");
+    app.get("/users/me")
+        .header("Authorization", "Bearer not-a-jwt")
+        .send()
+        .await
+        .expect_problem(StatusCode::UNAUTHORIZED, "invalid_token");
+}
+
+#[tokio::test]
+async fn edit_profile_changes_only_the_callers_account() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    app.register("bob").await;
+
+    app.patch("/users/edit_profile")
+        .json(json!({ "username": "alice_001" }))
+        .send()
+        .await
+        .expect_status(StatusCode::UNAUTHORIZED);
+
+    app.patch("/users/edit_profile")
+        .token(&alice)
+        .json(json!({ "username": "alice_001" }))
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+    let me = app.get("/users/me").token(&alice).send().await.json();
+    assert_eq!(me["username"], "alice_001");
+
+    app.patch("/users/edit_profile")
+        .token(&alice)
+        .json(json!({}))
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+    app.patch("/users/edit_profile")
+        .token(&alice)
+        .json(json!({ "username": "bob" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::CONFLICT, "duplicate_key");
+    app.patch("/users/edit_profile")
+        .token(&alice)
+        .json(json!({ "username": "deleted-user" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::CONFLICT, "conflict");
+    app.patch("/users/edit_profile")
+        .token(&alice)
+        .json(json!({ "email": "nope" }))
+        .send()
+        .await
+        .expect_problem(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed");
+}
+
+#[tokio::test]
+async fn listing_users_is_admin_only_and_paginated() {
+    let app = TestApp::new().await;
+    let admin = app.admin("root").await;
+    let alice = app.register("alice").await;
+    app.register("bob").await;
+
+    app.get("/users/admin/users").send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.get("/users/admin/users").token(&alice).send().await.expect_problem(StatusCode::FORBIDDEN, "forbidden");
+
+    let first = app
+        .get("/users/admin/users?limit=2&sort=oldest")
+        .token(&admin)
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    let names: Vec<_> = first["items"].as_array().unwrap().iter().map(|u| u["username"].clone()).collect();
+    assert_eq!(names, ["root", "alice"]);
+    assert_eq!(first["has_more"], true);
+    assert!(first["items"][0].get("password").is_none());
+
+    let cursor = first["next_cursor"].as_str().unwrap();
+    let second = app
+        .get(&format!("/users/admin/users?limit=2&sort=oldest&cursor={}", cursor))
+        .token(&admin)
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    assert_eq!(second["items"][0]["username"], "bob");
+    assert_eq!(second["has_more"], false);
+
+    app.get("/users/admin/users?cursor=garbage")
+        .token(&admin)
+        .send()
+        .await
+        .expect_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn deleting_users_is_admin_only_and_guarded() {
+    let app = TestApp::new().await;
+    let admin = app.admin("root").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+
+    let uri = format!("/users/admin/users/{}", bob.id);
+    app.delete(&uri).send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.delete(&uri).token(&alice).send().await.expect_problem(StatusCode::FORBIDDEN, "forbidden");
+
+    app.delete(&format!("/users/admin/users/{}", admin.id))
+        .token(&admin)
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+    app.delete("/users/admin/users/not-an-id")
+        .token(&admin)
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "invalid_id");
+    app.delete("/users/admin/users/65a000000000000000000000")
+        .token(&admin)
+        .send()
+        .await
+        .expect_problem(StatusCode::NOT_FOUND, "not_found");
+    app.delete(&format!("{}?posts=shred", uri))
+        .token(&admin)
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+    app.delete(&format!("{}?posts=reassign:{}", uri, bob.id))
+        .token(&admin)
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+}
+
+#[tokio::test]
+async fn deleting_a_user_anonymizes_their_posts_and_ends_their_sessions() {
+    let app = TestApp::new().await;
+    let admin = app.admin("root").await;
+    let bob = app.register("bob").await;
+    let post_id = app.publish(&bob, "Bob's post").await;
+
+    let body = app
+        .delete(&format!("/users/admin/users/{}", bob.id))
+        .token(&admin)
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    assert_eq!(body["posts_policy"], "anonymize");
+    assert_eq!(body["posts_affected"], 1);
+
+    let post = app.get(&format!("/posts/{}", post_id)).send().await.expect_status(StatusCode::OK).json();
+    assert_eq!(post["author_name"], "deleted-user");
+
+    app.get("/users/me").token(&bob).send().await.expect_status(StatusCode::UNAUTHORIZED);
+    app.post("/users/login")
+        .json(json!({ "email": bob.email, "password": PASSWORD }))
+        .send()
+        .await
+        .expect_status(StatusCode::UNAUTHORIZED);
+    app.delete(&format!("/users/admin/users/{}", bob.id))
+        .token(&admin)
+        .send()
+        .await
+        .expect_status(StatusCode::NOT_FOUND);
+
+    // The ghost account keeps the posts and can't be deleted itself
+    let ghost_id = oid(&post["author_id"]);
+    app.delete(&format!("/users/admin/users/{}", ghost_id))
+        .token(&admin)
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+
+    // A deleted email stays taken
+    app.post("/users/register")
+        .json(json!({ "username": "bob2", "email": email_of("bob"), "password": PASSWORD }))
+        .send()
+        .await
+        .expect_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn deleting_a_user_can_trash_or_reassign_their_posts() {
+    let app = TestApp::new().await;
+    let admin = app.admin("root").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    let bobs = app.publish(&bob, "Bob's post").await;
+    let carols = app.publish(&carol, "Carol's post").await;
+
+    let body = app
+        .delete(&format!("/users/admin/users/{}?posts=delete", bob.id))
+        .token(&admin)
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    assert_eq!(body["posts_policy"], "delete");
+    app.get(&format!("/posts/{}", bobs)).send().await.expect_status(StatusCode::NOT_FOUND);
+
+    // Posts can only go to an active account
+    app.delete(&format!("/users/admin/users/{}?posts=reassign:{}", carol.id, bob.id))
+        .token(&admin)
+        .send()
+        .await
+        .expect_problem(StatusCode::BAD_REQUEST, "bad_request");
+
+    let body = app
+        .delete(&format!("/users/admin/users/{}?posts=reassign:{}", carol.id, alice.id))
+        .token(&admin)
+        .send()
+        .await
+        .expect_status(StatusCode::OK)
+        .json();
+    assert_eq!(body["posts_reassigned_to"], alice.id.as_str());
+    let post = app.get(&format!("/posts/{}", carols)).send().await.json();
+    assert_eq!(post["author_name"], "alice");
+}
+
+#[tokio::test]
+async fn author_feeds_resolve_usernames_and_ids() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let hello = app.publish(&alice, "Hello feeds").await;
+    app.create_post(&alice, json!({ "title": "Draft", "content": "Not ready yet", "status": "draft" })).await;
+
+    let rss = app.get("/users/alice/feed.rss").send().await.expect_status(StatusCode::OK);
+    assert!(rss.header("content-type").unwrap().starts_with("application/rss+xml"));
+    assert!(rss.text().contains("Hello feeds"));
+
+    let atom = app.get(&format!("/users/{}/feed.atom", alice.id)).send().await.expect_status(StatusCode::OK);
+    assert!(atom.header("content-type").unwrap().starts_with("application/atom+xml"));
+
+    let feed = app.get("/users/alice/feed.json").send().await.expect_status(StatusCode::OK);
+    let items = feed.json()["items"].as_array().unwrap().clone();
+    assert_eq!(items.len(), 1, "drafts stay out of feeds");
+    assert_eq!(items[0]["authors"][0]["name"], "alice");
+
+    // Unchanged since the last fetch
+    let last_modified = feed.header("last-modified").unwrap().to_string();
+    app.get("/users/alice/feed.json")
+        .header("If-Modified-Since", &last_modified)
+        .send()
+        .await
+        .expect_status(StatusCode::NOT_MODIFIED);
+
+    // Trashing the only post empties the feed, which is a change (HTTP dates count whole seconds)
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    app.delete(&format!("/posts/{}", hello)).token(&alice).if_match(1).send().await.expect_status(StatusCode::OK);
+    let feed = app.get("/users/alice/feed.json")
+        .header("If-Modified-Since", &last_modified)
+        .send()
+        .await
+        .expect_status(StatusCode::OK);
+    assert!(feed.json()["items"].as_array().unwrap().is_empty());
+
+    app.get("/users/nobody/feed.rss").send().await.expect_problem(StatusCode::NOT_FOUND, "not_found");
+}